@@ -0,0 +1,143 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::os::unix::io::RawFd;
+use std::time::Instant;
+
+use crate::resp::RedisCommand;
+use crate::storage::Storage;
+
+/// A client parked on a blocking command until a write to one of its keys
+/// lets the command be served, or its deadline passes.
+#[derive(Debug)]
+pub struct BlockedClient {
+    pub command: RedisCommand,
+    /// The database the command was issued on.
+    pub db: usize,
+    pub keys: Vec<Vec<u8>>,
+    /// `None` when the client waits for as long as it takes.
+    pub deadline: Option<Instant>,
+}
+
+/// The clients parked on blocking commands, found by connection, by the keys
+/// they wait on and by deadline. The clients waiting on a key are kept in the
+/// order they blocked in, so that the one waiting longest is served first, as
+/// in redis.
+#[derive(Default)]
+pub struct BlockingRegistry {
+    clients: HashMap<RawFd, BlockedClient>,
+    waiting: HashMap<(usize, Vec<u8>), VecDeque<RawFd>>,
+    deadlines: BTreeSet<(Instant, RawFd)>,
+}
+
+impl BlockingRegistry {
+    pub fn is_blocked(&self, fd: RawFd) -> bool {
+        self.clients.contains_key(&fd)
+    }
+
+    pub fn client(&self, fd: RawFd) -> Option<&BlockedClient> {
+        self.clients.get(&fd)
+    }
+
+    /// Parks the client on `fd`, telling the databases which keys it waits
+    /// on so that writes to them get reported.
+    pub fn block(&mut self, fd: RawFd, client: BlockedClient, databases: &mut [Storage]) {
+        for key in &client.keys {
+            let queue = self.waiting.entry((client.db, key.clone())).or_default();
+            // A command may name the same key more than once.
+            if !queue.contains(&fd) {
+                queue.push_back(fd);
+                databases[client.db].block_on(key);
+            }
+        }
+        if let Some(deadline) = client.deadline {
+            self.deadlines.insert((deadline, fd));
+        }
+        self.clients.insert(fd, client);
+    }
+
+    /// Takes the client on `fd` off every key it waits on, returning what it
+    /// was blocked on, or `None` if it was not blocked.
+    pub fn unblock(&mut self, fd: RawFd, databases: &mut [Storage]) -> Option<BlockedClient> {
+        let client = self.clients.remove(&fd)?;
+        for key in &client.keys {
+            let waiting_key = (client.db, key.clone());
+            let Some(queue) = self.waiting.get_mut(&waiting_key) else {
+                continue;
+            };
+            if let Some(position) = queue.iter().position(|&waiting| waiting == fd) {
+                queue.remove(position);
+                databases[client.db].unblock_on(key);
+            }
+            if queue.is_empty() {
+                self.waiting.remove(&waiting_key);
+            }
+        }
+        if let Some(deadline) = client.deadline {
+            self.deadlines.remove(&(deadline, fd));
+        }
+        Some(client)
+    }
+
+    /// The clients waiting on `key` in database `db`, the longest waiting
+    /// first.
+    pub fn waiting_on(&self, db: usize, key: &[u8]) -> Vec<RawFd> {
+        self.waiting
+            .get(&(db, key.to_vec()))
+            .map(|queue| queue.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// The earliest deadline of any blocked client, which the event loop
+    /// must wake up for.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.deadlines.first().map(|(deadline, _)| *deadline)
+    }
+
+    /// The clients whose deadline is at or before `now`.
+    pub fn timed_out(&self, now: Instant) -> Vec<RawFd> {
+        self.deadlines
+            .iter()
+            .take_while(|(deadline, _)| *deadline <= now)
+            .map(|(_, fd)| *fd)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn blpop(keys: &[&str], deadline: Option<Instant>) -> BlockedClient {
+        let keys: Vec<Vec<u8>> = keys.iter().map(|key| key.as_bytes().to_vec()).collect();
+        BlockedClient {
+            command: RedisCommand::BLPOP(keys.clone(), Duration::ZERO),
+            db: 0,
+            keys,
+            deadline,
+        }
+    }
+
+    #[test]
+    fn test_block_and_unblock() {
+        let mut databases = vec![Storage::new()];
+        let mut registry = BlockingRegistry::default();
+        let now = Instant::now();
+        let later = now + Duration::from_secs(1);
+        registry.block(7, blpop(&["a", "b", "a"], Some(later)), &mut databases);
+        registry.block(8, blpop(&["a"], Some(now)), &mut databases);
+        registry.block(9, blpop(&["b"], None), &mut databases);
+        assert_eq!(registry.waiting_on(0, b"a"), vec![7, 8]);
+        assert_eq!(registry.waiting_on(0, b"b"), vec![7, 9]);
+        assert_eq!(registry.next_deadline(), Some(now));
+        assert_eq!(registry.timed_out(now), vec![8]);
+        assert!(registry.unblock(7, &mut databases).is_some());
+        assert!(!registry.is_blocked(7));
+        assert!(registry.unblock(7, &mut databases).is_none());
+        assert_eq!(registry.waiting_on(0, b"a"), vec![8]);
+        assert_eq!(registry.waiting_on(0, b"b"), vec![9]);
+        assert_eq!(registry.next_deadline(), Some(now));
+        registry.unblock(8, &mut databases);
+        assert!(registry.waiting_on(0, b"a").is_empty());
+        assert_eq!(registry.next_deadline(), None);
+    }
+}
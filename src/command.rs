@@ -0,0 +1,617 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::error::RedisError;
+use crate::execute::{self, Session};
+use crate::resp::{self, parse_integer, RedisCommand, RedisValue};
+use crate::storage::{glob, Storage};
+
+/// Turns the arguments of a command, without its name, into the command to
+/// run. Related commands share a parser, so it gets the upper case name too.
+pub type CommandParser = fn(&str, &[&[u8]]) -> Result<RedisCommand, RedisError>;
+
+/// Runs a command its parser returned against the databases, on behalf of the
+/// connection whose session is given, and returns the reply.
+pub type CommandHandler = fn(RedisCommand, &mut [Storage], &mut Session) -> RedisValue;
+
+/// How a command behaves, as COMMAND INFO reports it to clients deciding
+/// where and how to send it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandFlag {
+    Write,
+    ReadOnly,
+    /// May use more memory, so is refused once the server is out of it.
+    DenyOom,
+    Admin,
+    NoScript,
+    Blocking,
+    /// Allowed while the server is still loading its data.
+    Loading,
+    /// Allowed on a replica with stale data.
+    Stale,
+    Fast,
+    /// Allowed before the client has authenticated.
+    NoAuth,
+    /// Its keys cannot be found from the first key, last key and step alone.
+    MovableKeys,
+}
+
+impl CommandFlag {
+    fn name(self) -> &'static str {
+        match self {
+            CommandFlag::Write => "write",
+            CommandFlag::ReadOnly => "readonly",
+            CommandFlag::DenyOom => "denyoom",
+            CommandFlag::Admin => "admin",
+            CommandFlag::NoScript => "noscript",
+            CommandFlag::Blocking => "blocking",
+            CommandFlag::Loading => "loading",
+            CommandFlag::Stale => "stale",
+            CommandFlag::Fast => "fast",
+            CommandFlag::NoAuth => "no_auth",
+            CommandFlag::MovableKeys => "movablekeys",
+        }
+    }
+}
+
+/// Everything the server knows about a command, and how to run it.
+#[derive(Debug)]
+pub struct CommandSpec {
+    /// In lower case, the way COMMAND reports it.
+    pub name: &'static str,
+    /// The number of arguments including the name, or minus the least number
+    /// of them for commands taking a variable number.
+    pub arity: i64,
+    pub flags: &'static [CommandFlag],
+    /// The positions of the first and the last key, the last one counting
+    /// from the end when negative, and the step between keys. All are zero for
+    /// commands without keys.
+    pub first_key: i64,
+    pub last_key: i64,
+    pub key_step: i64,
+    /// The group COMMAND DOCS files the command under.
+    pub group: &'static str,
+    pub summary: &'static str,
+    pub parse: CommandParser,
+    pub execute: CommandHandler,
+}
+
+/// Declares the command table, each command given as its name, arity, flags,
+/// first key, last key and key step, group, parser, handler and summary.
+macro_rules! commands {
+    ($($name:ident $arity:literal [$($flag:ident)*] ($first:literal, $last:literal, $step:literal)
+        $group:literal $parse:ident $execute:ident $summary:literal;)*) => {
+        pub static COMMANDS: &[CommandSpec] = &[$(CommandSpec {
+            name: stringify!($name),
+            arity: $arity,
+            flags: &[$(CommandFlag::$flag),*],
+            first_key: $first,
+            last_key: $last,
+            key_step: $step,
+            group: $group,
+            summary: $summary,
+            parse: resp::$parse,
+            execute: execute::$execute,
+        }),*];
+    };
+}
+
+commands! {
+    ping -1 [Fast] (0, 0, 0) "connection" parse_ping execute_ping
+        "Returns the server's liveliness response.";
+    echo 2 [Fast] (0, 0, 0) "connection" parse_echo execute_ping
+        "Returns the given string.";
+    get 2 [ReadOnly Fast] (1, 1, 1) "string" parse_get execute_get
+        "Returns the string value of a key.";
+    set -3 [Write DenyOom] (1, 1, 1) "string" parse_set execute_set
+        "Sets the string value of a key, ignoring its type. The key is created if it doesn't exist.";
+    expire -3 [Write Fast] (1, 1, 1) "generic" parse_expire execute_expire
+        "Sets the expiration time of a key in seconds.";
+    pexpire -3 [Write Fast] (1, 1, 1) "generic" parse_expire execute_expire
+        "Sets the expiration time of a key in milliseconds.";
+    expireat -3 [Write Fast] (1, 1, 1) "generic" parse_expire execute_expire
+        "Sets the expiration time of a key to a Unix timestamp.";
+    pexpireat -3 [Write Fast] (1, 1, 1) "generic" parse_expire execute_expire
+        "Sets the expiration time of a key to a Unix milliseconds timestamp.";
+    ttl 2 [ReadOnly Fast] (1, 1, 1) "generic" parse_ttl execute_ttl
+        "Returns the expiration time in seconds of a key.";
+    pttl 2 [ReadOnly Fast] (1, 1, 1) "generic" parse_ttl execute_ttl
+        "Returns the expiration time in milliseconds of a key.";
+    expiretime 2 [ReadOnly Fast] (1, 1, 1) "generic" parse_ttl execute_ttl
+        "Returns the expiration time of a key as a Unix timestamp.";
+    pexpiretime 2 [ReadOnly Fast] (1, 1, 1) "generic" parse_ttl execute_ttl
+        "Returns the expiration time of a key as a Unix milliseconds timestamp.";
+    persist 2 [Write Fast] (1, 1, 1) "generic" parse_ttl execute_ttl
+        "Removes the expiration time of a key.";
+    incr 2 [Write DenyOom Fast] (1, 1, 1) "string" parse_incr execute_incrby
+        "Increments the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.";
+    decr 2 [Write DenyOom Fast] (1, 1, 1) "string" parse_incr execute_incrby
+        "Decrements the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.";
+    incrby 3 [Write DenyOom Fast] (1, 1, 1) "string" parse_incrby execute_incrby
+        "Increments the integer value of a key by a number. Uses 0 as initial value if the key doesn't exist.";
+    decrby 3 [Write DenyOom Fast] (1, 1, 1) "string" parse_incrby execute_incrby
+        "Decrements a number from the integer value of a key. Uses 0 as initial value if the key doesn't exist.";
+    incrbyfloat 3 [Write DenyOom Fast] (1, 1, 1) "string" parse_incrbyfloat execute_incrbyfloat
+        "Increment the floating point value of a key by a number. Uses 0 as initial value if the key doesn't exist.";
+    append 3 [Write DenyOom Fast] (1, 1, 1) "string" parse_append execute_append
+        "Appends a string to the value of a key. Creates the key if it doesn't exist.";
+    strlen 2 [ReadOnly Fast] (1, 1, 1) "string" parse_strlen execute_strlen
+        "Returns the length of a string value.";
+    getrange 4 [ReadOnly] (1, 1, 1) "string" parse_getrange execute_getrange
+        "Returns a substring of the string stored at a key.";
+    setrange 4 [Write DenyOom] (1, 1, 1) "string" parse_setrange execute_setrange
+        "Overwrites a part of a string value with another by an offset. Creates the key if it doesn't exist.";
+    setbit 4 [Write DenyOom] (1, 1, 1) "bitmap" parse_setbit execute_setbit
+        "Sets or clears the bit at offset of the string value. Creates the key if it doesn't exist.";
+    getbit 3 [ReadOnly Fast] (1, 1, 1) "bitmap" parse_getbit execute_getbit
+        "Returns a bit value by offset.";
+    bitcount -2 [ReadOnly] (1, 1, 1) "bitmap" parse_bitcount execute_bitcount
+        "Counts the number of set bits (population counting) in a string.";
+    bitpos -3 [ReadOnly] (1, 1, 1) "bitmap" parse_bitpos execute_bitpos
+        "Finds the first set (1) or clear (0) bit in a string.";
+    bitop -4 [Write DenyOom] (2, -1, 1) "bitmap" parse_bitop execute_bitop
+        "Performs bitwise operations on multiple strings, and stores the result.";
+    bitfield -2 [Write DenyOom] (1, 1, 1) "bitmap" parse_bitfield execute_bitfield
+        "Performs arbitrary bitfield integer operations on strings.";
+    bitfield_ro -2 [ReadOnly Fast] (1, 1, 1) "bitmap" parse_bitfield execute_bitfield
+        "Performs arbitrary read-only bitfield integer operations on strings.";
+    mget -2 [ReadOnly Fast] (1, -1, 1) "string" parse_mget execute_mget
+        "Atomically returns the string values of one or more keys.";
+    mset -3 [Write DenyOom] (1, -1, 2) "string" parse_mset execute_mset
+        "Atomically creates or modifies the string values of one or more keys.";
+    msetnx -3 [Write DenyOom] (1, -1, 2) "string" parse_mset execute_mset
+        "Atomically modifies the string values of one or more keys only when all keys don't exist.";
+    del -2 [Write] (1, -1, 1) "generic" parse_del execute_del
+        "Deletes one or more keys.";
+    unlink -2 [Write Fast] (1, -1, 1) "generic" parse_del execute_del
+        "Asynchronously deletes one or more keys.";
+    exists -2 [ReadOnly Fast] (1, -1, 1) "generic" parse_del execute_del
+        "Determines whether one or more keys exist.";
+    touch -2 [ReadOnly Fast] (1, -1, 1) "generic" parse_del execute_del
+        "Returns the number of existing keys out of those specified after updating the time they were last accessed.";
+    type 2 [ReadOnly Fast] (1, 1, 1) "generic" parse_type execute_type
+        "Determines the type of value stored at a key.";
+    rename 3 [Write] (1, 2, 1) "generic" parse_rename execute_rename
+        "Renames a key and overwrites the destination.";
+    renamenx 3 [Write Fast] (1, 2, 1) "generic" parse_rename execute_rename
+        "Renames a key only when the target key name doesn't exist.";
+    copy -3 [Write DenyOom] (1, 2, 1) "generic" parse_copy execute_copy
+        "Copies the value of a key to a new key.";
+    randomkey 1 [ReadOnly] (0, 0, 0) "generic" parse_randomkey execute_randomkey
+        "Returns a random key name from the database.";
+    dbsize 1 [ReadOnly Fast] (0, 0, 0) "server" parse_randomkey execute_randomkey
+        "Returns the number of keys in the database.";
+    flushdb -1 [Write Admin] (0, 0, 0) "server" parse_flushdb execute_flushdb
+        "Removes all keys from the current database.";
+    flushall -1 [Write Admin] (0, 0, 0) "server" parse_flushdb execute_flushdb
+        "Removes all keys from all databases.";
+    keys 2 [ReadOnly] (0, 0, 0) "generic" parse_keys execute_keys
+        "Returns all key names that match a pattern.";
+    scan -2 [ReadOnly] (0, 0, 0) "generic" parse_scan execute_scan
+        "Iterates over the key names in the database.";
+    lpush -3 [Write DenyOom Fast] (1, 1, 1) "list" parse_lpush execute_lpush
+        "Prepends one or more elements to a list. Creates the key if it doesn't exist.";
+    rpush -3 [Write DenyOom Fast] (1, 1, 1) "list" parse_lpush execute_lpush
+        "Appends one or more elements to a list. Creates the key if it doesn't exist.";
+    lpop -2 [Write Fast] (1, 1, 1) "list" parse_lpop execute_lpop
+        "Returns the first elements in a list after removing it. Deletes the list if the last element was popped.";
+    rpop -2 [Write Fast] (1, 1, 1) "list" parse_lpop execute_lpop
+        "Returns and removes the last elements of a list. Deletes the list if the last element was popped.";
+    lrange 4 [ReadOnly] (1, 1, 1) "list" parse_lrange execute_lrange
+        "Returns a range of elements from a list.";
+    ltrim 4 [Write] (1, 1, 1) "list" parse_lrange execute_lrange
+        "Removes elements from both ends a list. Deletes the list if all elements were trimmed.";
+    lindex 3 [ReadOnly] (1, 1, 1) "list" parse_lindex execute_lindex
+        "Returns an element from a list by its index.";
+    lset 4 [Write DenyOom] (1, 1, 1) "list" parse_lset execute_lset
+        "Sets the value of an element in a list by its index.";
+    lrem 4 [Write] (1, 1, 1) "list" parse_lset execute_lset
+        "Removes elements from a list. Deletes the list if the last element was removed.";
+    linsert 5 [Write DenyOom] (1, 1, 1) "list" parse_linsert execute_linsert
+        "Inserts an element before or after another element in a list.";
+    llen 2 [ReadOnly Fast] (1, 1, 1) "list" parse_llen execute_llen
+        "Returns the length of a list.";
+    lmove 5 [Write DenyOom] (1, 2, 1) "list" parse_lmove execute_lmove
+        "Returns an element after popping it from one list and pushing it to another. Deletes the list if the last element was moved.";
+    blpop -3 [Write Blocking] (1, -2, 1) "list" parse_blpop execute_blocking
+        "Removes and returns the first element in a list. Blocks until an element is available otherwise. Deletes the list if the last element was popped.";
+    brpop -3 [Write Blocking] (1, -2, 1) "list" parse_blpop execute_blocking
+        "Removes and returns the last element in a list. Blocks until an element is available otherwise. Deletes the list if the last element was popped.";
+    blmove 6 [Write DenyOom Blocking] (1, 2, 1) "list" parse_blmove execute_blocking
+        "Pops an element from a list, pushes it to another list and returns it. Blocks until an element is available otherwise. Deletes the list if the last element was moved.";
+    lpos -3 [ReadOnly] (1, 1, 1) "list" parse_lpos execute_lpos
+        "Returns the index of matching elements in a list.";
+    hset -4 [Write DenyOom Fast] (1, 1, 1) "hash" parse_hset execute_hset
+        "Creates or modifies the value of a field in a hash.";
+    hget 3 [ReadOnly Fast] (1, 1, 1) "hash" parse_hget execute_hget
+        "Returns the value of a field in a hash.";
+    hexists 3 [ReadOnly Fast] (1, 1, 1) "hash" parse_hget execute_hget
+        "Determines whether a field exists in a hash.";
+    hmget -3 [ReadOnly Fast] (1, 1, 1) "hash" parse_hmget execute_hmget
+        "Returns the values of all fields in a hash.";
+    hdel -3 [Write Fast] (1, 1, 1) "hash" parse_hmget execute_hmget
+        "Deletes one or more fields and their values from a hash. Deletes the hash if no fields remain.";
+    hgetall 2 [ReadOnly] (1, 1, 1) "hash" parse_hgetall execute_hgetall
+        "Returns all fields and values in a hash.";
+    hkeys 2 [ReadOnly] (1, 1, 1) "hash" parse_hgetall execute_hgetall
+        "Returns all fields in a hash.";
+    hvals 2 [ReadOnly] (1, 1, 1) "hash" parse_hgetall execute_hgetall
+        "Returns all values in a hash.";
+    hlen 2 [ReadOnly Fast] (1, 1, 1) "hash" parse_hgetall execute_hgetall
+        "Returns the number of fields in a hash.";
+    hincrby 4 [Write DenyOom Fast] (1, 1, 1) "hash" parse_hincrby execute_hincrby
+        "Increments the integer value of a field in a hash by a number. Uses 0 as initial value if the field doesn't exist.";
+    hincrbyfloat 4 [Write DenyOom Fast] (1, 1, 1) "hash" parse_hincrbyfloat execute_hincrbyfloat
+        "Increments the floating point value of a field by a number. Uses 0 as initial value if the field doesn't exist.";
+    hscan -3 [ReadOnly] (1, 1, 1) "hash" parse_hscan execute_hscan
+        "Iterates over fields and values of a hash.";
+    hrandfield -2 [ReadOnly] (1, 1, 1) "hash" parse_hrandfield execute_hrandfield
+        "Returns one or more random fields from a hash.";
+    sadd -3 [Write DenyOom Fast] (1, 1, 1) "set" parse_sadd execute_sadd
+        "Adds one or more members to a set. Creates the key if it doesn't exist.";
+    srem -3 [Write Fast] (1, 1, 1) "set" parse_sadd execute_sadd
+        "Removes one or more members from a set. Deletes the set if the last member was removed.";
+    smismember -3 [ReadOnly Fast] (1, 1, 1) "set" parse_sadd execute_sadd
+        "Determines whether multiple members belong to a set.";
+    sismember 3 [ReadOnly Fast] (1, 1, 1) "set" parse_sismember execute_sismember
+        "Determines whether a member belongs to a set.";
+    smembers 2 [ReadOnly] (1, 1, 1) "set" parse_smembers execute_smembers
+        "Returns all members of a set.";
+    scard 2 [ReadOnly Fast] (1, 1, 1) "set" parse_smembers execute_smembers
+        "Returns the number of members in a set.";
+    spop -2 [Write Fast] (1, 1, 1) "set" parse_spop execute_spop
+        "Returns one or more random members from a set after removing them. Deletes the set if the last member was popped.";
+    srandmember -2 [ReadOnly] (1, 1, 1) "set" parse_srandmember execute_srandmember
+        "Get one or multiple random members from a set.";
+    sinter -2 [ReadOnly] (1, -1, 1) "set" parse_sinter execute_sinter
+        "Returns the intersect of multiple sets.";
+    sunion -2 [ReadOnly] (1, -1, 1) "set" parse_sinter execute_sinter
+        "Returns the union of multiple sets.";
+    sdiff -2 [ReadOnly] (1, -1, 1) "set" parse_sinter execute_sinter
+        "Returns the difference of multiple sets.";
+    sinterstore -3 [Write DenyOom] (1, -1, 1) "set" parse_sinterstore execute_sinterstore
+        "Stores the intersect of multiple sets in a key.";
+    sunionstore -3 [Write DenyOom] (1, -1, 1) "set" parse_sinterstore execute_sinterstore
+        "Stores the union of multiple sets in a key.";
+    sdiffstore -3 [Write DenyOom] (1, -1, 1) "set" parse_sinterstore execute_sinterstore
+        "Stores the difference of multiple sets in a key.";
+    sintercard -3 [ReadOnly MovableKeys] (0, 0, 0) "set" parse_sintercard execute_sintercard
+        "Returns the number of members of the intersect of multiple sets.";
+    sscan -3 [ReadOnly] (1, 1, 1) "set" parse_sscan execute_sscan
+        "Iterates over members of a set.";
+    zadd -4 [Write DenyOom Fast] (1, 1, 1) "sorted-set" parse_zadd execute_zadd
+        "Adds one or more members to a sorted set, or updates their scores. Creates the key if it doesn't exist.";
+    zrange -4 [ReadOnly] (1, 1, 1) "sorted-set" parse_zrange execute_zrange
+        "Returns members in a sorted set within a range of indexes.";
+    zrank -3 [ReadOnly Fast] (1, 1, 1) "sorted-set" parse_zrank execute_zrank
+        "Returns the index of a member in a sorted set ordered by ascending scores.";
+    zscore 3 [ReadOnly Fast] (1, 1, 1) "sorted-set" parse_zscore execute_zscore
+        "Returns the score of a member in a sorted set.";
+    zincrby 4 [Write DenyOom Fast] (1, 1, 1) "sorted-set" parse_zincrby execute_zincrby
+        "Increments the score of a member in a sorted set.";
+    zrem -3 [Write Fast] (1, 1, 1) "sorted-set" parse_zrem execute_zrem
+        "Removes one or more members from a sorted set. Deletes the sorted set if all members were removed.";
+    zcard 2 [ReadOnly Fast] (1, 1, 1) "sorted-set" parse_zcard execute_zcard
+        "Returns the number of members in a sorted set.";
+    zpopmin -2 [Write Fast] (1, 1, 1) "sorted-set" parse_zpopmin execute_zpopmin
+        "Returns the lowest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped.";
+    zpopmax -2 [Write Fast] (1, 1, 1) "sorted-set" parse_zpopmin execute_zpopmin
+        "Returns the highest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped.";
+    bzpopmin -3 [Write Fast Blocking] (1, -2, 1) "sorted-set" parse_blpop execute_blocking
+        "Removes and returns the member with the lowest score from one or more sorted sets. Blocks until a member is available otherwise. Deletes the sorted set if the last element was popped.";
+    bzpopmax -3 [Write Fast Blocking] (1, -2, 1) "sorted-set" parse_blpop execute_blocking
+        "Removes and returns the member with the highest score from one or more sorted sets. Blocks until a member available otherwise. Deletes the sorted set if the last element was popped.";
+    zunionstore -4 [Write DenyOom MovableKeys] (1, 1, 1) "sorted-set" parse_zunionstore execute_zunionstore
+        "Stores the union of multiple sorted sets in a key.";
+    zinterstore -4 [Write DenyOom MovableKeys] (1, 1, 1) "sorted-set" parse_zunionstore execute_zunionstore
+        "Stores the intersect of multiple sorted sets in a key.";
+    xadd -5 [Write DenyOom Fast] (1, 1, 1) "stream" parse_xadd execute_xadd
+        "Appends a new message to a stream. Creates the key if it doesn't exist.";
+    xrange -4 [ReadOnly] (1, 1, 1) "stream" parse_xrange execute_xrange
+        "Returns the messages from a stream within a range of IDs.";
+    xrevrange -4 [ReadOnly] (1, 1, 1) "stream" parse_xrange execute_xrange
+        "Returns the messages from a stream within a range of IDs in reverse order.";
+    xlen 2 [ReadOnly Fast] (1, 1, 1) "stream" parse_xlen execute_xlen
+        "Return the number of messages in a stream.";
+    xtrim -4 [Write] (1, 1, 1) "stream" parse_xtrim execute_xtrim
+        "Deletes messages from the beginning of a stream.";
+    xdel -3 [Write Fast] (1, 1, 1) "stream" parse_xdel execute_xdel
+        "Returns the number of messages after removing them from a stream.";
+    xread -4 [ReadOnly Blocking MovableKeys] (0, 0, 0) "stream" parse_xread execute_blocking
+        "Returns messages from multiple streams with IDs greater than the ones requested. Blocks until a message is available otherwise.";
+    xreadgroup -7 [Write Blocking MovableKeys] (0, 0, 0) "stream" parse_xread execute_blocking
+        "Returns new or historical messages from a stream for a consumer in a group. Blocks until a message is available otherwise.";
+    xgroup -2 [] (0, 0, 0) "stream" parse_xgroup execute_xgroup
+        "A container for consumer groups commands.";
+    xack -4 [Write Fast] (1, 1, 1) "stream" parse_xack execute_xack
+        "Returns the number of messages that were successfully acknowledged by the consumer group member of a stream.";
+    xpending -3 [ReadOnly] (1, 1, 1) "stream" parse_xpending execute_xpending
+        "Returns the information and entries from a stream consumer group's pending entries list.";
+    xclaim -6 [Write Fast] (1, 1, 1) "stream" parse_xclaim execute_xclaim
+        "Changes, or acquires, ownership of a message in a consumer group, as if the message was delivered a consumer group member.";
+    xautoclaim -6 [Write Fast] (1, 1, 1) "stream" parse_xautoclaim execute_xautoclaim
+        "Changes, or acquires, ownership of messages in a consumer group, as if the messages were delivered to as consumer group member.";
+    select 2 [Loading Stale Fast] (0, 0, 0) "connection" parse_select execute_select
+        "Changes the selected database.";
+    move 3 [Write Fast] (1, 1, 1) "generic" parse_move execute_move
+        "Moves a key to another database.";
+    swapdb 3 [Write Fast] (0, 0, 0) "server" parse_swapdb execute_swapdb
+        "Swaps two Redis databases.";
+    hello -1 [NoScript Loading Stale Fast NoAuth] (0, 0, 0) "connection" parse_hello execute_hello
+        "Handshakes with the Redis server.";
+    config -2 [] (0, 0, 0) "server" parse_config execute_config
+        "A container for server configuration commands.";
+    command -1 [Loading Stale] (0, 0, 0) "server" parse_command execute_command
+        "Returns detailed information about all commands.";
+}
+
+/// Finds a command by name, in any case.
+pub fn lookup(name: &str) -> Option<&'static CommandSpec> {
+    static BY_NAME: OnceLock<HashMap<&'static str, &'static CommandSpec>> = OnceLock::new();
+    let by_name = BY_NAME.get_or_init(|| COMMANDS.iter().map(|spec| (spec.name, spec)).collect());
+    by_name.get(name.to_ascii_lowercase().as_str()).copied()
+}
+
+impl CommandSpec {
+    /// Whether the command can be called with `len` arguments, counting its
+    /// name.
+    pub fn accepts(&self, len: usize) -> bool {
+        match self.arity {
+            arity if arity >= 0 => len as i64 == arity,
+            arity => len as i64 >= -arity,
+        }
+    }
+
+    fn has_flag(&self, flag: CommandFlag) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    /// The ACL categories the command belongs to, following from its flags and
+    /// group the way they do in redis.
+    fn acl_categories(&self) -> Vec<&'static str> {
+        let mut categories = Vec::new();
+        if self.has_flag(CommandFlag::Write) {
+            categories.push("write");
+        }
+        if self.has_flag(CommandFlag::ReadOnly) {
+            categories.push("read");
+        }
+        match self.group {
+            "generic" => categories.push("keyspace"),
+            "sorted-set" => categories.push("sortedset"),
+            "server" => {}
+            group => categories.push(group),
+        }
+        if self.has_flag(CommandFlag::Admin) {
+            categories.extend(["admin", "dangerous"]);
+        }
+        categories.push(match self.has_flag(CommandFlag::Fast) {
+            true => "fast",
+            false => "slow",
+        });
+        if self.has_flag(CommandFlag::Blocking) {
+            categories.push("blocking");
+        }
+        categories
+    }
+
+    /// The positions of the keys in `request`, a call to the command starting
+    /// with its name, or `None` if they cannot be told from its arguments.
+    fn key_positions(&self, request: &[&[u8]]) -> Option<Vec<usize>> {
+        let numkeys = |position: usize| {
+            let count = usize::try_from(parse_integer(request.get(position)?).ok()?).ok()?;
+            (count > 0 && position + count < request.len()).then_some(count)
+        };
+        let positions = match self.name {
+            "sintercard" => (2..2 + numkeys(1)?).collect(),
+            "zunionstore" | "zinterstore" => std::iter::once(1).chain(3..3 + numkeys(2)?).collect(),
+            "xread" | "xreadgroup" => {
+                // The keys are the first half of what follows STREAMS.
+                let streams = request
+                    .iter()
+                    .position(|arg| arg.eq_ignore_ascii_case(b"STREAMS"))?;
+                let rest = request.len() - streams - 1;
+                if rest == 0 || !rest.is_multiple_of(2) {
+                    return None;
+                }
+                (streams + 1..streams + 1 + rest / 2).collect()
+            }
+            _ if self.first_key == 0 => Vec::new(),
+            _ => {
+                let last = match self.last_key {
+                    last if last < 0 => request.len() as i64 + last,
+                    last => last,
+                };
+                (self.first_key..=last)
+                    .step_by(self.key_step as usize)
+                    .map(|position| position as usize)
+                    .collect()
+            }
+        };
+        Some(positions)
+    }
+
+    /// Replies to COMMAND INFO for this command.
+    pub fn info(&self) -> RedisValue {
+        let status_set = |names: Vec<String>| {
+            RedisValue::Set(names.into_iter().map(RedisValue::SimpleString).collect())
+        };
+        let flags = self
+            .flags
+            .iter()
+            .map(|flag| flag.name().to_string())
+            .collect();
+        let categories = self
+            .acl_categories()
+            .into_iter()
+            .map(|category| format!("@{}", category))
+            .collect();
+        RedisValue::Array(Some(vec![
+            RedisValue::BulkString(Some(self.name.into())),
+            RedisValue::Integer(self.arity),
+            status_set(flags),
+            RedisValue::Integer(self.first_key),
+            RedisValue::Integer(self.last_key),
+            RedisValue::Integer(self.key_step),
+            status_set(categories),
+            // Tips, key specifications and subcommands, none of which are
+            // described.
+            RedisValue::Set(Vec::new()),
+            RedisValue::Array(Some(Vec::new())),
+            RedisValue::Array(Some(Vec::new())),
+        ]))
+    }
+
+    /// Replies to COMMAND DOCS for this command, with its name and a map of
+    /// its documentation.
+    pub fn docs(&self) -> (RedisValue, RedisValue) {
+        let bulk_string = |value: &str| RedisValue::BulkString(Some(value.into()));
+        let docs = RedisValue::Map(vec![
+            (bulk_string("summary"), bulk_string(self.summary)),
+            (bulk_string("group"), bulk_string(self.group)),
+        ]);
+        (bulk_string(self.name), docs)
+    }
+}
+
+/// What COMMAND LIST lists commands by.
+#[derive(Debug, PartialEq)]
+pub enum CommandFilter {
+    /// Commands added by the named module, of which there are none.
+    Module(Vec<u8>),
+    AclCategory(Vec<u8>),
+    Pattern(Vec<u8>),
+}
+
+impl CommandFilter {
+    pub fn matches(&self, spec: &CommandSpec) -> bool {
+        match self {
+            CommandFilter::Module(_) => false,
+            CommandFilter::AclCategory(category) => spec
+                .acl_categories()
+                .iter()
+                .any(|name| name.as_bytes().eq_ignore_ascii_case(category)),
+            CommandFilter::Pattern(pattern) => glob::matches(pattern, spec.name.as_bytes()),
+        }
+    }
+}
+
+/// Replies to COMMAND GETKEYS with the keys in `request`, a command starting
+/// with its name.
+pub fn get_keys(request: &[Vec<u8>]) -> Result<Vec<Vec<u8>>, RedisError> {
+    let request: Vec<&[u8]> = request.iter().map(Vec::as_slice).collect();
+    let spec = lookup(&String::from_utf8_lossy(request[0]))
+        .ok_or(RedisError::other("ERR Invalid command specified"))?;
+    if !spec.accepts(request.len()) {
+        return Err(RedisError::other(
+            "ERR Invalid number of arguments specified for command",
+        ));
+    }
+    let positions = spec.key_positions(&request).ok_or(RedisError::other(
+        "ERR Invalid arguments specified for command",
+    ))?;
+    if positions.is_empty() {
+        return Err(RedisError::other("ERR The command has no key arguments"));
+    }
+    Ok(positions
+        .into_iter()
+        .filter_map(|position| request.get(position))
+        .map(|key| key.to_vec())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        for (i, spec) in COMMANDS.iter().enumerate() {
+            assert_eq!(spec.name, spec.name.to_lowercase());
+            assert!(std::ptr::eq(lookup(spec.name).unwrap(), &COMMANDS[i]));
+            assert!(std::ptr::eq(
+                lookup(&spec.name.to_uppercase()).unwrap(),
+                &COMMANDS[i]
+            ));
+        }
+        assert!(lookup("foo").is_none());
+        let get = lookup("Get").unwrap();
+        assert!(get.accepts(2));
+        assert!(!get.accepts(3));
+        let mset = lookup("mset").unwrap();
+        assert!(!mset.accepts(2));
+        assert!(mset.accepts(5));
+    }
+
+    #[test]
+    fn test_get_keys() {
+        let keys = |request: &[&str]| {
+            let request: Vec<Vec<u8>> = request.iter().map(|arg| arg.as_bytes().to_vec()).collect();
+            get_keys(&request).map_err(|e| e.to_string())
+        };
+        let strings =
+            |strings: &[&str]| Ok(strings.iter().map(|s| s.as_bytes().to_vec()).collect());
+        assert_eq!(keys(&["GET", "a"]), strings(&["a"]));
+        assert_eq!(keys(&["mset", "a", "1", "b", "2"]), strings(&["a", "b"]));
+        assert_eq!(keys(&["DEL", "a", "b", "c"]), strings(&["a", "b", "c"]));
+        assert_eq!(
+            keys(&["lmove", "a", "b", "LEFT", "RIGHT"]),
+            strings(&["a", "b"])
+        );
+        assert_eq!(
+            keys(&["ZUNIONSTORE", "d", "2", "a", "b", "WEIGHTS", "1", "2"]),
+            strings(&["d", "a", "b"])
+        );
+        assert_eq!(
+            keys(&["XREAD", "COUNT", "1", "STREAMS", "a", "b", "0", "0"]),
+            strings(&["a", "b"])
+        );
+        assert_eq!(
+            keys(&["foo", "a"]),
+            Err("ERR Invalid command specified".to_string())
+        );
+        assert_eq!(
+            keys(&["GET"]),
+            Err("ERR Invalid number of arguments specified for command".to_string())
+        );
+        assert_eq!(
+            keys(&["SINTERCARD", "3", "a"]),
+            Err("ERR Invalid arguments specified for command".to_string())
+        );
+        assert_eq!(
+            keys(&["PING"]),
+            Err("ERR The command has no key arguments".to_string())
+        );
+    }
+
+    #[test]
+    fn test_info() {
+        let RedisValue::Array(Some(info)) = lookup("get").unwrap().info() else {
+            panic!("COMMAND INFO replies with an array");
+        };
+        let status_set = |names: &[&str]| {
+            RedisValue::Set(
+                names
+                    .iter()
+                    .map(|name| RedisValue::SimpleString(name.to_string()))
+                    .collect(),
+            )
+        };
+        assert_eq!(info[0], RedisValue::BulkString(Some(b"get".to_vec())));
+        assert_eq!(info[1], RedisValue::Integer(2));
+        assert_eq!(info[2], status_set(&["readonly", "fast"]));
+        assert_eq!(
+            info[3..6],
+            [
+                RedisValue::Integer(1),
+                RedisValue::Integer(1),
+                RedisValue::Integer(1)
+            ]
+        );
+        assert_eq!(info[6], status_set(&["@read", "@string", "@fast"]));
+        let filter = CommandFilter::AclCategory(b"BLOCKING".to_vec());
+        assert!(filter.matches(lookup("blpop").unwrap()));
+        assert!(!filter.matches(lookup("lpop").unwrap()));
+        assert!(CommandFilter::Pattern(b"z*store".to_vec()).matches(lookup("zunionstore").unwrap()));
+    }
+}
@@ -0,0 +1,115 @@
+use std::fmt;
+
+// Redis quotes at most this many bytes of a client's command in the reply to
+// an unknown command.
+const QUOTED_COMMAND_LIMIT: usize = 128;
+
+/// The errors a command can fail with, each displayed as the exact message
+/// redis replies with. Those clients are expected to tell apart have their
+/// own variant, while errors specific to a single command are free form
+/// messages.
+#[derive(Debug, PartialEq)]
+pub enum RedisError {
+    /// A command name that is not recognised, and the arguments it came with.
+    UnknownCommand {
+        name: Vec<u8>,
+        args: Vec<Vec<u8>>,
+    },
+    /// The name of a command, or `command|subcommand`, given the wrong number
+    /// of arguments.
+    WrongArity(String),
+    Syntax,
+    WrongType,
+    NotInteger,
+    /// Input that is not valid RESP, and the offset of the byte at which the
+    /// frame containing it went wrong.
+    Protocol {
+        message: String,
+        offset: usize,
+    },
+    /// A message specific to a command, error code included.
+    Other(String),
+}
+
+impl RedisError {
+    pub fn protocol(message: impl Into<String>, offset: usize) -> RedisError {
+        RedisError::Protocol {
+            message: message.into(),
+            offset,
+        }
+    }
+
+    pub fn other(message: impl Into<String>) -> RedisError {
+        RedisError::Other(message.into())
+    }
+}
+
+impl fmt::Display for RedisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RedisError::UnknownCommand { name, args } => {
+                let name = &name[..name.len().min(QUOTED_COMMAND_LIMIT)];
+                write!(
+                    f,
+                    "ERR unknown command '{}', with args beginning with: ",
+                    String::from_utf8_lossy(name)
+                )?;
+                let mut quoted = 0;
+                for arg in args {
+                    if quoted >= QUOTED_COMMAND_LIMIT {
+                        break;
+                    }
+                    let arg = &arg[..arg.len().min(QUOTED_COMMAND_LIMIT - quoted)];
+                    write!(f, "'{}' ", String::from_utf8_lossy(arg))?;
+                    quoted += arg.len() + 3;
+                }
+                Ok(())
+            }
+            RedisError::WrongArity(command) => write!(
+                f,
+                "ERR wrong number of arguments for '{}' command",
+                command.to_lowercase()
+            ),
+            RedisError::Syntax => write!(f, "ERR syntax error"),
+            RedisError::WrongType => write!(
+                f,
+                "WRONGTYPE Operation against a key holding the wrong kind of value"
+            ),
+            RedisError::NotInteger => write!(f, "ERR value is not an integer or out of range"),
+            RedisError::Protocol { message, .. } => write!(f, "ERR Protocol error: {}", message),
+            RedisError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for RedisError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_command() {
+        let error = |name: &str, args: &[&str]| RedisError::UnknownCommand {
+            name: name.as_bytes().to_vec(),
+            args: args.iter().map(|arg| arg.as_bytes().to_vec()).collect(),
+        };
+        assert_eq!(
+            error("foo", &[]).to_string(),
+            "ERR unknown command 'foo', with args beginning with: "
+        );
+        assert_eq!(
+            error("Foo", &["a", "bc"]).to_string(),
+            "ERR unknown command 'Foo', with args beginning with: 'a' 'bc' "
+        );
+        // Arguments are quoted until 128 bytes of them have been.
+        let long = "x".repeat(200);
+        assert_eq!(
+            error("foo", &["abc", &long, "d"]).to_string(),
+            format!(
+                "ERR unknown command 'foo', with args beginning with: 'abc' '{}' ",
+                "x".repeat(122)
+            )
+        );
+    }
+}
@@ -0,0 +1,1860 @@
+use std::time::Duration;
+
+use crate::command;
+use crate::error::RedisError;
+use crate::resp::{Protocol, RedisCommand, RedisValue};
+use crate::storage::{
+    unix_time_millis, DeliveredEntry, ExpireCondition, FieldValues, ListEnd, SetOp, Storage,
+    StreamEntry, StreamId, StreamsRead, ZAddOptions,
+};
+use crate::{DATABASES, ERR_DB_INDEX};
+
+/// The state of a connection that commands can change.
+#[derive(Debug, Default)]
+pub struct Session {
+    /// The database selected with SELECT, which commands operate on.
+    pub db: usize,
+    /// The protocol chosen with HELLO, which replies are encoded in.
+    pub protocol: Protocol,
+}
+
+pub fn execute_ping(
+    command: RedisCommand,
+    _databases: &mut [Storage],
+    _session: &mut Session,
+) -> RedisValue {
+    match command {
+        RedisCommand::PING(message) | RedisCommand::ECHO(message) => message,
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_hello(
+    command: RedisCommand,
+    _databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    match command {
+        RedisCommand::HELLO(version) => {
+            session.protocol = version.unwrap_or(session.protocol);
+            let proto = match session.protocol {
+                Protocol::Resp2 => 2,
+                Protocol::Resp3 => 3,
+            };
+            let info = |field: &str, value| (RedisValue::BulkString(Some(field.into())), value);
+            let bulk_string = |value: &str| RedisValue::BulkString(Some(value.into()));
+            RedisValue::Map(vec![
+                info("server", bulk_string("quickcache")),
+                info("version", bulk_string(env!("CARGO_PKG_VERSION"))),
+                info("proto", RedisValue::Integer(proto)),
+                info("mode", bulk_string("standalone")),
+                info("role", bulk_string("master")),
+                info("modules", RedisValue::Array(Some(Vec::new()))),
+            ])
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_command(
+    command: RedisCommand,
+    _databases: &mut [Storage],
+    _session: &mut Session,
+) -> RedisValue {
+    match command {
+        RedisCommand::COMMANDCOUNT => RedisValue::Integer(command::COMMANDS.len() as i64),
+        RedisCommand::COMMANDINFO(names) if names.is_empty() => RedisValue::Array(Some(
+            command::COMMANDS.iter().map(|spec| spec.info()).collect(),
+        )),
+        RedisCommand::COMMANDINFO(names) => RedisValue::Array(Some(
+            names
+                .iter()
+                .map(
+                    |name| match command::lookup(&String::from_utf8_lossy(name)) {
+                        Some(spec) => spec.info(),
+                        None => RedisValue::Null,
+                    },
+                )
+                .collect(),
+        )),
+        RedisCommand::COMMANDDOCS(names) => {
+            // Unlike COMMAND INFO, unknown commands are left out rather than
+            // replied to with a null.
+            let specs: Vec<_> = match names.is_empty() {
+                true => command::COMMANDS.iter().collect(),
+                false => names
+                    .iter()
+                    .filter_map(|name| command::lookup(&String::from_utf8_lossy(name)))
+                    .collect(),
+            };
+            RedisValue::Map(specs.into_iter().map(|spec| spec.docs()).collect())
+        }
+        RedisCommand::COMMANDGETKEYS(request) => {
+            reply(command::get_keys(&request).map(bulk_string_array))
+        }
+        RedisCommand::COMMANDLIST(filter) => RedisValue::Array(Some(
+            command::COMMANDS
+                .iter()
+                .filter(|spec| filter.as_ref().is_none_or(|filter| filter.matches(spec)))
+                .map(|spec| RedisValue::BulkString(Some(spec.name.into())))
+                .collect(),
+        )),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_config(
+    command: RedisCommand,
+    _databases: &mut [Storage],
+    _session: &mut Session,
+) -> RedisValue {
+    match command {
+        RedisCommand::CONFIG => RedisValue::SimpleString("OK".to_string()),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_get(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::GET(key) => reply(
+            storage
+                .get_string(&key)
+                .map(|value| RedisValue::BulkString(value.map(<[u8]>::to_vec))),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_set(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::SET(key, value, options) => {
+            // SET overwrites a value of any type, unless the old one has to
+            // be returned.
+            if options.get {
+                if let Err(e) = storage.get_string(&key) {
+                    return RedisValue::Error(e.to_string());
+                }
+            }
+            let outcome = storage.set(key, value, options);
+            if options.get {
+                RedisValue::BulkString(outcome.previous)
+            } else if outcome.written {
+                RedisValue::SimpleString("OK".to_string())
+            } else {
+                RedisValue::BulkString(None)
+            }
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_expire(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::EXPIRE(key, seconds, condition) => {
+            let when = seconds
+                .checked_mul(1000)
+                .and_then(|millis| millis.checked_add(unix_time_millis()));
+            expire_command(storage, &key, when, condition, "expire")
+        }
+        RedisCommand::PEXPIRE(key, millis, condition) => {
+            let when = millis.checked_add(unix_time_millis());
+            expire_command(storage, &key, when, condition, "pexpire")
+        }
+        RedisCommand::EXPIREAT(key, seconds, condition) => expire_command(
+            storage,
+            &key,
+            seconds.checked_mul(1000),
+            condition,
+            "expireat",
+        ),
+        RedisCommand::PEXPIREAT(key, millis, condition) => {
+            expire_command(storage, &key, Some(millis), condition, "pexpireat")
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_ttl(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::TTL(key) => {
+            ttl_command(storage, &key, |ttl| (ttl.as_millis() as i64 + 500) / 1000)
+        }
+        RedisCommand::PTTL(key) => ttl_command(storage, &key, |ttl| ttl.as_millis() as i64),
+        RedisCommand::EXPIRETIME(key) => expire_time_command(storage, &key, |millis| millis / 1000),
+        RedisCommand::PEXPIRETIME(key) => expire_time_command(storage, &key, |millis| millis),
+        RedisCommand::PERSIST(key) => RedisValue::Integer(storage.persist(&key) as i64),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_incrby(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::INCRBY(key, delta) => {
+            reply(storage.incr_by(&key, delta).map(RedisValue::Integer))
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_incrbyfloat(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::INCRBYFLOAT(key, delta) => reply(
+            storage
+                .incr_by_float(&key, delta)
+                .map(|value| RedisValue::BulkString(Some(value))),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_append(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::APPEND(key, value) => reply(
+            storage
+                .append(&key, &value)
+                .map(|len| RedisValue::Integer(len as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_strlen(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::STRLEN(key) => reply(
+            storage
+                .get_string(&key)
+                .map(|value| RedisValue::Integer(value.map_or(0, <[u8]>::len) as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_getrange(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::GETRANGE(key, start, end) => reply(
+            storage
+                .get_range(&key, start, end)
+                .map(|range| RedisValue::BulkString(Some(range))),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_setrange(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::SETRANGE(key, offset, value) => reply(
+            storage
+                .set_range(&key, offset, &value)
+                .map(|len| RedisValue::Integer(len as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_setbit(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::SETBIT(key, offset, value) => reply(
+            storage
+                .set_bit(&key, offset, value)
+                .map(|previous| RedisValue::Integer(previous as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_getbit(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::GETBIT(key, offset) => reply(
+            storage
+                .get_bit(&key, offset)
+                .map(|bit| RedisValue::Integer(bit as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_bitcount(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::BITCOUNT(key, range) => reply(
+            storage
+                .bit_count(&key, range)
+                .map(|count| RedisValue::Integer(count as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_bitpos(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::BITPOS(key, bit, start, end, unit) => reply(
+            storage
+                .bit_position(&key, bit, start, end, unit)
+                .map(RedisValue::Integer),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_bitop(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::BITOP(op, dst, sources) => reply(
+            storage
+                .bit_op(op, &dst, &sources)
+                .map(|len| RedisValue::Integer(len as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_bitfield(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::BITFIELD(key, ops) => reply(storage.bit_field(&key, &ops).map(|results| {
+            RedisValue::Array(Some(
+                results
+                    .into_iter()
+                    .map(|result| match result {
+                        Some(value) => RedisValue::Integer(value),
+                        None => RedisValue::BulkString(None),
+                    })
+                    .collect(),
+            ))
+        })),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_mget(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::MGET(keys) => {
+            let values = keys
+                .iter()
+                .map(|key| {
+                    // Keys holding other types read as missing rather than
+                    // failing the whole command.
+                    let value = storage.get_string(key).ok().flatten();
+                    RedisValue::BulkString(value.map(<[u8]>::to_vec))
+                })
+                .collect();
+            RedisValue::Array(Some(values))
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_mset(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::MSET(pairs) => {
+            storage.mset(pairs);
+            RedisValue::SimpleString("OK".to_string())
+        }
+        RedisCommand::MSETNX(pairs) => RedisValue::Integer(storage.msetnx(pairs) as i64),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_del(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::DEL(keys) => {
+            let deleted = keys.iter().filter(|key| storage.delete(key)).count();
+            RedisValue::Integer(deleted as i64)
+        }
+        RedisCommand::EXISTS(keys) | RedisCommand::TOUCH(keys) => {
+            let existing = keys.iter().filter(|key| storage.exists(key)).count();
+            RedisValue::Integer(existing as i64)
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_type(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::TYPE(key) => {
+            let key_type = storage.key_type(&key).unwrap_or("none");
+            RedisValue::SimpleString(key_type.to_string())
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_rename(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::RENAME(src, dst) => reply(
+            storage
+                .rename(&src, &dst, false)
+                .map(|_| RedisValue::SimpleString("OK".to_string())),
+        ),
+        RedisCommand::RENAMENX(src, dst) => reply(
+            storage
+                .rename(&src, &dst, true)
+                .map(|renamed| RedisValue::Integer(renamed as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_copy(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::COPY(src, dst, target, replace) => {
+            let result = match target.map(db_index) {
+                None => storage.copy(&src, &dst, None, replace),
+                Some(None) => Err(RedisError::other(ERR_DB_INDEX)),
+                Some(Some(target)) if target == session.db => {
+                    storage.copy(&src, &dst, None, replace)
+                }
+                Some(Some(target)) => {
+                    let [storage, target] = databases
+                        .get_disjoint_mut([session.db, target])
+                        .expect("indices are distinct and in range");
+                    storage.copy(&src, &dst, Some(target), replace)
+                }
+            };
+            reply(result.map(|copied| RedisValue::Integer(copied as i64)))
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_randomkey(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::RANDOMKEY => RedisValue::BulkString(storage.random_key()),
+        RedisCommand::DBSIZE => RedisValue::Integer(storage.dbsize() as i64),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_flushdb(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::FLUSHDB => {
+            storage.flush();
+            RedisValue::SimpleString("OK".to_string())
+        }
+        RedisCommand::FLUSHALL => {
+            databases.iter_mut().for_each(Storage::flush);
+            RedisValue::SimpleString("OK".to_string())
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_keys(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::KEYS(pattern) => {
+            let keys = storage.keys(&pattern);
+            bulk_string_array(keys)
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_lpush(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::LPUSH(key, elements) => push_command(storage, &key, ListEnd::Left, &elements),
+        RedisCommand::RPUSH(key, elements) => {
+            push_command(storage, &key, ListEnd::Right, &elements)
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_lpop(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::LPOP(key, count) => pop_command(storage, &key, ListEnd::Left, count),
+        RedisCommand::RPOP(key, count) => pop_command(storage, &key, ListEnd::Right, count),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_lrange(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::LRANGE(key, start, end) => {
+            reply(storage.list_range(&key, start, end).map(bulk_string_array))
+        }
+        RedisCommand::LTRIM(key, start, end) => reply(
+            storage
+                .list_trim(&key, start, end)
+                .map(|_| RedisValue::SimpleString("OK".to_string())),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_lindex(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::LINDEX(key, index) => {
+            reply(storage.list_index(&key, index).map(RedisValue::BulkString))
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_lset(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::LSET(key, index, element) => reply(
+            storage
+                .list_set(&key, index, &element)
+                .map(|_| RedisValue::SimpleString("OK".to_string())),
+        ),
+        RedisCommand::LREM(key, count, element) => reply(
+            storage
+                .list_remove(&key, count, &element)
+                .map(|removed| RedisValue::Integer(removed as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_linsert(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::LINSERT(key, before, pivot, element) => reply(
+            storage
+                .list_insert(&key, before, &pivot, &element)
+                .map(RedisValue::Integer),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_llen(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::LLEN(key) => reply(
+            storage
+                .list_len(&key)
+                .map(|len| RedisValue::Integer(len as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_lmove(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::LMOVE(src, dst, from, to) => reply(
+            storage
+                .list_move(&src, &dst, from, to)
+                .map(RedisValue::BulkString),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_lpos(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::LPOS(key, element, options) => {
+            let positions = storage.list_position(
+                &key,
+                &element,
+                options.rank,
+                options.count.unwrap_or(1),
+                options.maxlen,
+            );
+            reply(positions.map(|positions| {
+                let mut positions = positions
+                    .into_iter()
+                    .map(|position| RedisValue::Integer(position as i64));
+                match options.count {
+                    Some(_) => RedisValue::Array(Some(positions.collect())),
+                    None => positions.next().unwrap_or(RedisValue::BulkString(None)),
+                }
+            }))
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_hset(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::HSET(key, pairs) => reply(
+            storage
+                .hash_set(&key, pairs)
+                .map(|added| RedisValue::Integer(added as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_hget(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::HGET(key, field) => {
+            reply(storage.hash_get(&key, &field).map(RedisValue::BulkString))
+        }
+        RedisCommand::HEXISTS(key, field) => reply(
+            storage
+                .hash_exists(&key, &field)
+                .map(|exists| RedisValue::Integer(exists as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_hmget(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::HMGET(key, fields) => reply(storage.hash_mget(&key, &fields).map(|values| {
+            RedisValue::Array(Some(
+                values.into_iter().map(RedisValue::BulkString).collect(),
+            ))
+        })),
+        RedisCommand::HDEL(key, fields) => reply(
+            storage
+                .hash_delete(&key, &fields)
+                .map(|removed| RedisValue::Integer(removed as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_hgetall(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::HGETALL(key) => reply(storage.hash_get_all(&key).map(|pairs| {
+            RedisValue::Map(
+                pairs
+                    .into_iter()
+                    .map(|(field, value)| {
+                        (
+                            RedisValue::BulkString(Some(field)),
+                            RedisValue::BulkString(Some(value)),
+                        )
+                    })
+                    .collect(),
+            )
+        })),
+        RedisCommand::HKEYS(key) => {
+            reply(storage.hash_get_all(&key).map(|pairs| {
+                bulk_string_array(pairs.into_iter().map(|(field, _)| field).collect())
+            }))
+        }
+        RedisCommand::HVALS(key) => {
+            reply(storage.hash_get_all(&key).map(|pairs| {
+                bulk_string_array(pairs.into_iter().map(|(_, value)| value).collect())
+            }))
+        }
+        RedisCommand::HLEN(key) => reply(
+            storage
+                .hash_len(&key)
+                .map(|len| RedisValue::Integer(len as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_hincrby(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::HINCRBY(key, field, delta) => reply(
+            storage
+                .hash_incr_by(&key, &field, delta)
+                .map(RedisValue::Integer),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_hincrbyfloat(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::HINCRBYFLOAT(key, field, delta) => reply(
+            storage
+                .hash_incr_by_float(&key, &field, delta)
+                .map(|value| RedisValue::BulkString(Some(value))),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_hscan(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::HSCAN(key, cursor, options) => reply(
+            storage
+                .hash_scan(&key, cursor, &options)
+                .map(|(next, pairs)| {
+                    RedisValue::Array(Some(vec![
+                        RedisValue::BulkString(Some(next.to_string().into_bytes())),
+                        flat_pair_array(pairs),
+                    ]))
+                }),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_hrandfield(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::HRANDFIELD(key, count, with_values) => {
+            let pairs = storage.hash_random(&key, count.unwrap_or(1));
+            reply(pairs.map(|pairs| match count {
+                None => RedisValue::BulkString(pairs.into_iter().next().map(|(field, _)| field)),
+                Some(_) if with_values => flat_pair_array(pairs),
+                Some(_) => bulk_string_array(pairs.into_iter().map(|(field, _)| field).collect()),
+            }))
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_sadd(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::SADD(key, members) => reply(
+            storage
+                .set_add(&key, &members)
+                .map(|added| RedisValue::Integer(added as i64)),
+        ),
+        RedisCommand::SREM(key, members) => reply(
+            storage
+                .set_remove(&key, &members)
+                .map(|removed| RedisValue::Integer(removed as i64)),
+        ),
+        RedisCommand::SMISMEMBER(key, members) => {
+            reply(storage.set_contains(&key, &members).map(|found| {
+                RedisValue::Array(Some(
+                    found
+                        .into_iter()
+                        .map(|found| RedisValue::Integer(found as i64))
+                        .collect(),
+                ))
+            }))
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_sismember(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::SISMEMBER(key, member) => reply(
+            storage
+                .set_contains(&key, &[member])
+                .map(|found| RedisValue::Integer(found[0] as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_smembers(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::SMEMBERS(key) => reply(storage.set_members(&key).map(bulk_string_set)),
+        RedisCommand::SCARD(key) => reply(
+            storage
+                .set_len(&key)
+                .map(|len| RedisValue::Integer(len as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_spop(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::SPOP(key, count) => {
+            let popped = storage.set_pop(&key, count.unwrap_or(1));
+            reply(popped.map(|popped| match count {
+                None => RedisValue::BulkString(popped.into_iter().next()),
+                Some(_) => bulk_string_array(popped),
+            }))
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_srandmember(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::SRANDMEMBER(key, count) => {
+            let members = storage.set_random(&key, count.unwrap_or(1));
+            reply(members.map(|members| match count {
+                None => RedisValue::BulkString(members.into_iter().next()),
+                Some(_) => bulk_string_array(members),
+            }))
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_sinter(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::SINTER(keys) => reply(
+            storage
+                .set_combine(SetOp::Inter, &keys)
+                .map(bulk_string_set),
+        ),
+        RedisCommand::SUNION(keys) => reply(
+            storage
+                .set_combine(SetOp::Union, &keys)
+                .map(bulk_string_set),
+        ),
+        RedisCommand::SDIFF(keys) => {
+            reply(storage.set_combine(SetOp::Diff, &keys).map(bulk_string_set))
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_sinterstore(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::SINTERSTORE(dst, keys) => reply(
+            storage
+                .set_combine_store(SetOp::Inter, &dst, &keys)
+                .map(|len| RedisValue::Integer(len as i64)),
+        ),
+        RedisCommand::SUNIONSTORE(dst, keys) => reply(
+            storage
+                .set_combine_store(SetOp::Union, &dst, &keys)
+                .map(|len| RedisValue::Integer(len as i64)),
+        ),
+        RedisCommand::SDIFFSTORE(dst, keys) => reply(
+            storage
+                .set_combine_store(SetOp::Diff, &dst, &keys)
+                .map(|len| RedisValue::Integer(len as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_sintercard(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::SINTERCARD(keys, limit) => reply(
+            storage
+                .set_inter_card(&keys, limit)
+                .map(|count| RedisValue::Integer(count as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_sscan(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::SSCAN(key, cursor, options) => reply(
+            storage
+                .set_scan(&key, cursor, &options)
+                .map(|(next, members)| {
+                    RedisValue::Array(Some(vec![
+                        RedisValue::BulkString(Some(next.to_string().into_bytes())),
+                        bulk_string_array(members),
+                    ]))
+                }),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_zadd(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::ZADD(key, entries, options) => reply(
+            storage
+                .zset_add(&key, &entries, options)
+                .map(|count| RedisValue::Integer(count as i64)),
+        ),
+        RedisCommand::ZADDINCR(key, delta, member, options) => reply(
+            storage
+                .zset_incr_by(&key, &member, delta, options)
+                .map(|score| match score {
+                    Some(score) => RedisValue::Double(score),
+                    None => RedisValue::BulkString(None),
+                }),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_zincrby(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::ZINCRBY(key, delta, member) => reply(
+            storage
+                .zset_incr_by(&key, &member, delta, ZAddOptions::default())
+                .map(|score| RedisValue::Double(score.expect("no flags to rule it out"))),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_zrange(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::ZRANGE(key, options) => reply(
+            storage
+                .zset_range(&key, &options)
+                .map(|entries| scored_array(entries, options.with_scores)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_zrank(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::ZRANK(key, member, with_score) => {
+            reply(storage.zset_rank(&key, &member).map(|rank| match rank {
+                Some((rank, score)) if with_score => RedisValue::Array(Some(vec![
+                    RedisValue::Integer(rank as i64),
+                    RedisValue::Double(score),
+                ])),
+                Some((rank, _)) => RedisValue::Integer(rank as i64),
+                None if with_score => RedisValue::Array(None),
+                None => RedisValue::BulkString(None),
+            }))
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_zscore(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::ZSCORE(key, member) => {
+            reply(storage.zset_score(&key, &member).map(|score| match score {
+                Some(score) => RedisValue::Double(score),
+                None => RedisValue::BulkString(None),
+            }))
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_zrem(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::ZREM(key, members) => reply(
+            storage
+                .zset_remove(&key, &members)
+                .map(|removed| RedisValue::Integer(removed as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_zcard(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::ZCARD(key) => reply(
+            storage
+                .zset_len(&key)
+                .map(|len| RedisValue::Integer(len as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_zpopmin(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::ZPOPMIN(key, count) => reply(
+            storage
+                .zset_pop(&key, count.unwrap_or(1), false)
+                .map(|popped| scored_array(popped, true)),
+        ),
+        RedisCommand::ZPOPMAX(key, count) => reply(
+            storage
+                .zset_pop(&key, count.unwrap_or(1), true)
+                .map(|popped| scored_array(popped, true)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_zunionstore(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::ZUNIONSTORE(dst, keys, weights, aggregate) => reply(
+            storage
+                .zset_combine_store(SetOp::Union, &dst, &keys, &weights, aggregate)
+                .map(|len| RedisValue::Integer(len as i64)),
+        ),
+        RedisCommand::ZINTERSTORE(dst, keys, weights, aggregate) => reply(
+            storage
+                .zset_combine_store(SetOp::Inter, &dst, &keys, &weights, aggregate)
+                .map(|len| RedisValue::Integer(len as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_xadd(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::XADD(key, id, fields, no_mkstream, trim) => reply(
+            storage
+                .stream_add(&key, id, &fields, no_mkstream, trim)
+                .map(|id| match id {
+                    Some(id) => stream_id_bulk_string(id),
+                    None => RedisValue::BulkString(None),
+                }),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_xrange(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::XRANGE(key, start, end, count) => reply(
+            storage
+                .stream_range(&key, start, end, count, false)
+                .map(stream_entries_array),
+        ),
+        RedisCommand::XREVRANGE(key, start, end, count) => reply(
+            storage
+                .stream_range(&key, start, end, count, true)
+                .map(stream_entries_array),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_xlen(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::XLEN(key) => reply(
+            storage
+                .stream_len(&key)
+                .map(|len| RedisValue::Integer(len as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_xtrim(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::XTRIM(key, trim) => reply(
+            storage
+                .stream_trim(&key, trim)
+                .map(|removed| RedisValue::Integer(removed as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_xdel(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::XDEL(key, ids) => reply(
+            storage
+                .stream_delete(&key, &ids)
+                .map(|deleted| RedisValue::Integer(deleted as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_xgroup(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::XGROUPCREATE(key, group, id, mkstream) => reply(
+            storage
+                .stream_group_create(&key, &group, id, mkstream)
+                .map(|_| RedisValue::SimpleString("OK".to_string())),
+        ),
+        RedisCommand::XGROUPSETID(key, group, id) => reply(
+            storage
+                .stream_group_set_id(&key, &group, id)
+                .map(|_| RedisValue::SimpleString("OK".to_string())),
+        ),
+        RedisCommand::XGROUPDESTROY(key, group) => reply(
+            storage
+                .stream_group_destroy(&key, &group)
+                .map(|destroyed| RedisValue::Integer(destroyed as i64)),
+        ),
+        RedisCommand::XGROUPCREATECONSUMER(key, group, consumer) => reply(
+            storage
+                .stream_group_create_consumer(&key, &group, &consumer)
+                .map(|created| RedisValue::Integer(created as i64)),
+        ),
+        RedisCommand::XGROUPDELCONSUMER(key, group, consumer) => reply(
+            storage
+                .stream_group_delete_consumer(&key, &group, &consumer)
+                .map(|pending| RedisValue::Integer(pending as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_xack(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::XACK(key, group, ids) => reply(
+            storage
+                .stream_ack(&key, &group, &ids)
+                .map(|acked| RedisValue::Integer(acked as i64)),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_xpending(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::XPENDING(key, group, None) => {
+            reply(storage.stream_pending_summary(&key, &group).map(|summary| {
+                let (first, last) = match summary.bounds {
+                    Some((first, last)) => {
+                        (stream_id_bulk_string(first), stream_id_bulk_string(last))
+                    }
+                    None => (RedisValue::BulkString(None), RedisValue::BulkString(None)),
+                };
+                let consumers = summary
+                    .consumers
+                    .into_iter()
+                    .map(|(consumer, count)| {
+                        bulk_string_array(vec![consumer, count.to_string().into_bytes()])
+                    })
+                    .collect::<Vec<_>>();
+                RedisValue::Array(Some(vec![
+                    RedisValue::Integer(summary.count as i64),
+                    first,
+                    last,
+                    RedisValue::Array((!consumers.is_empty()).then_some(consumers)),
+                ]))
+            }))
+        }
+        RedisCommand::XPENDING(key, group, Some(range)) => reply(
+            storage
+                .stream_pending_range(&key, &group, &range)
+                .map(|pending| {
+                    RedisValue::Array(Some(
+                        pending
+                            .into_iter()
+                            .map(|entry| {
+                                RedisValue::Array(Some(vec![
+                                    stream_id_bulk_string(entry.id),
+                                    RedisValue::BulkString(Some(entry.consumer)),
+                                    RedisValue::Integer(entry.idle),
+                                    RedisValue::Integer(entry.deliveries as i64),
+                                ]))
+                            })
+                            .collect(),
+                    ))
+                }),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_xclaim(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::XCLAIM(key, group, consumer, min_idle, ids, options) => reply(
+            storage
+                .stream_claim(&key, &group, &consumer, min_idle, &ids, options)
+                .map(|claimed| match options.just_id {
+                    true => stream_ids_array(claimed.into_iter().map(|(id, _)| id)),
+                    false => stream_entries_array(claimed),
+                }),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_xautoclaim(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::XAUTOCLAIM(key, group, consumer, min_idle, start, count, just_id) => reply(
+            storage
+                .stream_auto_claim(&key, &group, &consumer, min_idle, start, count, just_id)
+                .map(|result| {
+                    let claimed = match just_id {
+                        true => stream_ids_array(result.claimed.into_iter().map(|(id, _)| id)),
+                        false => stream_entries_array(result.claimed),
+                    };
+                    RedisValue::Array(Some(vec![
+                        stream_id_bulk_string(result.next),
+                        claimed,
+                        stream_ids_array(result.deleted),
+                    ]))
+                }),
+        ),
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_blocking(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        command @ (RedisCommand::BLPOP(..)
+        | RedisCommand::BRPOP(..)
+        | RedisCommand::BLMOVE(..)
+        | RedisCommand::BZPOPMIN(..)
+        | RedisCommand::BZPOPMAX(..)
+        | RedisCommand::XREAD(..)
+        | RedisCommand::XREADGROUP(..)) => {
+            // Without a client to park, which is always the case for XREAD
+            // and XREADGROUP without BLOCK, having nothing to serve replies
+            // as if the command timed out right away.
+            reply(serve_blocking(&command, storage).unwrap_or(Ok(RedisValue::Array(None))))
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_select(
+    command: RedisCommand,
+    _databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    match command {
+        RedisCommand::SELECT(index) => match db_index(index) {
+            Some(index) => {
+                session.db = index;
+                RedisValue::SimpleString("OK".to_string())
+            }
+            None => RedisValue::Error(ERR_DB_INDEX.to_string()),
+        },
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_move(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    match command {
+        RedisCommand::MOVE(key, target) => {
+            let Some(target) = db_index(target) else {
+                return RedisValue::Error(ERR_DB_INDEX.to_string());
+            };
+            if target == session.db {
+                return RedisValue::Error(
+                    "ERR source and destination objects are the same".to_string(),
+                );
+            }
+            let [storage, target] = databases
+                .get_disjoint_mut([session.db, target])
+                .expect("indices are distinct and in range");
+            RedisValue::Integer(storage.move_to(&key, target) as i64)
+        }
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_swapdb(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    _session: &mut Session,
+) -> RedisValue {
+    match command {
+        RedisCommand::SWAPDB(first, second) => match (db_index(first), db_index(second)) {
+            (Some(first), Some(second)) => {
+                // Connections keep their selected index, so they see the
+                // other database's data from now on.
+                if first != second {
+                    let [first, second] = databases
+                        .get_disjoint_mut([first, second])
+                        .expect("indices are distinct and in range");
+                    first.swap_keys(second);
+                }
+                RedisValue::SimpleString("OK".to_string())
+            }
+            _ => RedisValue::Error(ERR_DB_INDEX.to_string()),
+        },
+        _ => misrouted(),
+    }
+}
+
+pub fn execute_scan(
+    command: RedisCommand,
+    databases: &mut [Storage],
+    session: &mut Session,
+) -> RedisValue {
+    let storage = &mut databases[session.db];
+    match command {
+        RedisCommand::SCAN(cursor, options) => {
+            let (next, keys) = storage.scan(cursor, &options);
+            RedisValue::Array(Some(vec![
+                RedisValue::BulkString(Some(next.to_string().into_bytes())),
+                bulk_string_array(keys),
+            ]))
+        }
+        _ => misrouted(),
+    }
+}
+
+/// Runs a blocking command as it would run without blocking. Returns `None`
+/// when there is nothing to serve yet, in which case the client has to wait.
+pub fn serve_blocking(
+    command: &RedisCommand,
+    storage: &mut Storage,
+) -> Option<Result<RedisValue, RedisError>> {
+    match command {
+        RedisCommand::BLPOP(keys, _) | RedisCommand::BRPOP(keys, _) => {
+            let end = match command {
+                RedisCommand::BLPOP(..) => ListEnd::Left,
+                _ => ListEnd::Right,
+            };
+            for key in keys {
+                match storage.pop(key, end, 1) {
+                    Ok(Some(popped)) => {
+                        let reply = [key.clone()].into_iter().chain(popped).collect();
+                        return Some(Ok(bulk_string_array(reply)));
+                    }
+                    Ok(None) => {}
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+            None
+        }
+        RedisCommand::BLMOVE(src, dst, from, to, _) => storage
+            .list_move(src, dst, *from, *to)
+            .transpose()
+            .map(|moved| moved.map(|element| RedisValue::BulkString(Some(element)))),
+        RedisCommand::BZPOPMIN(keys, _) | RedisCommand::BZPOPMAX(keys, _) => {
+            let max = matches!(command, RedisCommand::BZPOPMAX(..));
+            for key in keys {
+                match storage.zset_pop(key, 1, max) {
+                    Ok(popped) => {
+                        if let Some((member, score)) = popped.into_iter().next() {
+                            return Some(Ok(RedisValue::Array(Some(vec![
+                                RedisValue::BulkString(Some(key.clone())),
+                                RedisValue::BulkString(Some(member)),
+                                RedisValue::Double(score),
+                            ]))));
+                        }
+                    }
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+            None
+        }
+        RedisCommand::XREAD(streams, count, _) => match storage.stream_read(streams, *count) {
+            Ok(read) if read.is_empty() => None,
+            result => Some(result.map(|read| {
+                streams_array(
+                    read.into_iter()
+                        .map(|(key, entries)| {
+                            let entries = entries
+                                .into_iter()
+                                .map(|(id, fields)| (id, Some(fields)))
+                                .collect();
+                            (key, entries)
+                        })
+                        .collect(),
+                )
+            })),
+        },
+        RedisCommand::XREADGROUP(group, consumer, streams, count, no_ack, _) => {
+            match storage.stream_read_group(group, consumer, streams, *count, *no_ack) {
+                Ok(read) if read.is_empty() => None,
+                result => Some(result.map(streams_array)),
+            }
+        }
+        _ => unreachable!("not a blocking command"),
+    }
+}
+
+/// Replies to a command the command table routed to a handler that does not
+/// run it. That is a bug in the table, but not one worth taking the whole
+/// server down for.
+fn misrouted() -> RedisValue {
+    RedisValue::Error("ERR command routed to the wrong handler".to_string())
+}
+
+/// Turns the outcome of a storage operation that can fail with a redis error
+/// into a reply.
+pub fn reply(result: Result<RedisValue, RedisError>) -> RedisValue {
+    match result {
+        Ok(value) => value,
+        Err(e) => RedisValue::Error(e.to_string()),
+    }
+}
+
+/// Checks a database index given by a client.
+fn db_index(index: i64) -> Option<usize> {
+    usize::try_from(index)
+        .ok()
+        .filter(|&index| index < DATABASES)
+}
+
+fn bulk_string_array(strings: Vec<Vec<u8>>) -> RedisValue {
+    RedisValue::Array(Some(
+        strings
+            .into_iter()
+            .map(|string| RedisValue::BulkString(Some(string)))
+            .collect(),
+    ))
+}
+
+fn bulk_string_set(members: Vec<Vec<u8>>) -> RedisValue {
+    RedisValue::Set(
+        members
+            .into_iter()
+            .map(|member| RedisValue::BulkString(Some(member)))
+            .collect(),
+    )
+}
+
+/// Lists sorted set members, each followed by its score if `with_scores`.
+fn scored_array(entries: Vec<(Vec<u8>, f64)>, with_scores: bool) -> RedisValue {
+    RedisValue::Array(Some(
+        entries
+            .into_iter()
+            .flat_map(|(member, score)| {
+                let member = RedisValue::BulkString(Some(member));
+                match with_scores {
+                    true => vec![member, RedisValue::Double(score)],
+                    false => vec![member],
+                }
+            })
+            .collect(),
+    ))
+}
+
+/// Flattens field and value pairs into a single array, the way RESP2 sends
+/// hashes.
+fn flat_pair_array(pairs: Vec<(Vec<u8>, Vec<u8>)>) -> RedisValue {
+    bulk_string_array(
+        pairs
+            .into_iter()
+            .flat_map(|(field, value)| [field, value])
+            .collect(),
+    )
+}
+
+fn stream_id_bulk_string(id: StreamId) -> RedisValue {
+    RedisValue::BulkString(Some(id.to_string().into_bytes()))
+}
+
+/// Replies with a stream entry as its ID followed by its fields and values,
+/// which are a null array for a pending entry deleted from the stream.
+fn stream_entry(id: StreamId, fields: Option<FieldValues>) -> RedisValue {
+    let fields = fields.map_or(RedisValue::Array(None), flat_pair_array);
+    RedisValue::Array(Some(vec![stream_id_bulk_string(id), fields]))
+}
+
+fn stream_entries_array(entries: Vec<StreamEntry>) -> RedisValue {
+    RedisValue::Array(Some(
+        entries
+            .into_iter()
+            .map(|(id, fields)| stream_entry(id, Some(fields)))
+            .collect(),
+    ))
+}
+
+fn stream_ids_array(ids: impl IntoIterator<Item = StreamId>) -> RedisValue {
+    RedisValue::Array(Some(ids.into_iter().map(stream_id_bulk_string).collect()))
+}
+
+/// Replies to XREAD and XREADGROUP with each stream's key and entries, or a
+/// null array if no stream had anything to read.
+fn streams_array(streams: StreamsRead<DeliveredEntry>) -> RedisValue {
+    if streams.is_empty() {
+        return RedisValue::Array(None);
+    }
+    RedisValue::Array(Some(
+        streams
+            .into_iter()
+            .map(|(key, entries)| {
+                let entries = entries
+                    .into_iter()
+                    .map(|(id, fields)| stream_entry(id, fields))
+                    .collect();
+                RedisValue::Array(Some(vec![
+                    RedisValue::BulkString(Some(key)),
+                    RedisValue::Array(Some(entries)),
+                ]))
+            })
+            .collect(),
+    ))
+}
+
+fn push_command(
+    storage: &mut Storage,
+    key: &[u8],
+    end: ListEnd,
+    elements: &[Vec<u8>],
+) -> RedisValue {
+    reply(
+        storage
+            .push(key, end, elements)
+            .map(|len| RedisValue::Integer(len as i64)),
+    )
+}
+
+/// Replies with the popped element, or with an array of them when a count
+/// was given.
+fn pop_command(
+    storage: &mut Storage,
+    key: &[u8],
+    end: ListEnd,
+    count: Option<usize>,
+) -> RedisValue {
+    let popped = storage.pop(key, end, count.unwrap_or(1));
+    reply(popped.map(|popped| match (popped, count) {
+        (Some(popped), Some(_)) => bulk_string_array(popped),
+        (Some(popped), None) => RedisValue::BulkString(popped.into_iter().next()),
+        (None, Some(_)) => RedisValue::Array(None),
+        (None, None) => RedisValue::BulkString(None),
+    }))
+}
+
+/// Applies an EXPIRE family command once its argument has been turned into an
+/// absolute unix time in milliseconds, `None` meaning that computing it
+/// overflowed.
+fn expire_command(
+    storage: &mut Storage,
+    key: &[u8],
+    when: Option<i64>,
+    condition: ExpireCondition,
+    command: &str,
+) -> RedisValue {
+    match when {
+        Some(when) => RedisValue::Integer(storage.expire_at(key, when, condition) as i64),
+        None => RedisValue::Error(format!("ERR invalid expire time in '{}' command", command)),
+    }
+}
+
+fn ttl_command(storage: &mut Storage, key: &[u8], unit: fn(Duration) -> i64) -> RedisValue {
+    let reply = match storage.get(key) {
+        None => -2,
+        Some(data) => data.ttl().map_or(-1, unit),
+    };
+    RedisValue::Integer(reply)
+}
+
+fn expire_time_command(storage: &mut Storage, key: &[u8], unit: fn(i64) -> i64) -> RedisValue {
+    let reply = match storage.get(key) {
+        None => -2,
+        Some(data) => data.expire_time_millis().map_or(-1, unit),
+    };
+    RedisValue::Integer(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_misrouted_command() {
+        let mut databases = vec![Storage::new()];
+        let mut session = Session::default();
+        let ping = RedisCommand::PING(RedisValue::SimpleString("PONG".to_string()));
+        assert_eq!(execute_get(ping, &mut databases, &mut session), misrouted());
+    }
+}
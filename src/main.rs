@@ -1,64 +1,100 @@
+pub mod blocking;
+pub mod command;
+pub mod error;
+pub mod execute;
+pub mod poller;
 pub mod resp;
 pub mod storage;
 
-use resp::{RedisCommand, RedisValue};
+use blocking::{BlockedClient, BlockingRegistry};
+use error::RedisError;
+use execute::{reply, serve_blocking, Session};
+use poller::{Event, EventInterest, Poller, RegistrationAction};
+use resp::{RedisCommand, RedisValue, RequestParser};
 use std::collections::HashMap;
 use std::io::{Read, Write};
 use std::net::{TcpListener, TcpStream};
-use std::os::unix::io::{AsRawFd, RawFd};
-use storage::Storage;
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+use storage::{Storage, StreamId};
 
-#[allow(unused_macros)]
-macro_rules! syscall {
-    ($fn: ident ( $($arg: expr),* $(,)* ) ) => {{
-        let res = unsafe { libc::$fn($($arg, )*) };
-        if res == -1 {
-            Err(std::io::Error::last_os_error())
-        } else {
-            Ok(res)
-        }
-    }};
-}
+// The active expiry cycle runs ten times a second and may use up to a quarter
+// of each period, the same defaults redis uses.
+const ACTIVE_EXPIRE_PERIOD: Duration = Duration::from_millis(100);
+const ACTIVE_EXPIRE_BUDGET: Duration = Duration::from_millis(25);
+// Time given to moving keys between tables of a resizing dict on every cycle,
+// redis' activerehashing budget.
+const ACTIVE_REHASH_BUDGET: Duration = Duration::from_millis(1);
+// The number of logical databases, numbered from zero, as in redis' default
+// configuration.
+const DATABASES: usize = 16;
+const ERR_DB_INDEX: &str = "ERR DB index is out of range";
 
 struct RequestContext {
     stream: TcpStream,
+    // Bytes received but not yet parsed into a complete frame. A frame split
+    // across several reads stays here until the rest of it arrives.
+    read_buffer: Vec<u8>,
+    // Where parsing the request at the start of `read_buffer` got to.
+    parser: RequestParser,
     write_buffer: Vec<u8>,
+    // What commands sent on this connection have changed about it.
+    session: Session,
+    // Set once the client has shut down its side of the connection, which is
+    // closed as soon as the replies still queued have been sent.
+    peer_closed: bool,
+    // Set once the client has sent something that is not RESP, after which,
+    // like redis, no more of its input is run and the connection is closed as
+    // soon as the protocol error has been sent.
+    close_after_reply: bool,
 }
 
 impl RequestContext {
     fn new(stream: TcpStream) -> RequestContext {
         RequestContext {
             stream,
+            read_buffer: Vec::with_capacity(1024),
+            parser: RequestParser::default(),
             write_buffer: Vec::with_capacity(1024),
+            session: Session::default(),
+            peer_closed: false,
+            close_after_reply: false,
         }
     }
 
-    fn handle_read(&mut self) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
-        let mut buffer = [0; 1024];
-        let mut total_data = Vec::with_capacity(1024);
+    /// Appends everything currently readable to `read_buffer`. Sets
+    /// `peer_closed` once the client has shut down its side, which leaves what
+    /// it sent before that in the buffer to be processed.
+    fn handle_read(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut buffer = [0; 16 * 1024];
         loop {
             match self.stream.read(&mut buffer) {
                 Ok(0) => {
-                    // client discoonnected
-                    return Ok(None);
+                    self.peer_closed = true;
+                    break;
                 }
                 Ok(n) => {
-                    total_data.extend_from_slice(&buffer[..n]);
-                    if n < buffer.len() {
-                        break;
-                    }
+                    self.read_buffer.extend_from_slice(&buffer[..n]);
                 }
                 Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
                     break;
                 }
+                Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => {}
                 Err(e) => return Err(Box::new(e)),
             }
         }
-        Ok(Some(total_data))
+        Ok(())
+    }
+
+    /// Whether the connection is to be closed once the replies still queued
+    /// have been sent.
+    fn closing(&self) -> bool {
+        self.peer_closed || self.close_after_reply
     }
 
-    fn dispatch_write(&mut self, response: &[u8]) {
-        self.write_buffer.extend_from_slice(response);
+    /// Queues a reply encoded in the connection's protocol.
+    fn dispatch_reply(&mut self, value: &RedisValue) {
+        value.encode(self.session.protocol, &mut self.write_buffer);
     }
 
     fn write_to_socket(&mut self) -> std::io::Result<()> {
@@ -80,156 +116,153 @@ impl RequestContext {
     }
 }
 
-fn kqueue() -> std::io::Result<RawFd> {
-    let fd = syscall!(kqueue())?;
-    if let Ok(flags) = syscall!(fcntl(fd, libc::F_GETFD)) {
-        syscall!(fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC))?;
+/// Runs every complete command in the connection's read buffer in order and
+/// queues the replies on the connection, so that pipelined clients get one
+/// reply per command. A trailing partial frame is left in the buffer, and so
+/// is everything after a command the client blocks on, until it is served.
+/// After a protocol error nothing more the client sends is run.
+fn process_request(
+    request_context: &mut RequestContext,
+    databases: &mut [Storage],
+    registry: &mut BlockingRegistry,
+) {
+    let fd = request_context.stream.as_raw_fd();
+    if registry.is_blocked(fd) {
+        return;
     }
-    Ok(fd)
-}
-
-enum KqueueEventInterest {
-    Read,
-    Write,
-}
-
-enum KqueueRegistrationAction {
-    Register,
-    Unregister,
-}
-
-fn update_kqueue(
-    kq: i32,
-    fd: i32,
-    interest: KqueueEventInterest,
-    action: KqueueRegistrationAction,
-) -> std::io::Result<()> {
-    let (filter, flags) = match interest {
-        KqueueEventInterest::Read => (libc::EVFILT_READ, libc::EV_ADD),
-        KqueueEventInterest::Write => (libc::EVFILT_WRITE, libc::EV_ADD),
-    };
-    let flags = match action {
-        KqueueRegistrationAction::Register => flags,
-        KqueueRegistrationAction::Unregister => libc::EV_DELETE,
-    };
-    let mut event = libc::kevent {
-        ident: fd as usize,
-        filter,
-        flags,
-        fflags: 0,
-        data: 0,
-        udata: std::ptr::null_mut(),
-    };
-    syscall!(kevent(
-        kq,
-        &mut event,
-        1,
-        std::ptr::null_mut(),
-        0,
-        std::ptr::null()
-    ))?;
-    Ok(())
-}
-
-fn get_kqueue_events(kq: i32) -> std::io::Result<Vec<libc::kevent>> {
-    let mut events: Vec<libc::kevent> = vec![
-        libc::kevent {
-            ident: 0,
-            filter: 0,
-            flags: 0,
-            fflags: 0,
-            data: 0,
-            udata: std::ptr::null_mut(),
+    if request_context.close_after_reply {
+        request_context.read_buffer.clear();
+        return;
+    }
+    let mut offset = 0;
+    while offset < request_context.read_buffer.len() {
+        let parsed = request_context
+            .parser
+            .parse(&request_context.read_buffer[offset..]);
+        let (request, consumed) = match parsed {
+            Ok(Some(frame)) => frame,
+            Ok(None) => break,
+            Err(e) => {
+                // There is no way to find the start of the next frame after a
+                // protocol error, so the rest of the input, including any of
+                // the bad frame still to arrive, is discarded along with the
+                // connection.
+                if let RedisError::Protocol { offset: at, .. } = e {
+                    eprintln!("Protocol error at byte {}: {}", offset + at, e);
+                }
+                request_context.dispatch_reply(&RedisValue::Error(e.to_string()));
+                request_context.close_after_reply = true;
+                offset = request_context.read_buffer.len();
+                break;
+            }
         };
-        512
-    ];
-    let n = syscall!(kevent(
-        kq,
-        std::ptr::null(),
-        0,
-        events.as_mut_ptr(),
-        events.len() as i32,
-        std::ptr::null()
-    ))?;
-    events.truncate(n as usize);
-    Ok(events)
+        offset += consumed;
+        if request.is_empty() {
+            continue;
+        }
+        let (spec, command) = match resp::extract_commands(request) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                request_context.dispatch_reply(&RedisValue::Error(e.to_string()));
+                continue;
+            }
+        };
+        let Some((keys, timeout)) = blocking_keys(&command) else {
+            let response = (spec.execute)(command, databases, &mut request_context.session);
+            request_context.dispatch_reply(&response);
+            continue;
+        };
+        let db = request_context.session.db;
+        if let Some(result) = serve_blocking(&command, &mut databases[db]) {
+            request_context.dispatch_reply(&reply(result));
+            continue;
+        }
+        let client = BlockedClient {
+            command: pin_last_ids(command, &mut databases[db]),
+            db,
+            keys,
+            deadline: (!timeout.is_zero()).then(|| Instant::now() + timeout),
+        };
+        registry.block(fd, client, databases);
+        break;
+    }
+    request_context.read_buffer.drain(..offset);
 }
 
-fn handle_request(request: Vec<u8>, storage: &mut Storage) -> Vec<u8> {
-    let extracted_command = match resp::extract_commands(&request) {
-        Ok(cmd) => cmd,
-        Err(e) => {
-            eprintln!("Failed to parse request: {}", e);
-            return b"-ERR failed to parse request\r\n".to_vec();
+/// The keys a blocking command waits on and how long it may wait, zero
+/// meaning no limit, or `None` for commands that never block.
+fn blocking_keys(command: &RedisCommand) -> Option<(Vec<Vec<u8>>, Duration)> {
+    match command {
+        RedisCommand::BLPOP(keys, timeout)
+        | RedisCommand::BRPOP(keys, timeout)
+        | RedisCommand::BZPOPMIN(keys, timeout)
+        | RedisCommand::BZPOPMAX(keys, timeout) => Some((keys.clone(), *timeout)),
+        RedisCommand::BLMOVE(src, _, _, _, timeout) => Some((vec![src.clone()], *timeout)),
+        RedisCommand::XREAD(streams, _, Some(timeout))
+        | RedisCommand::XREADGROUP(_, _, streams, _, _, Some(timeout)) => {
+            let keys = streams.iter().map(|(key, _)| key.clone()).collect();
+            Some((keys, *timeout))
         }
-    };
-    const OK_RESPONSE: &[u8] = "+OK\r\n".as_bytes();
-    const NULL_BULK_STRING: &[u8] = "$-1\r\n".as_bytes();
-    match extracted_command {
-        RedisCommand::PING(message) | RedisCommand::ECHO(message) => {
-            message.to_resp_string().as_bytes().to_vec()
-        }
-        RedisCommand::COMMAND => OK_RESPONSE.to_vec(),
-        RedisCommand::CONFIG => OK_RESPONSE.to_vec(),
-        RedisCommand::GET(key) => match key {
-            RedisValue::BulkString(Some(k)) | RedisValue::SimpleString(k) => match storage.get(k) {
-                Some(data) => data.value().to_resp_string().as_bytes().to_vec(),
-                None => NULL_BULK_STRING.to_vec(),
-            },
-            _ => RedisValue::Error("ERR invalid".to_string())
-                .to_resp_string()
-                .as_bytes()
-                .to_vec(),
-        },
-        RedisCommand::SET(key, value, expiry) => match key {
-            RedisValue::BulkString(Some(k)) | RedisValue::SimpleString(k) => {
-                storage.set(k, value, expiry);
-                OK_RESPONSE.to_vec()
-            }
-            _ => RedisValue::Error("ERR invalid".to_string())
-                .to_resp_string()
-                .as_bytes()
-                .to_vec(),
-        },
+        _ => None,
     }
 }
 
-fn main() {
-    let listener = TcpListener::bind("127.0.0.1:6379").expect("Failed to bind to address");
-    listener
-        .set_nonblocking(true)
-        .expect("Failed to set non-blocking mode on listener");
+/// Replaces the `$` IDs of an XREAD that is about to block with the last IDs
+/// of the streams as they are now, so that the client waits for entries added
+/// after it blocked rather than after whichever one comes last.
+fn pin_last_ids(command: RedisCommand, storage: &mut Storage) -> RedisCommand {
+    let RedisCommand::XREAD(streams, count, block) = command else {
+        return command;
+    };
+    let streams = streams
+        .into_iter()
+        .map(|(key, id)| {
+            let id = id.or_else(|| {
+                let last = storage.stream_last_id(&key).ok().flatten();
+                Some(last.unwrap_or(StreamId::MIN))
+            });
+            (key, id)
+        })
+        .collect();
+    RedisCommand::XREAD(streams, count, block)
+}
+
+fn run_event_loop(listener: TcpListener, mut poller: impl Poller) {
     let listener_fd = listener.as_raw_fd();
-    let kq = kqueue().expect("Failed to create kqueue");
     let mut streams_map = HashMap::new();
-    update_kqueue(
-        kq,
-        listener_fd,
-        KqueueEventInterest::Read,
-        KqueueRegistrationAction::Register,
-    )
-    .expect("Failed to register listener with kqueue");
-    let mut storage = Storage::new();
+    poller
+        .update(
+            listener_fd,
+            EventInterest::Read,
+            RegistrationAction::Register,
+        )
+        .expect("Failed to register listener with poller");
+    let mut databases: Vec<Storage> = (0..DATABASES).map(|_| Storage::new()).collect();
+    let mut registry = BlockingRegistry::default();
+    let mut events: Vec<Event> = Vec::with_capacity(512);
+    let mut last_expire_cycle = Instant::now();
     loop {
-        println!("Waiting for events");
-        println!("Requests in flight {}", streams_map.len());
-        let events = get_kqueue_events(kq).expect("Failed to get kqueue events");
-        println!("Got {} events", events.len());
-        for event in events {
-            if event.ident == listener_fd as usize {
+        // Wake up at least once per expiry period, even when no client is
+        // active, so that expired keys keep getting evicted, and in time for
+        // the first blocked client to time out.
+        let mut timeout = ACTIVE_EXPIRE_PERIOD.saturating_sub(last_expire_cycle.elapsed());
+        if let Some(deadline) = registry.next_deadline() {
+            timeout = timeout.min(deadline.saturating_duration_since(Instant::now()));
+        }
+        poller
+            .wait(&mut events, Some(timeout))
+            .expect("Failed to get poller events");
+        for event in &events {
+            if event.fd == listener_fd {
                 match listener.accept() {
                     Ok((stream, _)) => {
                         stream
                             .set_nonblocking(true)
                             .expect("Failed to set non-blocking mode on stream");
                         let fd = stream.as_raw_fd();
-                        update_kqueue(
-                            kq,
-                            fd,
-                            KqueueEventInterest::Read,
-                            KqueueRegistrationAction::Register,
-                        )
-                        .expect("Failed to register stream with kqueue");
+                        poller
+                            .update(fd, EventInterest::Read, RegistrationAction::Register)
+                            .expect("Failed to register stream with poller");
                         streams_map.insert(fd, RequestContext::new(stream));
                     }
                     Err(e) => {
@@ -238,58 +271,249 @@ fn main() {
                         }
                     }
                 }
-            } else {
-                let fd = event.ident as i32;
-                match streams_map.get_mut(&fd) {
-                    Some(request_context) => {
-                        match event.filter {
-                            libc::EVFILT_READ => match request_context.handle_read() {
-                                Ok(Some(request)) => {
-                                    let response = handle_request(request, &mut storage);
-                                    request_context.dispatch_write(&response);
-                                    update_kqueue(
-                                        kq,
-                                        fd,
-                                        KqueueEventInterest::Write,
-                                        KqueueRegistrationAction::Register,
-                                    )
-                                    .expect("Failed to register stream with kqueue");
-                                }
-                                Ok(None) => {
-                                    println!("Client disconnected");
-                                    streams_map.remove(&fd);
-                                }
-                                Err(e) => {
-                                    eprintln!("Failed to read from stream: {}", e);
-                                }
-                            },
-                            libc::EVFILT_WRITE => match request_context.write_to_socket() {
-                                Ok(()) => {
-                                    println!("Response sent");
-                                    update_kqueue(kq, fd, KqueueEventInterest::Write, KqueueRegistrationAction::Unregister).unwrap_or_else(|e| {
-                                    eprintln!("Failed to unregister write event for file descriptor: {}", e)
-                                });
-                                }
-                                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                                    eprintln!("Write would block, which should not happen!!");
-                                }
-                                Err(e) => {
-                                    eprintln!("Failed to write to stream: {}", e);
-                                }
-                            },
-                            _ => {
-                                eprintln!(
-                                    "Got unexpected event for file descriptor: {} {}",
-                                    fd, event.filter
+                continue;
+            }
+            let fd = event.fd;
+            let request_context = match streams_map.get_mut(&fd) {
+                Some(request_context) => request_context,
+                None => {
+                    eprintln!("Got event for unknown file descriptor: {}", fd);
+                    continue;
+                }
+            };
+            if event.readable {
+                match request_context.handle_read() {
+                    Ok(()) => {
+                        process_request(request_context, &mut databases, &mut registry);
+                        if request_context.closing() {
+                            // The replies to what the client sent before
+                            // shutting down its side, or to the protocol error,
+                            // are still owed. Whatever cannot be sent right
+                            // away is sent once the socket is writable, with
+                            // nothing more to read meanwhile.
+                            if request_context.write_to_socket().is_err()
+                                || request_context.write_buffer.is_empty()
+                            {
+                                close_connection(
+                                    &mut poller,
+                                    &mut streams_map,
+                                    &mut registry,
+                                    &mut databases,
+                                    fd,
                                 );
+                                continue;
                             }
+                            poller
+                                .update(fd, EventInterest::Read, RegistrationAction::Unregister)
+                                .expect("Failed to unregister stream with poller");
+                        }
+                        if !request_context.write_buffer.is_empty() {
+                            poller
+                                .update(fd, EventInterest::Write, RegistrationAction::Register)
+                                .expect("Failed to register stream with poller");
                         }
                     }
-                    None => {
-                        eprintln!("Got event for unknown file descriptor: {}", fd);
+                    Err(e) => {
+                        eprintln!("Failed to read from stream: {}", e);
+                        close_connection(
+                            &mut poller,
+                            &mut streams_map,
+                            &mut registry,
+                            &mut databases,
+                            fd,
+                        );
+                        continue;
                     }
                 }
             }
+            if event.writable {
+                match request_context.write_to_socket() {
+                    Ok(()) => {
+                        if request_context.write_buffer.is_empty() && request_context.closing() {
+                            close_connection(
+                                &mut poller,
+                                &mut streams_map,
+                                &mut registry,
+                                &mut databases,
+                                fd,
+                            );
+                        } else if request_context.write_buffer.is_empty() {
+                            poller
+                                .update(fd, EventInterest::Write, RegistrationAction::Unregister)
+                                .unwrap_or_else(|e| {
+                                    eprintln!(
+                                        "Failed to unregister write event for file descriptor: {}",
+                                        e
+                                    )
+                                });
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to write to stream: {}", e);
+                        close_connection(
+                            &mut poller,
+                            &mut streams_map,
+                            &mut registry,
+                            &mut databases,
+                            fd,
+                        );
+                    }
+                }
+            }
+        }
+        handle_blocked_clients(&mut poller, &mut streams_map, &mut registry, &mut databases);
+        if last_expire_cycle.elapsed() >= ACTIVE_EXPIRE_PERIOD {
+            // The budgets are shared by all databases. Each one still gets
+            // at least a sample of keys checked once they are used up.
+            let start = Instant::now();
+            for storage in &mut databases {
+                storage.active_expire_cycle(ACTIVE_EXPIRE_BUDGET.saturating_sub(start.elapsed()));
+            }
+            let start = Instant::now();
+            for storage in &mut databases {
+                storage.rehash_for(ACTIVE_REHASH_BUDGET.saturating_sub(start.elapsed()));
+            }
+            last_expire_cycle = Instant::now();
         }
     }
 }
+
+/// Replies to blocked clients whose timeout has passed, then serves those
+/// blocked on keys that writes have made ready. Serving a client can write to
+/// other keys, like BLMOVE's destination, or run commands it had pipelined
+/// behind the blocking one, so this goes on until no key is left ready.
+fn handle_blocked_clients(
+    poller: &mut impl Poller,
+    streams_map: &mut HashMap<i32, RequestContext>,
+    registry: &mut BlockingRegistry,
+    databases: &mut [Storage],
+) {
+    for fd in registry.timed_out(Instant::now()) {
+        registry.unblock(fd, databases);
+        resume_client(
+            poller,
+            streams_map,
+            registry,
+            databases,
+            fd,
+            &RedisValue::Array(None),
+        );
+    }
+    loop {
+        let ready: Vec<(usize, Vec<u8>)> = databases
+            .iter_mut()
+            .enumerate()
+            .flat_map(|(db, storage)| {
+                storage
+                    .take_ready_keys()
+                    .into_iter()
+                    .map(move |key| (db, key))
+            })
+            .collect();
+        if ready.is_empty() {
+            break;
+        }
+        for (db, key) in ready {
+            for fd in registry.waiting_on(db, &key) {
+                let Some(client) = registry.client(fd) else {
+                    continue;
+                };
+                // A key that now holds another type does not serve the
+                // client, which keeps waiting, as in redis.
+                let Some(Ok(value)) = serve_blocking(&client.command, &mut databases[db]) else {
+                    continue;
+                };
+                registry.unblock(fd, databases);
+                resume_client(poller, streams_map, registry, databases, fd, &value);
+            }
+        }
+    }
+}
+
+/// Sends the reply to the command a client was blocked on, and runs the
+/// commands it pipelined behind it.
+fn resume_client(
+    poller: &mut impl Poller,
+    streams_map: &mut HashMap<i32, RequestContext>,
+    registry: &mut BlockingRegistry,
+    databases: &mut [Storage],
+    fd: i32,
+    response: &RedisValue,
+) {
+    let Some(request_context) = streams_map.get_mut(&fd) else {
+        return;
+    };
+    request_context.dispatch_reply(response);
+    process_request(request_context, databases, registry);
+    poller
+        .update(fd, EventInterest::Write, RegistrationAction::Register)
+        .expect("Failed to register stream with poller");
+}
+
+fn close_connection(
+    poller: &mut impl Poller,
+    streams_map: &mut HashMap<i32, RequestContext>,
+    registry: &mut BlockingRegistry,
+    databases: &mut [Storage],
+    fd: i32,
+) {
+    for interest in [EventInterest::Read, EventInterest::Write] {
+        poller
+            .update(fd, interest, RegistrationAction::Unregister)
+            .unwrap_or_else(|e| eprintln!("Failed to unregister file descriptor {}: {}", fd, e));
+    }
+    // A client that disconnects while blocked must not be served later.
+    registry.unblock(fd, databases);
+    streams_map.remove(&fd);
+}
+
+fn main() {
+    let listener = TcpListener::bind("127.0.0.1:6379").expect("Failed to bind to address");
+    listener
+        .set_nonblocking(true)
+        .expect("Failed to set non-blocking mode on listener");
+    #[cfg(target_os = "linux")]
+    let poller = poller::Epoll::new().expect("Failed to create epoll instance");
+    #[cfg(not(target_os = "linux"))]
+    let poller = poller::Kqueue::new().expect("Failed to create kqueue");
+    run_event_loop(listener, poller);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request_context() -> (RequestContext, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        (RequestContext::new(stream), client)
+    }
+
+    #[test]
+    fn test_protocol_error_closes_connection() {
+        let (mut request_context, _client) = request_context();
+        let mut databases: Vec<Storage> = (0..DATABASES).map(|_| Storage::new()).collect();
+        let mut registry = BlockingRegistry::default();
+        // A bulk string longer than announced. The rest of its frame, which
+        // would otherwise be run as a SET of its own, arrives with the next
+        // read.
+        request_context
+            .read_buffer
+            .extend_from_slice(b"*1\r\n$4\r\nPING\r\n*3\r\n$3\r\nSET\r\n$1\r\nkey");
+        process_request(&mut request_context, &mut databases, &mut registry);
+        assert_eq!(
+            request_context.write_buffer,
+            b"+PONG\r\n-ERR Protocol error: expected CRLF after bulk string\r\n"
+        );
+        assert!(request_context.closing());
+        let replies = request_context.write_buffer.clone();
+        request_context
+            .read_buffer
+            .extend_from_slice(b"\r\n*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n");
+        process_request(&mut request_context, &mut databases, &mut registry);
+        assert!(request_context.read_buffer.is_empty());
+        assert_eq!(request_context.write_buffer, replies);
+        assert!(databases[0].get(b"k").is_none());
+    }
+}
@@ -0,0 +1,87 @@
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+
+#[allow(unused_macros)]
+macro_rules! syscall {
+    ($fn: ident ( $($arg: expr),* $(,)* ) ) => {{
+        let res = unsafe { libc::$fn($($arg, )*) };
+        if res == -1 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(res)
+        }
+    }};
+}
+
+#[cfg(target_os = "linux")]
+mod epoll;
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly"
+))]
+mod kqueue;
+
+#[cfg(target_os = "linux")]
+pub use epoll::Epoll;
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly"
+))]
+pub use kqueue::Kqueue;
+
+/// The kind of readiness a file descriptor is registered for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventInterest {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationAction {
+    Register,
+    Unregister,
+}
+
+/// A readiness notification for a single file descriptor. Backends that report
+/// read and write readiness separately (kqueue) produce one event per filter.
+#[derive(Debug, Clone, Copy)]
+pub struct Event {
+    pub fd: RawFd,
+    pub readable: bool,
+    pub writable: bool,
+}
+
+pub trait Poller {
+    /// Starts watching `fd` for `interest`. Registering an interest that is
+    /// already registered is a no-op.
+    fn register(&mut self, fd: RawFd, interest: EventInterest) -> std::io::Result<()>;
+
+    /// Stops watching `fd` for `interest`. Unregistering an interest that is
+    /// not registered is a no-op.
+    fn unregister(&mut self, fd: RawFd, interest: EventInterest) -> std::io::Result<()>;
+
+    /// Blocks until at least one registered file descriptor is ready or
+    /// `timeout` elapses, and replaces the contents of `events` with the ready
+    /// set. A `timeout` of `None` waits indefinitely.
+    fn wait(&mut self, events: &mut Vec<Event>, timeout: Option<Duration>) -> std::io::Result<()>;
+
+    fn update(
+        &mut self,
+        fd: RawFd,
+        interest: EventInterest,
+        action: RegistrationAction,
+    ) -> std::io::Result<()> {
+        match action {
+            RegistrationAction::Register => self.register(fd, interest),
+            RegistrationAction::Unregister => self.unregister(fd, interest),
+        }
+    }
+}
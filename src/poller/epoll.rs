@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+
+use super::{Event, EventInterest, Poller};
+
+const MAX_EVENTS: usize = 512;
+
+pub struct Epoll {
+    epfd: RawFd,
+    // epoll keeps a single mask per descriptor, so the read and write
+    // interests have to be merged before every `epoll_ctl` call.
+    interests: HashMap<RawFd, u32>,
+    events: Vec<libc::epoll_event>,
+}
+
+impl Epoll {
+    pub fn new() -> std::io::Result<Epoll> {
+        let epfd = syscall!(epoll_create1(libc::EPOLL_CLOEXEC))?;
+        Ok(Epoll {
+            epfd,
+            interests: HashMap::new(),
+            events: vec![libc::epoll_event { events: 0, u64: 0 }; MAX_EVENTS],
+        })
+    }
+
+    fn ctl(&self, op: i32, fd: RawFd, mask: u32) -> std::io::Result<()> {
+        let mut event = libc::epoll_event {
+            events: mask,
+            u64: fd as u64,
+        };
+        syscall!(epoll_ctl(self.epfd, op, fd, &mut event))?;
+        Ok(())
+    }
+}
+
+fn interest_mask(interest: EventInterest) -> u32 {
+    match interest {
+        EventInterest::Read => libc::EPOLLIN as u32,
+        EventInterest::Write => libc::EPOLLOUT as u32,
+    }
+}
+
+impl Poller for Epoll {
+    fn register(&mut self, fd: RawFd, interest: EventInterest) -> std::io::Result<()> {
+        let current = self.interests.get(&fd).copied().unwrap_or(0);
+        let mask = current | interest_mask(interest);
+        if mask == current {
+            return Ok(());
+        }
+        let op = if current == 0 {
+            libc::EPOLL_CTL_ADD
+        } else {
+            libc::EPOLL_CTL_MOD
+        };
+        self.ctl(op, fd, mask)?;
+        self.interests.insert(fd, mask);
+        Ok(())
+    }
+
+    fn unregister(&mut self, fd: RawFd, interest: EventInterest) -> std::io::Result<()> {
+        let current = match self.interests.get(&fd) {
+            Some(&mask) => mask,
+            None => return Ok(()),
+        };
+        let mask = current & !interest_mask(interest);
+        if mask == current {
+            return Ok(());
+        }
+        if mask == 0 {
+            self.interests.remove(&fd);
+            self.ctl(libc::EPOLL_CTL_DEL, fd, 0)
+        } else {
+            self.interests.insert(fd, mask);
+            self.ctl(libc::EPOLL_CTL_MOD, fd, mask)
+        }
+    }
+
+    fn wait(&mut self, events: &mut Vec<Event>, timeout: Option<Duration>) -> std::io::Result<()> {
+        events.clear();
+        // Round up so that a sub-millisecond timeout does not turn into a busy
+        // loop of zero-timeout waits.
+        let timeout_ms = match timeout {
+            Some(timeout) => timeout.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as i32,
+            None => -1,
+        };
+        let n = match syscall!(epoll_wait(
+            self.epfd,
+            self.events.as_mut_ptr(),
+            self.events.len() as i32,
+            timeout_ms
+        )) {
+            Ok(n) => n as usize,
+            Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        for event in &self.events[..n] {
+            let flags = event.events;
+            let readable =
+                (libc::EPOLLIN | libc::EPOLLHUP | libc::EPOLLERR | libc::EPOLLRDHUP) as u32;
+            events.push(Event {
+                fd: event.u64 as RawFd,
+                readable: flags & readable != 0,
+                writable: flags & libc::EPOLLOUT as u32 != 0,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Epoll {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.epfd);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+
+use super::{Event, EventInterest, Poller};
+
+const MAX_EVENTS: usize = 512;
+
+pub struct Kqueue {
+    kq: RawFd,
+    events: Vec<libc::kevent>,
+}
+
+impl Kqueue {
+    pub fn new() -> std::io::Result<Kqueue> {
+        let kq = syscall!(kqueue())?;
+        if let Ok(flags) = syscall!(fcntl(kq, libc::F_GETFD)) {
+            syscall!(fcntl(kq, libc::F_SETFD, flags | libc::FD_CLOEXEC))?;
+        }
+        Ok(Kqueue {
+            kq,
+            events: vec![empty_kevent(); MAX_EVENTS],
+        })
+    }
+
+    fn change(&self, fd: RawFd, interest: EventInterest, flags: u16) -> std::io::Result<()> {
+        let filter = match interest {
+            EventInterest::Read => libc::EVFILT_READ,
+            EventInterest::Write => libc::EVFILT_WRITE,
+        };
+        let mut event = empty_kevent();
+        event.ident = fd as _;
+        event.filter = filter;
+        event.flags = flags as _;
+        syscall!(kevent(
+            self.kq,
+            &event,
+            1,
+            std::ptr::null_mut(),
+            0,
+            std::ptr::null()
+        ))?;
+        Ok(())
+    }
+}
+
+fn empty_kevent() -> libc::kevent {
+    // The layout of `kevent` differs between the BSDs, zeroing keeps the
+    // construction portable.
+    unsafe { std::mem::zeroed() }
+}
+
+impl Poller for Kqueue {
+    fn register(&mut self, fd: RawFd, interest: EventInterest) -> std::io::Result<()> {
+        self.change(fd, interest, libc::EV_ADD as u16)
+    }
+
+    fn unregister(&mut self, fd: RawFd, interest: EventInterest) -> std::io::Result<()> {
+        match self.change(fd, interest, libc::EV_DELETE as u16) {
+            Err(ref e) if e.raw_os_error() == Some(libc::ENOENT) => Ok(()),
+            result => result,
+        }
+    }
+
+    fn wait(&mut self, events: &mut Vec<Event>, timeout: Option<Duration>) -> std::io::Result<()> {
+        events.clear();
+        let timespec = timeout.map(|timeout| libc::timespec {
+            tv_sec: timeout.as_secs() as _,
+            tv_nsec: timeout.subsec_nanos() as _,
+        });
+        let timespec_ptr = match &timespec {
+            Some(timespec) => timespec as *const libc::timespec,
+            None => std::ptr::null(),
+        };
+        let n = match syscall!(kevent(
+            self.kq,
+            std::ptr::null(),
+            0,
+            self.events.as_mut_ptr(),
+            self.events.len() as _,
+            timespec_ptr
+        )) {
+            Ok(n) => n as usize,
+            Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        for event in &self.events[..n] {
+            events.push(Event {
+                fd: event.ident as RawFd,
+                readable: event.filter == libc::EVFILT_READ,
+                writable: event.filter == libc::EVFILT_WRITE,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Kqueue {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.kq);
+        }
+    }
+}
@@ -1,360 +1,2336 @@
-use anyhow::anyhow;
+// A decoder for every RESP2 and RESP3 type. Clients only ever send arrays of
+// bulk strings or inline commands, which `RequestParser` handles, so only the
+// tests need it.
+#[cfg(test)]
+mod decode;
+
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crate::command::{self, CommandFilter, CommandSpec};
+use crate::error::RedisError;
+use crate::storage::{
+    unix_time_millis, Aggregate, BitOp, BitUnit, BitfieldOp, BitfieldType, ClaimOptions,
+    ExpireCondition, LexBound, ListEnd, ListPositionOptions, Overflow, PendingRange, ScanOptions,
+    ScoreBound, SetCondition, SetExpiry, SetOptions, StreamAddId, StreamId, StreamTrim,
+    TrimStrategy, ZAddOptions, ZRangeBy, ZRangeOptions,
+};
+
+/// The protocol a connection speaks, RESP2 until the client switches with
+/// HELLO.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
+}
 
 #[derive(Debug, PartialEq)]
 pub enum RedisValue {
     SimpleString(String),
     Error(String),
     Integer(i64),
-    BulkString(Option<String>),
+    BulkString(Option<Vec<u8>>),
     Array(Option<Vec<RedisValue>>),
     Boolean(bool),
     Null,
+    Map(Vec<(RedisValue, RedisValue)>),
+    Set(Vec<RedisValue>),
+    Double(f64),
+    /// An integer too large for `Integer`, in decimal digits.
+    BigNumber(String),
+    /// Text along with its three letter format, `txt` or `mkd`.
+    VerbatimString(String, Vec<u8>),
+    /// Out of band data, which is not the reply to any command.
+    Push(Vec<RedisValue>),
+    /// Auxiliary data about the value that follows it.
+    Attribute(Vec<(RedisValue, RedisValue)>, Box<RedisValue>),
 }
 
 impl RedisValue {
-    pub fn to_resp_string(&self) -> String {
+    /// Encodes the value for a connection speaking `protocol`. RESP2 has no
+    /// types beyond arrays, bulk strings and integers, so the others are sent
+    /// as the closest of those, the way redis does: maps as flat arrays of
+    /// keys and values, doubles as bulk strings, booleans as integers, and
+    /// attributes not at all.
+    pub fn to_resp_bytes(&self, protocol: Protocol) -> Vec<u8> {
+        let mut result = Vec::new();
+        self.encode(protocol, &mut result);
+        result
+    }
+
+    /// Appends the encoded value to `buffer`, like `to_resp_bytes` but
+    /// encoding straight into the buffer rather than into a new one.
+    pub fn encode(&self, protocol: Protocol, buffer: &mut Vec<u8>) {
+        self.write_to(protocol, buffer)
+            .expect("writing to a Vec cannot fail");
+    }
+
+    /// Writes the encoded value to `writer` piece by piece, so that large
+    /// aggregates are never held in memory in their encoded form.
+    pub fn write_to(&self, protocol: Protocol, writer: &mut impl Write) -> io::Result<()> {
+        let resp3 = protocol == Protocol::Resp3;
         match self {
-            RedisValue::SimpleString(s) => format!("+{}\r\n", s),
-            RedisValue::Error(e) => format!("-{}\r\n", e),
-            RedisValue::Integer(i) => format!(":{}\r\n", i),
-            RedisValue::BulkString(Some(b)) => format!("${}\r\n{}\r\n", b.len(), b),
-            RedisValue::BulkString(None) => "$-1\r\n".to_string(),
-            RedisValue::Array(Some(a)) => {
-                let mut result = format!("*{}\r\n", a.len());
-                for v in a {
-                    result.push_str(&&v.to_resp_string());
+            RedisValue::SimpleString(s) => write_line(writer, b'+', s),
+            // Errors can quote what a client sent, and a line break in there
+            // would end the error early, so redis turns them into spaces.
+            RedisValue::Error(e) if e.contains(['\r', '\n']) => {
+                write_line(writer, b'-', e.replace(['\r', '\n'], " "))
+            }
+            RedisValue::Error(e) => write_line(writer, b'-', e),
+            RedisValue::Integer(i) => write_line(writer, b':', i),
+            RedisValue::BulkString(Some(b)) => write_length_prefixed(writer, b'$', &[b]),
+            RedisValue::BulkString(None) | RedisValue::Null if resp3 => writer.write_all(b"_\r\n"),
+            RedisValue::BulkString(None) | RedisValue::Null => writer.write_all(b"$-1\r\n"),
+            RedisValue::Array(Some(a)) => write_aggregate(writer, b'*', a.len(), a, protocol),
+            RedisValue::Array(None) if resp3 => writer.write_all(b"_\r\n"),
+            RedisValue::Array(None) => writer.write_all(b"*-1\r\n"),
+            RedisValue::Boolean(b) if resp3 => {
+                writer.write_all(if *b { b"#t\r\n" } else { b"#f\r\n" })
+            }
+            RedisValue::Boolean(b) => writer.write_all(if *b { b":1\r\n" } else { b":0\r\n" }),
+            RedisValue::Map(pairs) => {
+                let values = pairs.iter().flat_map(|(key, value)| [key, value]);
+                match resp3 {
+                    true => write_aggregate(writer, b'%', pairs.len(), values, protocol),
+                    false => write_aggregate(writer, b'*', pairs.len() * 2, values, protocol),
+                }
+            }
+            RedisValue::Set(a) => write_aggregate(
+                writer,
+                if resp3 { b'~' } else { b'*' },
+                a.len(),
+                a,
+                protocol,
+            ),
+            RedisValue::Double(d) => {
+                let text = match d.is_nan() {
+                    true => "nan".to_string(),
+                    false => format_double(*d),
+                };
+                match resp3 {
+                    true => write_line(writer, b',', text),
+                    false => write_length_prefixed(writer, b'$', &[text.as_bytes()]),
                 }
-                result
             }
-            RedisValue::Array(None) => "*-1\r\n".to_string(),
-            RedisValue::Boolean(b) => format!("#{}\r\n", if *b { "t" } else { "f" }),
-            RedisValue::Null => "_\r\n".to_string(),
+            RedisValue::BigNumber(n) if resp3 => write_line(writer, b'(', n),
+            RedisValue::BigNumber(n) => write_length_prefixed(writer, b'$', &[n.as_bytes()]),
+            RedisValue::VerbatimString(format, text) if resp3 => {
+                write_length_prefixed(writer, b'=', &[format.as_bytes(), b":", text])
+            }
+            RedisValue::VerbatimString(_, text) => write_length_prefixed(writer, b'$', &[text]),
+            RedisValue::Push(a) => write_aggregate(
+                writer,
+                if resp3 { b'>' } else { b'*' },
+                a.len(),
+                a,
+                protocol,
+            ),
+            RedisValue::Attribute(pairs, value) if resp3 => {
+                let values = pairs.iter().flat_map(|(key, value)| [key, value]);
+                write_aggregate(writer, b'|', pairs.len(), values, protocol)?;
+                value.write_to(protocol, writer)
+            }
+            RedisValue::Attribute(_, value) => value.write_to(protocol, writer),
         }
     }
 }
 
+/// Writes a type byte followed by `line` and CRLF.
+fn write_line(writer: &mut impl Write, type_byte: u8, line: impl Display) -> io::Result<()> {
+    write!(writer, "{}{}\r\n", type_byte as char, line)
+}
+
+/// Writes a string type: its type byte, length and then the payload, which is
+/// given in parts so that callers need not join them first.
+fn write_length_prefixed(
+    writer: &mut impl Write,
+    type_byte: u8,
+    parts: &[&[u8]],
+) -> io::Result<()> {
+    let len: usize = parts.iter().map(|part| part.len()).sum();
+    write_line(writer, type_byte, len)?;
+    for part in parts {
+        writer.write_all(part)?;
+    }
+    writer.write_all(b"\r\n")
+}
+
+/// Writes an aggregate type: its type byte, `len`, which for maps and
+/// attributes counts pairs, and then each of the values.
+fn write_aggregate<'a>(
+    writer: &mut impl Write,
+    type_byte: u8,
+    len: usize,
+    values: impl IntoIterator<Item = &'a RedisValue>,
+    protocol: Protocol,
+) -> io::Result<()> {
+    write_line(writer, type_byte, len)?;
+    for value in values {
+        value.write_to(protocol, writer)?;
+    }
+    Ok(())
+}
+
 #[derive(Debug, PartialEq)]
 pub enum RedisCommand {
     PING(RedisValue),
     ECHO(RedisValue),
-    SET(RedisValue, RedisValue, Option<u64>),
-    GET(RedisValue),
+    SET(Vec<u8>, Vec<u8>, SetOptions),
+    GET(Vec<u8>),
+    EXPIRE(Vec<u8>, i64, ExpireCondition),
+    PEXPIRE(Vec<u8>, i64, ExpireCondition),
+    EXPIREAT(Vec<u8>, i64, ExpireCondition),
+    PEXPIREAT(Vec<u8>, i64, ExpireCondition),
+    TTL(Vec<u8>),
+    PTTL(Vec<u8>),
+    EXPIRETIME(Vec<u8>),
+    PEXPIRETIME(Vec<u8>),
+    PERSIST(Vec<u8>),
+    INCRBY(Vec<u8>, i64),
+    INCRBYFLOAT(Vec<u8>, f64),
+    APPEND(Vec<u8>, Vec<u8>),
+    STRLEN(Vec<u8>),
+    GETRANGE(Vec<u8>, i64, i64),
+    SETRANGE(Vec<u8>, usize, Vec<u8>),
+    SETBIT(Vec<u8>, u64, bool),
+    GETBIT(Vec<u8>, u64),
+    BITCOUNT(Vec<u8>, Option<(i64, i64, BitUnit)>),
+    /// Key, bit to look for, start, end if given, and the unit of the range.
+    BITPOS(Vec<u8>, bool, i64, Option<i64>, BitUnit),
+    BITOP(BitOp, Vec<u8>, Vec<Vec<u8>>),
+    BITFIELD(Vec<u8>, Vec<BitfieldOp>),
+    MGET(Vec<Vec<u8>>),
+    MSET(Vec<(Vec<u8>, Vec<u8>)>),
+    MSETNX(Vec<(Vec<u8>, Vec<u8>)>),
+    DEL(Vec<Vec<u8>>),
+    EXISTS(Vec<Vec<u8>>),
+    TOUCH(Vec<Vec<u8>>),
+    TYPE(Vec<u8>),
+    RENAME(Vec<u8>, Vec<u8>),
+    RENAMENX(Vec<u8>, Vec<u8>),
+    COPY(Vec<u8>, Vec<u8>, Option<i64>, bool),
+    RANDOMKEY,
+    DBSIZE,
+    FLUSHDB,
+    FLUSHALL,
+    KEYS(Vec<u8>),
+    SCAN(u64, ScanOptions),
+    SELECT(i64),
+    MOVE(Vec<u8>, i64),
+    SWAPDB(i64, i64),
+    LPUSH(Vec<u8>, Vec<Vec<u8>>),
+    RPUSH(Vec<u8>, Vec<Vec<u8>>),
+    LPOP(Vec<u8>, Option<usize>),
+    RPOP(Vec<u8>, Option<usize>),
+    LRANGE(Vec<u8>, i64, i64),
+    LINDEX(Vec<u8>, i64),
+    LSET(Vec<u8>, i64, Vec<u8>),
+    LREM(Vec<u8>, i64, Vec<u8>),
+    LTRIM(Vec<u8>, i64, i64),
+    /// Key, whether to insert before the pivot rather than after, pivot and
+    /// element.
+    LINSERT(Vec<u8>, bool, Vec<u8>, Vec<u8>),
+    LLEN(Vec<u8>),
+    LMOVE(Vec<u8>, Vec<u8>, ListEnd, ListEnd),
+    /// Keys and how long to block for, zero meaning no limit.
+    BLPOP(Vec<Vec<u8>>, Duration),
+    BRPOP(Vec<Vec<u8>>, Duration),
+    /// LMOVE's arguments and how long to block for, zero meaning no limit.
+    BLMOVE(Vec<u8>, Vec<u8>, ListEnd, ListEnd, Duration),
+    LPOS(Vec<u8>, Vec<u8>, ListPositionOptions),
+    HSET(Vec<u8>, Vec<(Vec<u8>, Vec<u8>)>),
+    HGET(Vec<u8>, Vec<u8>),
+    HMGET(Vec<u8>, Vec<Vec<u8>>),
+    HDEL(Vec<u8>, Vec<Vec<u8>>),
+    HGETALL(Vec<u8>),
+    HINCRBY(Vec<u8>, Vec<u8>, i64),
+    HINCRBYFLOAT(Vec<u8>, Vec<u8>, f64),
+    HKEYS(Vec<u8>),
+    HVALS(Vec<u8>),
+    HLEN(Vec<u8>),
+    HEXISTS(Vec<u8>, Vec<u8>),
+    HSCAN(Vec<u8>, u64, ScanOptions),
+    /// Key, count and whether to include values. Without a count a single
+    /// field is returned rather than an array.
+    HRANDFIELD(Vec<u8>, Option<i64>, bool),
+    SADD(Vec<u8>, Vec<Vec<u8>>),
+    SREM(Vec<u8>, Vec<Vec<u8>>),
+    SISMEMBER(Vec<u8>, Vec<u8>),
+    SMISMEMBER(Vec<u8>, Vec<Vec<u8>>),
+    SMEMBERS(Vec<u8>),
+    SCARD(Vec<u8>),
+    /// Key and count. Without a count a single member is returned rather
+    /// than an array.
+    SPOP(Vec<u8>, Option<usize>),
+    SRANDMEMBER(Vec<u8>, Option<i64>),
+    SINTER(Vec<Vec<u8>>),
+    SUNION(Vec<Vec<u8>>),
+    SDIFF(Vec<Vec<u8>>),
+    SINTERSTORE(Vec<u8>, Vec<Vec<u8>>),
+    SUNIONSTORE(Vec<u8>, Vec<Vec<u8>>),
+    SDIFFSTORE(Vec<u8>, Vec<Vec<u8>>),
+    /// Keys and the limit, zero meaning none.
+    SINTERCARD(Vec<Vec<u8>>, usize),
+    SSCAN(Vec<u8>, u64, ScanOptions),
+    ZADD(Vec<u8>, Vec<(f64, Vec<u8>)>, ZAddOptions),
+    /// ZADD with the INCR flag: key, increment, member and the other flags.
+    ZADDINCR(Vec<u8>, f64, Vec<u8>, ZAddOptions),
+    ZRANGE(Vec<u8>, ZRangeOptions),
+    /// Key, member and whether to reply with the score as well.
+    ZRANK(Vec<u8>, Vec<u8>, bool),
+    ZSCORE(Vec<u8>, Vec<u8>),
+    ZINCRBY(Vec<u8>, f64, Vec<u8>),
+    ZREM(Vec<u8>, Vec<Vec<u8>>),
+    ZCARD(Vec<u8>),
+    ZPOPMIN(Vec<u8>, Option<usize>),
+    ZPOPMAX(Vec<u8>, Option<usize>),
+    /// Keys and how long to block for, zero meaning no limit.
+    BZPOPMIN(Vec<Vec<u8>>, Duration),
+    BZPOPMAX(Vec<Vec<u8>>, Duration),
+    /// Destination, keys, their weights and how to aggregate scores.
+    ZUNIONSTORE(Vec<u8>, Vec<Vec<u8>>, Vec<f64>, Aggregate),
+    ZINTERSTORE(Vec<u8>, Vec<Vec<u8>>, Vec<f64>, Aggregate),
+    /// Key, ID, fields with their values, NOMKSTREAM and how to trim the
+    /// stream afterwards.
+    XADD(
+        Vec<u8>,
+        StreamAddId,
+        Vec<(Vec<u8>, Vec<u8>)>,
+        bool,
+        Option<StreamTrim>,
+    ),
+    /// Key, the lowest and highest IDs, and count. XREVRANGE takes them the
+    /// other way around, but they are stored in the same order.
+    XRANGE(Vec<u8>, StreamId, StreamId, Option<usize>),
+    XREVRANGE(Vec<u8>, StreamId, StreamId, Option<usize>),
+    XLEN(Vec<u8>),
+    XTRIM(Vec<u8>, StreamTrim),
+    XDEL(Vec<u8>, Vec<StreamId>),
+    /// Keys with the ID to read after, `None` standing for `$`, count, and
+    /// how long to block for with BLOCK, zero meaning no limit.
+    XREAD(
+        Vec<(Vec<u8>, Option<StreamId>)>,
+        Option<usize>,
+        Option<Duration>,
+    ),
+    /// Key, group, the ID the group has read up to, `None` standing for `$`,
+    /// and MKSTREAM.
+    XGROUPCREATE(Vec<u8>, Vec<u8>, Option<StreamId>, bool),
+    XGROUPSETID(Vec<u8>, Vec<u8>, Option<StreamId>),
+    XGROUPDESTROY(Vec<u8>, Vec<u8>),
+    /// Key, group and consumer.
+    XGROUPCREATECONSUMER(Vec<u8>, Vec<u8>, Vec<u8>),
+    XGROUPDELCONSUMER(Vec<u8>, Vec<u8>, Vec<u8>),
+    /// Group, consumer, keys with the ID to read after, `None` standing for
+    /// `>`, count, NOACK and BLOCK.
+    XREADGROUP(
+        Vec<u8>,
+        Vec<u8>,
+        Vec<(Vec<u8>, Option<StreamId>)>,
+        Option<usize>,
+        bool,
+        Option<Duration>,
+    ),
+    XACK(Vec<u8>, Vec<u8>, Vec<StreamId>),
+    XPENDING(Vec<u8>, Vec<u8>, Option<PendingRange>),
+    /// Key, group, consumer, minimum idle time, IDs and the other options.
+    XCLAIM(Vec<u8>, Vec<u8>, Vec<u8>, i64, Vec<StreamId>, ClaimOptions),
+    /// Key, group, consumer, minimum idle time, start ID, count and JUSTID.
+    XAUTOCLAIM(Vec<u8>, Vec<u8>, Vec<u8>, i64, StreamId, usize, bool),
+    /// The protocol version to switch to, if one was given.
+    HELLO(Option<Protocol>),
     CONFIG,
-    COMMAND,
-}
-
-fn pick_simple_string(
-    iter: &mut std::iter::Peekable<std::slice::Iter<u8>>,
-) -> Result<RedisValue, anyhow::Error> {
-    iter.next();
-    let mut result = Vec::new();
-    while let Some(&&byte) = iter.peek() {
-        if byte == b'\r' {
-            iter.next();
-            iter.next();
-            break;
-        }
-        result.push(byte);
-        iter.next();
-    }
-    Ok(RedisValue::SimpleString(String::from_utf8(result)?))
-}
-
-fn pick_error(
-    iter: &mut std::iter::Peekable<std::slice::Iter<u8>>,
-) -> Result<RedisValue, anyhow::Error> {
-    iter.next();
-    let mut result = Vec::new();
-    while let Some(&&byte) = iter.peek() {
-        if byte == b'\r' {
-            iter.next();
-            iter.next();
-            break;
-        }
-        result.push(byte);
-        iter.next();
-    }
-    Ok(RedisValue::Error(String::from_utf8(result)?))
-}
-
-fn pick_integer(
-    iter: &mut std::iter::Peekable<std::slice::Iter<u8>>,
-) -> Result<RedisValue, anyhow::Error> {
-    iter.next();
-    let mut result = Vec::new();
-    while let Some(&&byte) = iter.peek() {
-        if byte == b'\r' {
-            iter.next();
-            iter.next();
-            break;
-        }
-        result.push(byte);
-        iter.next();
-    }
-    Ok(RedisValue::Integer(String::from_utf8(result)?.parse()?))
-}
-
-fn pick_bulk_string(
-    iter: &mut std::iter::Peekable<std::slice::Iter<u8>>,
-) -> Result<RedisValue, anyhow::Error> {
-    iter.next();
-    let mut result = Vec::new();
-    while let Some(&&byte) = iter.peek() {
-        if byte == b'\r' {
-            iter.next();
-            iter.next();
-            break;
-        }
-        result.push(byte);
-        iter.next();
-    }
-    let len = String::from_utf8(result)?.parse()?;
-    if len == -1 {
-        return Ok(RedisValue::BulkString(None));
-    }
-    let mut result = Vec::new();
-    for _ in 0..len {
-        result.push(*iter.next().ok_or(anyhow!("Unexpected end of input"))?);
-    }
-    iter.next();
-    iter.next();
-    Ok(RedisValue::BulkString(Some(String::from_utf8(result)?)))
-}
-
-fn pick_array(
-    iter: &mut std::iter::Peekable<std::slice::Iter<u8>>,
-) -> Result<RedisValue, anyhow::Error> {
-    iter.next();
-    let mut result = Vec::new();
-    while let Some(&&byte) = iter.peek() {
-        if byte == b'\r' {
-            iter.next();
-            iter.next();
-            break;
-        }
-        result.push(byte);
-        iter.next();
-    }
-    let len = String::from_utf8(result)?.parse::<i64>()?;
-    if len == -1 {
-        return Ok(RedisValue::Array(None));
-    }
-    let mut array = Vec::with_capacity(len as usize);
-    while let Some(&&array_byte) = iter.peek() {
-        match array_byte {
-            b'+' => {
-                array.push(pick_simple_string(iter)?);
-            }
-            b'-' => {
-                array.push(pick_error(iter)?);
-            }
-            b':' => {
-                array.push(pick_integer(iter)?);
-            }
-            b'$' => {
-                array.push(pick_bulk_string(iter)?);
+    COMMANDCOUNT,
+    /// The commands to describe, or every command when none are named.
+    COMMANDINFO(Vec<Vec<u8>>),
+    COMMANDDOCS(Vec<Vec<u8>>),
+    /// A command and its arguments, whose keys to find.
+    COMMANDGETKEYS(Vec<Vec<u8>>),
+    COMMANDLIST(Option<CommandFilter>),
+}
+
+// Upper bounds mirroring redis' own protocol limits, so that a bogus header
+// cannot make us reserve an absurd amount of memory up front.
+const MAX_BULK_LENGTH: i64 = 512 * 1024 * 1024;
+const MAX_ARRAY_LENGTH: i64 = 1024 * 1024 * 1024;
+// Inline commands and the header lines of multibulk requests have to end
+// within this many bytes.
+const MAX_INLINE_LENGTH: usize = 64 * 1024;
+
+/// A command name followed by its arguments and the offset just past them,
+/// or `None` when the buffer ends before the command does.
+type ParsedRequest = Option<(Vec<Vec<u8>>, usize)>;
+
+/// Returns the line starting at `pos` (without its CRLF) and the offset of the
+/// byte following the CRLF, or `None` if the terminator has not arrived yet.
+fn read_line(buffer: &[u8], pos: usize) -> Option<(&[u8], usize)> {
+    let end = buffer[pos..].windows(2).position(|w| w == b"\r\n")?;
+    Some((&buffer[pos..pos + end], pos + end + 2))
+}
+
+fn parse_length(line: &[u8]) -> Option<i64> {
+    std::str::from_utf8(line).ok()?.parse().ok()
+}
+
+/// Parses the first command a client sent in `buffer` into the command name
+/// followed by its arguments, in one go. See `RequestParser` for the details.
+pub fn parse_request(buffer: &[u8]) -> Result<ParsedRequest, RedisError> {
+    RequestParser::default().parse(buffer)
+}
+
+/// Parses the commands a client sends, each either an array of bulk strings
+/// or, when it does not start with `*`, an inline command typed by hand.
+///
+/// A multibulk request that has only partly arrived is not parsed again from
+/// the start when more of it does: the parser keeps the arguments read so far
+/// and where it stopped, as redis does with its multibulklen and bulklen, so
+/// that a request arriving over many reads costs no more than one arriving at
+/// once.
+#[derive(Debug, Default)]
+pub struct RequestParser {
+    /// The arguments of the request parsed so far.
+    args: Vec<Vec<u8>>,
+    /// The number of arguments still to come.
+    remaining: usize,
+    /// The length of the argument being read, once its header has been.
+    bulk_len: Option<usize>,
+    /// The offset in the request to carry on from, zero until its header has
+    /// been read.
+    pos: usize,
+}
+
+impl RequestParser {
+    /// Parses the command starting at the beginning of `buffer`, which has to
+    /// start at the same command as on the previous call if that one returned
+    /// `Ok(None)`.
+    ///
+    /// Returns `Ok(None)` until the whole command has arrived and the number
+    /// of bytes it occupied once it has, so that pipelined commands can be
+    /// parsed one after another. An array with
+    /// no elements or a blank line is an empty command, which redis skips
+    /// without replying.
+    pub fn parse(&mut self, buffer: &[u8]) -> Result<ParsedRequest, RedisError> {
+        let parsed = self.resume(buffer);
+        if !matches!(parsed, Ok(None)) {
+            *self = RequestParser::default();
+        }
+        parsed
+    }
+
+    fn resume(&mut self, buffer: &[u8]) -> Result<ParsedRequest, RedisError> {
+        if self.pos == 0 {
+            match buffer.first() {
+                None => return Ok(None),
+                Some(b'*') => {}
+                Some(_) => return parse_inline_request(buffer),
+            }
+            let Some((line, next)) = read_line(buffer, 1) else {
+                if buffer.len() > MAX_INLINE_LENGTH {
+                    return Err(RedisError::protocol("too big mbulk count string", 0));
+                }
+                return Ok(None);
+            };
+            self.remaining = match parse_length(line) {
+                Some(len) if len <= MAX_ARRAY_LENGTH => len.max(0) as usize,
+                _ => return Err(RedisError::protocol("invalid multibulk length", 0)),
+            };
+            self.args = Vec::with_capacity(self.remaining.min(1024));
+            self.pos = next;
+        }
+        while self.remaining > 0 {
+            let len = match self.bulk_len {
+                Some(len) => len,
+                None => match self.read_bulk_len(buffer)? {
+                    Some(len) => len,
+                    None => return Ok(None),
+                },
+            };
+            // The payload is skipped by length rather than scanned.
+            let end = self.pos + len;
+            if buffer.len() < end + 2 {
+                return Ok(None);
+            }
+            if &buffer[end..end + 2] != b"\r\n" {
+                return Err(RedisError::protocol("expected CRLF after bulk string", end));
+            }
+            self.args.push(buffer[self.pos..end].to_vec());
+            self.pos = end + 2;
+            self.bulk_len = None;
+            self.remaining -= 1;
+        }
+        Ok(Some((std::mem::take(&mut self.args), self.pos)))
+    }
+
+    /// Reads the header of the next argument, moving past it.
+    fn read_bulk_len(&mut self, buffer: &[u8]) -> Result<Option<usize>, RedisError> {
+        match buffer.get(self.pos) {
+            None => return Ok(None),
+            Some(b'$') => {}
+            Some(&byte) => {
+                return Err(RedisError::protocol(
+                    format!("expected '$', got '{}'", byte as char),
+                    self.pos,
+                ))
+            }
+        }
+        let Some((line, next)) = read_line(buffer, self.pos + 1) else {
+            if buffer.len() - self.pos > MAX_INLINE_LENGTH {
+                return Err(RedisError::protocol("too big bulk count string", self.pos));
+            }
+            return Ok(None);
+        };
+        let len = match parse_length(line) {
+            Some(len) if (0..=MAX_BULK_LENGTH).contains(&len) => len as usize,
+            _ => return Err(RedisError::protocol("invalid bulk length", self.pos)),
+        };
+        self.bulk_len = Some(len);
+        self.pos = next;
+        Ok(Some(len))
+    }
+}
+
+/// Parses a line of text such as telnet or netcat send, ended by a newline
+/// with or without a carriage return before it.
+fn parse_inline_request(buffer: &[u8]) -> Result<ParsedRequest, RedisError> {
+    let Some(end) = buffer.iter().position(|&byte| byte == b'\n') else {
+        if buffer.len() > MAX_INLINE_LENGTH {
+            return Err(RedisError::protocol("too big inline request", 0));
+        }
+        return Ok(None);
+    };
+    let line = &buffer[..end];
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let request = split_inline_args(line)
+        .ok_or_else(|| RedisError::protocol("unbalanced quotes in request", 0))?;
+    Ok(Some((request, end + 1)))
+}
+
+/// Splits an inline command on whitespace the way redis-cli does. Double
+/// quotes take C-like escapes, `\xHH` included, while single quotes only
+/// escape a single quote. Returns `None` for a quote left open or one closed
+/// right before something other than whitespace.
+fn split_inline_args(line: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut args = Vec::new();
+    let mut rest = line;
+    loop {
+        rest = rest.trim_ascii_start();
+        if rest.is_empty() {
+            return Some(args);
+        }
+        let mut arg = Vec::new();
+        while let Some((&byte, tail)) = rest.split_first() {
+            rest = match byte {
+                byte if byte.is_ascii_whitespace() => break,
+                b'"' | b'\'' => pick_quoted(tail, byte, &mut arg)?,
+                byte => {
+                    arg.push(byte);
+                    tail
+                }
+            };
+        }
+        args.push(arg);
+    }
+}
+
+/// Appends to `arg` the rest of a string opened with `quote`, returning what
+/// follows its closing quote.
+fn pick_quoted<'a>(mut rest: &'a [u8], quote: u8, arg: &mut Vec<u8>) -> Option<&'a [u8]> {
+    let hex_digit = |digit: u8| (digit as char).to_digit(16).map(|digit| digit as u8);
+    loop {
+        rest = match rest {
+            [] => return None,
+            [end, tail @ ..] if *end == quote => {
+                return match tail.first() {
+                    Some(next) if !next.is_ascii_whitespace() => None,
+                    _ => Some(tail),
+                };
+            }
+            [b'\\', b'\'', tail @ ..] if quote == b'\'' => {
+                arg.push(b'\'');
+                tail
             }
-            b'*' => {
-                array.push(pick_array(iter)?);
+            [b'\\', b'x', high, low, tail @ ..]
+                if quote == b'"' && high.is_ascii_hexdigit() && low.is_ascii_hexdigit() =>
+            {
+                arg.push(hex_digit(*high)? << 4 | hex_digit(*low)?);
+                tail
             }
-            b'\r' => {
-                iter.next();
-                iter.next();
-                break;
+            [b'\\', escaped, tail @ ..] if quote == b'"' => {
+                arg.push(match escaped {
+                    b'n' => b'\n',
+                    b'r' => b'\r',
+                    b't' => b'\t',
+                    b'b' => 0x08,
+                    b'a' => 0x07,
+                    other => *other,
+                });
+                tail
             }
+            [byte, tail @ ..] => {
+                arg.push(*byte);
+                tail
+            }
+        };
+    }
+}
+
+const ERR_BIT_OFFSET: &str = "ERR bit offset is not an integer or out of range";
+const ERR_STREAM_ID: &str = "ERR Invalid stream ID specified as stream command argument";
+
+// Bits in a string of the maximum size.
+const MAX_BIT_OFFSET: i64 = 512 * 1024 * 1024 * 8 - 1;
+
+fn wrong_arity(command: &str) -> RedisError {
+    RedisError::WrongArity(command.to_string())
+}
+
+/// Parses a signed 64 bit integer as strictly as redis does: no sign other
+/// than a leading minus, no leading zeros and no surrounding whitespace.
+pub fn parse_integer(arg: &[u8]) -> Result<i64, RedisError> {
+    let digits = arg.strip_prefix(b"-").unwrap_or(arg);
+    let well_formed = match digits {
+        [] => false,
+        [b'0'] => digits.len() == arg.len(),
+        [first, ..] => *first != b'0' && digits.iter().all(u8::is_ascii_digit),
+    };
+    if !well_formed {
+        return Err(RedisError::NotInteger);
+    }
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .ok_or(RedisError::NotInteger)
+}
+
+/// Parses the count of HRANDFIELD and SRANDMEMBER, which redis bounds so that
+/// asking for its magnitude, doubled for WITHVALUES, cannot overflow.
+fn parse_random_count(arg: &[u8]) -> Result<i64, RedisError> {
+    let count = parse_integer(arg)?;
+    if !(-(i64::MAX / 2)..=i64::MAX / 2).contains(&count) {
+        return Err(RedisError::other("ERR value is out of range"));
+    }
+    Ok(count)
+}
+
+/// Parses a float the way INCRBYFLOAT accepts it, which excludes NaN,
+/// infinities and surrounding whitespace.
+pub fn parse_float(arg: &[u8]) -> Result<f64, RedisError> {
+    std::str::from_utf8(arg)
+        .ok()
+        .filter(|s| !s.starts_with(char::is_whitespace) && !s.ends_with(char::is_whitespace))
+        .and_then(|s| s.parse::<f64>().ok())
+        .filter(|f| f.is_finite())
+        .ok_or(RedisError::other("ERR value is not a valid float"))
+}
+
+/// Parses a sorted set score, which unlike INCRBYFLOAT arguments may be
+/// infinite.
+pub fn parse_score(arg: &[u8]) -> Result<f64, RedisError> {
+    std::str::from_utf8(arg)
+        .ok()
+        .filter(|s| !s.starts_with(char::is_whitespace) && !s.ends_with(char::is_whitespace))
+        .and_then(|s| s.parse::<f64>().ok())
+        .filter(|f| !f.is_nan())
+        .ok_or(RedisError::other("ERR value is not a valid float"))
+}
+
+/// Formats a double the way redis replies with one: the shortest form that
+/// parses back to the same value, in exponent notation outside of the range
+/// where `%.17g` would use plain digits.
+pub fn format_double(value: f64) -> String {
+    if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    let scientific = format!("{:e}", value);
+    let (mantissa, exponent) = scientific.split_once('e').expect("{:e} has an exponent");
+    let exponent: i32 = exponent.parse().expect("exponent is an integer");
+    if (-4..17).contains(&exponent) {
+        value.to_string()
+    } else {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+    }
+}
+
+fn parse_set_options(args: &[&[u8]]) -> Result<SetOptions, RedisError> {
+    let mut options = SetOptions::default();
+    let mut has_expiry = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let option = String::from_utf8_lossy(arg).to_uppercase();
+        match option.as_str() {
+            "NX" if options.condition != SetCondition::XX => options.condition = SetCondition::NX,
+            "XX" if options.condition != SetCondition::NX => options.condition = SetCondition::XX,
+            "GET" => options.get = true,
+            "KEEPTTL" if !has_expiry => {
+                options.expiry = SetExpiry::KeepTtl;
+                has_expiry = true;
+            }
+            "EX" | "PX" | "EXAT" | "PXAT" if !has_expiry => {
+                let value = parse_integer(args.next().ok_or(RedisError::Syntax)?)?;
+                let invalid = || RedisError::other("ERR invalid expire time in 'set' command");
+                if value <= 0 {
+                    return Err(invalid());
+                }
+                let millis = match option.as_str() {
+                    "EX" | "EXAT" => value.checked_mul(1000).ok_or_else(invalid)?,
+                    _ => value,
+                };
+                options.expiry = match option.as_str() {
+                    "EX" | "PX" => {
+                        // The deadline has to stay representable once the
+                        // current time is added to it.
+                        if millis > i64::MAX - unix_time_millis() {
+                            return Err(invalid());
+                        }
+                        SetExpiry::In(millis)
+                    }
+                    _ => SetExpiry::At(millis),
+                };
+                has_expiry = true;
+            }
+            _ => return Err(RedisError::Syntax),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_expire_condition(args: &[&[u8]]) -> Result<ExpireCondition, RedisError> {
+    let mut condition = ExpireCondition::default();
+    for arg in args {
+        match String::from_utf8_lossy(arg).to_uppercase().as_str() {
+            "NX" => condition.nx = true,
+            "XX" => condition.xx = true,
+            "GT" => condition.gt = true,
+            "LT" => condition.lt = true,
             _ => {
-                return Err(anyhow!("Unexpected byte in array"));
+                return Err(RedisError::other(format!(
+                    "ERR Unsupported option {}",
+                    String::from_utf8_lossy(arg)
+                )))
+            }
+        }
+    }
+    if condition.nx && (condition.xx || condition.gt || condition.lt) {
+        return Err(RedisError::other(
+            "ERR NX and XX, GT or LT options at the same time are not compatible",
+        ));
+    }
+    if condition.gt && condition.lt {
+        return Err(RedisError::other(
+            "ERR GT and LT options at the same time are not compatible",
+        ));
+    }
+    Ok(condition)
+}
+
+fn parse_cursor(arg: &[u8]) -> Result<u64, RedisError> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|cursor| cursor.parse::<u64>().ok())
+        .ok_or(RedisError::other("ERR invalid cursor"))
+}
+
+/// Parses the options of SCAN, or of HSCAN and the like when `allow_type` is
+/// false, as only SCAN filters on the type.
+fn parse_scan_options(args: &[&[u8]], allow_type: bool) -> Result<ScanOptions, RedisError> {
+    let mut options = ScanOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or(RedisError::Syntax)?;
+        match String::from_utf8_lossy(arg).to_uppercase().as_str() {
+            "MATCH" => options.pattern = Some(value.to_vec()),
+            "COUNT" => {
+                let count = parse_integer(value)?;
+                if count < 1 {
+                    return Err(RedisError::Syntax);
+                }
+                options.count = count as usize;
+            }
+            "TYPE" if allow_type => {
+                options.key_type = Some(String::from_utf8_lossy(value).into_owned())
+            }
+            _ => return Err(RedisError::Syntax),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_list_end(arg: &[u8]) -> Result<ListEnd, RedisError> {
+    match String::from_utf8_lossy(arg).to_uppercase().as_str() {
+        "LEFT" => Ok(ListEnd::Left),
+        "RIGHT" => Ok(ListEnd::Right),
+        _ => Err(RedisError::Syntax),
+    }
+}
+
+fn parse_list_position_options(args: &[&[u8]]) -> Result<ListPositionOptions, RedisError> {
+    let mut options = ListPositionOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = parse_integer(args.next().ok_or(RedisError::Syntax)?)?;
+        match String::from_utf8_lossy(arg).to_uppercase().as_str() {
+            "RANK" => {
+                if value == 0 {
+                    return Err(RedisError::other("ERR RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the last match"));
+                }
+                if value == i64::MIN {
+                    return Err(RedisError::other("ERR value is out of range, value must between -9223372036854775807 and 9223372036854775807"));
+                }
+                options.rank = value;
+            }
+            "COUNT" => {
+                if value < 0 {
+                    return Err(RedisError::other("ERR COUNT can't be negative"));
+                }
+                options.count = Some(value as usize);
+            }
+            "MAXLEN" => {
+                if value < 0 {
+                    return Err(RedisError::other("ERR MAXLEN can't be negative"));
+                }
+                options.maxlen = value as usize;
+            }
+            _ => return Err(RedisError::Syntax),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_bit_offset(arg: &[u8]) -> Result<u64, RedisError> {
+    parse_integer(arg)
+        .ok()
+        .filter(|offset| (0..=MAX_BIT_OFFSET).contains(offset))
+        .map(|offset| offset as u64)
+        .ok_or(RedisError::other(ERR_BIT_OFFSET))
+}
+
+fn parse_bit_unit(arg: &[u8]) -> Result<BitUnit, RedisError> {
+    match String::from_utf8_lossy(arg).to_uppercase().as_str() {
+        "BYTE" => Ok(BitUnit::Byte),
+        "BIT" => Ok(BitUnit::Bit),
+        _ => Err(RedisError::Syntax),
+    }
+}
+
+/// Parses a BITFIELD type: `i` or `u` followed by the width, up to 64 bits
+/// signed and 63 unsigned so that every value fits in a signed reply.
+fn parse_bitfield_type(arg: &[u8]) -> Result<BitfieldType, RedisError> {
+    let invalid = || {
+        RedisError::other("ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.")
+    };
+    let (signed, bits) = match arg.split_first() {
+        Some((b'i' | b'I', bits)) => (true, bits),
+        Some((b'u' | b'U', bits)) => (false, bits),
+        _ => return Err(invalid()),
+    };
+    let bits = parse_integer(bits).map_err(|_| invalid())?;
+    let max = if signed { 64 } else { 63 };
+    if !(1..=max).contains(&bits) {
+        return Err(invalid());
+    }
+    Ok(BitfieldType {
+        signed,
+        bits: bits as u32,
+    })
+}
+
+/// Parses a BITFIELD offset, which counts in units of the field width when
+/// prefixed with `#`.
+fn parse_bitfield_offset(arg: &[u8], field: BitfieldType) -> Result<u64, RedisError> {
+    let (digits, scale) = match arg.strip_prefix(b"#") {
+        Some(digits) => (digits, field.bits as i64),
+        None => (arg, 1),
+    };
+    parse_integer(digits)
+        .ok()
+        .filter(|offset| *offset >= 0)
+        .and_then(|offset| offset.checked_mul(scale))
+        .filter(|offset| *offset <= MAX_BIT_OFFSET)
+        .map(|offset| offset as u64)
+        .ok_or(RedisError::other(ERR_BIT_OFFSET))
+}
+
+fn parse_bitfield_ops(args: &[&[u8]], read_only: bool) -> Result<Vec<BitfieldOp>, RedisError> {
+    let mut ops = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut next = || args.next().ok_or(RedisError::Syntax);
+        let subcommand = String::from_utf8_lossy(arg).to_uppercase();
+        if read_only && subcommand != "GET" {
+            return Err(RedisError::other(
+                "ERR BITFIELD_RO only supports the GET subcommand",
+            ));
+        }
+        let op = match subcommand.as_str() {
+            "GET" => {
+                let field = parse_bitfield_type(next()?)?;
+                BitfieldOp::Get(field, parse_bitfield_offset(next()?, field)?)
+            }
+            "SET" | "INCRBY" => {
+                let field = parse_bitfield_type(next()?)?;
+                let offset = parse_bitfield_offset(next()?, field)?;
+                let value = parse_integer(next()?)?;
+                match subcommand.as_str() {
+                    "SET" => BitfieldOp::Set(field, offset, value),
+                    _ => BitfieldOp::IncrBy(field, offset, value),
+                }
+            }
+            "OVERFLOW" => {
+                let overflow = match String::from_utf8_lossy(next()?).to_uppercase().as_str() {
+                    "WRAP" => Overflow::Wrap,
+                    "SAT" => Overflow::Sat,
+                    "FAIL" => Overflow::Fail,
+                    _ => return Err(RedisError::other("ERR Invalid OVERFLOW type specified")),
+                };
+                BitfieldOp::Overflow(overflow)
+            }
+            _ => return Err(RedisError::Syntax),
+        };
+        ops.push(op);
+    }
+    Ok(ops)
+}
+
+/// Parses a BYSCORE bound: a score, exclusive when prefixed with `(`.
+fn parse_score_bound(arg: &[u8]) -> Result<ScoreBound, RedisError> {
+    let (score, exclusive) = match arg.strip_prefix(b"(") {
+        Some(score) => (score, true),
+        None => (arg, false),
+    };
+    let score =
+        parse_score(score).map_err(|_| RedisError::other("ERR min or max is not a float"))?;
+    Ok(ScoreBound { score, exclusive })
+}
+
+/// Parses a BYLEX bound: `-`, `+`, or a member prefixed with `[` or `(`.
+fn parse_lex_bound(arg: &[u8]) -> Result<LexBound, RedisError> {
+    match arg {
+        b"-" => Ok(LexBound::NegativeInfinity),
+        b"+" => Ok(LexBound::PositiveInfinity),
+        [b'[', member @ ..] => Ok(LexBound::Inclusive(member.to_vec())),
+        [b'(', member @ ..] => Ok(LexBound::Exclusive(member.to_vec())),
+        _ => Err(RedisError::other(
+            "ERR min or max not valid string range item",
+        )),
+    }
+}
+
+/// Parses the flags at the start of ZADD's arguments. Returns them along
+/// with whether INCR was given and the number of arguments they took up.
+fn parse_zadd_options(args: &[&[u8]]) -> Result<(ZAddOptions, bool, usize), RedisError> {
+    let mut options = ZAddOptions::default();
+    let mut incr = false;
+    let mut taken = 0;
+    for arg in args {
+        match String::from_utf8_lossy(arg).to_uppercase().as_str() {
+            "NX" if options.condition != SetCondition::XX => options.condition = SetCondition::NX,
+            "XX" if options.condition != SetCondition::NX => options.condition = SetCondition::XX,
+            "NX" | "XX" => {
+                return Err(RedisError::other(
+                    "ERR XX and NX options at the same time are not compatible",
+                ))
+            }
+            "GT" => options.gt = true,
+            "LT" => options.lt = true,
+            "CH" => options.ch = true,
+            "INCR" => incr = true,
+            _ => break,
+        }
+        taken += 1;
+    }
+    if options.condition == SetCondition::NX && (options.gt || options.lt)
+        || options.gt && options.lt
+    {
+        return Err(RedisError::other(
+            "ERR GT, LT, and/or NX options at the same time are not compatible",
+        ));
+    }
+    Ok((options, incr, taken))
+}
+
+/// Parses ZRANGE's arguments after the key.
+fn parse_zrange_options(args: &[&[u8]]) -> Result<ZRangeOptions, RedisError> {
+    let (start, stop) = (args[0], args[1]);
+    let (mut by_score, mut by_lex, mut rev, mut with_scores) = (false, false, false, false);
+    let mut limit = None;
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        match String::from_utf8_lossy(option).to_uppercase().as_str() {
+            "BYSCORE" => by_score = true,
+            "BYLEX" => by_lex = true,
+            "REV" => rev = true,
+            "WITHSCORES" => with_scores = true,
+            "LIMIT" => {
+                let mut next = || options.next().ok_or(RedisError::Syntax);
+                limit = Some((parse_integer(next()?)?, parse_integer(next()?)?));
             }
+            _ => return Err(RedisError::Syntax),
         }
     }
-    Ok(RedisValue::Array(Some(array)))
+    if by_score && by_lex {
+        return Err(RedisError::Syntax);
+    }
+    if limit.is_some() && !by_score && !by_lex {
+        return Err(RedisError::other(
+            "ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX",
+        ));
+    }
+    if with_scores && by_lex {
+        return Err(RedisError::other(
+            "ERR syntax error, WITHSCORES not supported in combination with BYLEX",
+        ));
+    }
+    // With REV, score and lex ranges are given from max to min.
+    let (min, max) = if rev { (stop, start) } else { (start, stop) };
+    let by = if by_score {
+        ZRangeBy::Score(parse_score_bound(min)?, parse_score_bound(max)?)
+    } else if by_lex {
+        ZRangeBy::Lex(parse_lex_bound(min)?, parse_lex_bound(max)?)
+    } else {
+        ZRangeBy::Rank(parse_integer(start)?, parse_integer(stop)?)
+    };
+    Ok(ZRangeOptions {
+        by,
+        rev,
+        limit,
+        with_scores,
+    })
 }
 
-fn pick_boolean(
-    iter: &mut std::iter::Peekable<std::slice::Iter<u8>>,
-) -> Result<RedisValue, anyhow::Error> {
-    iter.next();
-    let mut result = Vec::new();
-    while let Some(&&byte) = iter.peek() {
-        if byte == b'\r' {
-            iter.next();
-            iter.next();
-            break;
+/// Parses the WEIGHTS and AGGREGATE options of ZUNIONSTORE and ZINTERSTORE,
+/// which follow `numkeys` keys.
+fn parse_zstore_options(
+    args: &[&[u8]],
+    numkeys: usize,
+) -> Result<(Vec<f64>, Aggregate), RedisError> {
+    let mut weights = vec![1.0; numkeys];
+    let mut aggregate = Aggregate::default();
+    let mut options = args.iter();
+    while let Some(option) = options.next() {
+        match String::from_utf8_lossy(option).to_uppercase().as_str() {
+            "WEIGHTS" => {
+                for weight in weights.iter_mut() {
+                    let arg = options.next().ok_or(RedisError::Syntax)?;
+                    *weight = parse_score(arg)
+                        .map_err(|_| RedisError::other("ERR weight value is not a float"))?;
+                }
+            }
+            "AGGREGATE" => {
+                let arg = options.next().ok_or(RedisError::Syntax)?;
+                aggregate = match String::from_utf8_lossy(arg).to_uppercase().as_str() {
+                    "SUM" => Aggregate::Sum,
+                    "MIN" => Aggregate::Min,
+                    "MAX" => Aggregate::Max,
+                    _ => return Err(RedisError::Syntax),
+                };
+            }
+            _ => return Err(RedisError::Syntax),
         }
-        result.push(byte);
-        iter.next();
     }
-    match String::from_utf8(result)?.as_str() {
-        "t" => Ok(RedisValue::Boolean(true)),
-        "f" => Ok(RedisValue::Boolean(false)),
-        _ => Err(anyhow!("Unexpected byte in boolean")),
+    Ok((weights, aggregate))
+}
+
+/// Parses the timeout of the blocking list and sorted set commands, given in
+/// seconds.
+fn parse_timeout(arg: &[u8]) -> Result<Duration, RedisError> {
+    let timeout = parse_float(arg)
+        .map_err(|_| RedisError::other("ERR timeout is not a float or out of range"))?;
+    if timeout < 0.0 {
+        return Err(RedisError::other("ERR timeout is negative"));
+    }
+    Duration::try_from_secs_f64(timeout)
+        .map_err(|_| RedisError::other("ERR timeout is out of range"))
+}
+
+/// Parses one of the two numbers of a stream ID, which unlike other
+/// integers may not have a sign.
+fn parse_stream_id_part(part: &[u8]) -> Result<u64, RedisError> {
+    std::str::from_utf8(part)
+        .ok()
+        .filter(|s| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|s| s.parse().ok())
+        .ok_or(RedisError::other(ERR_STREAM_ID))
+}
+
+/// Parses a stream ID, either `ms-seq` or just `ms`, in which case the
+/// sequence number is `missing_seq`.
+fn parse_stream_id(arg: &[u8], missing_seq: u64) -> Result<StreamId, RedisError> {
+    match arg.iter().position(|&b| b == b'-') {
+        Some(dash) => Ok(StreamId {
+            ms: parse_stream_id_part(&arg[..dash])?,
+            seq: parse_stream_id_part(&arg[dash + 1..])?,
+        }),
+        None => Ok(StreamId {
+            ms: parse_stream_id_part(arg)?,
+            seq: missing_seq,
+        }),
+    }
+}
+
+/// Parses a bound of a stream range: `-`, `+`, or an ID, exclusive when
+/// prefixed with `(`. A bare millisecond time takes in all of its sequence
+/// numbers.
+fn parse_stream_bound(arg: &[u8], start: bool) -> Result<StreamId, RedisError> {
+    let missing_seq = if start { 0 } else { u64::MAX };
+    match arg {
+        b"-" => Ok(StreamId::MIN),
+        b"+" => Ok(StreamId::MAX),
+        [b'(', id @ ..] => {
+            let id = parse_stream_id(id, missing_seq)?;
+            match start {
+                true => id
+                    .next()
+                    .ok_or(RedisError::other("ERR invalid start ID for the interval")),
+                false => id
+                    .previous()
+                    .ok_or(RedisError::other("ERR invalid end ID for the interval")),
+            }
+        }
+        _ => parse_stream_id(arg, missing_seq),
     }
 }
 
-pub fn parse_resp(buffer: &[u8]) -> Result<Option<RedisValue>, anyhow::Error> {
-    let mut iter = buffer.iter().peekable();
-    while let Some(&&byte) = iter.peek() {
-        match byte {
-            b'+' => {
-                return Ok(Some(pick_simple_string(&mut iter)?));
-            }
-            b'-' => {
-                return Ok(Some(pick_error(&mut iter)?));
-            }
-            b':' => {
-                return Ok(Some(pick_integer(&mut iter)?));
-            }
-            b'$' => {
-                return Ok(Some(pick_bulk_string(&mut iter)?));
-            }
-            b'*' => {
-                return Ok(Some(pick_array(&mut iter)?));
-            }
-            b'#' => {
-                return Ok(Some(pick_boolean(&mut iter)?));
-            }
-            b'_' => {
-                iter.next();
-                iter.next();
-                iter.next();
-                return Ok(Some(RedisValue::Null));
+/// Parses the COUNT of stream commands, where zero or less means no limit.
+fn parse_stream_count(arg: &[u8]) -> Result<Option<usize>, RedisError> {
+    Ok(usize::try_from(parse_integer(arg)?)
+        .ok()
+        .filter(|count| *count > 0))
+}
+
+/// Parses the trimming options of XADD and XTRIM, along with NOMKSTREAM if
+/// `allow_nomkstream`. Stops at the first argument that is not an option,
+/// and returns the options with the number of arguments they took up.
+fn parse_stream_trim(
+    args: &[&[u8]],
+    allow_nomkstream: bool,
+) -> Result<(Option<StreamTrim>, bool, usize), RedisError> {
+    let mut strategy = None;
+    let mut approximate = false;
+    let mut limit = None;
+    let mut no_mkstream = false;
+    let mut taken = 0;
+    while let Some(arg) = args.get(taken) {
+        let option = String::from_utf8_lossy(arg).to_uppercase();
+        let mut next = || {
+            taken += 1;
+            args.get(taken).copied().ok_or(RedisError::Syntax)
+        };
+        match option.as_str() {
+            "NOMKSTREAM" if allow_nomkstream => no_mkstream = true,
+            "MAXLEN" | "MINID" => {
+                if strategy.is_some() {
+                    return Err(RedisError::other("ERR syntax error, MAXLEN and MINID options at the same time are not compatible"));
+                }
+                let mut threshold = next()?;
+                if threshold == b"~" || threshold == b"=" {
+                    approximate = threshold == b"~";
+                    threshold = next()?;
+                }
+                strategy = Some(if option == "MAXLEN" {
+                    let max = usize::try_from(parse_integer(threshold)?)
+                        .map_err(|_| RedisError::other("ERR The MAXLEN argument must be >= 0."))?;
+                    TrimStrategy::MaxLen(max)
+                } else {
+                    TrimStrategy::MinId(parse_stream_id(threshold, 0)?)
+                });
+            }
+            "LIMIT" => {
+                let count = usize::try_from(parse_integer(next()?)?)
+                    .map_err(|_| RedisError::other("ERR The LIMIT argument must be >= 0."))?;
+                // Zero is how redis spells no limit.
+                limit = Some((count > 0).then_some(count));
+            }
+            _ => break,
+        }
+        taken += 1;
+    }
+    let trim = match (strategy, limit) {
+        (None, Some(_)) => {
+            return Err(RedisError::other(
+                "ERR syntax error, LIMIT cannot be used without specifying a trimming strategy",
+            ))
+        }
+        (Some(_), Some(_)) if !approximate => {
+            return Err(RedisError::other(
+                "ERR syntax error, LIMIT cannot be used without the special ~ option",
+            ))
+        }
+        (strategy, limit) => strategy.map(|strategy| StreamTrim {
+            strategy,
+            approximate,
+            limit: limit.flatten(),
+        }),
+    };
+    Ok((trim, no_mkstream, taken))
+}
+
+/// Parses the options of XCLAIM that follow its IDs.
+fn parse_claim_options(args: &[&[u8]]) -> Result<ClaimOptions, RedisError> {
+    let mut options = ClaimOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let option = String::from_utf8_lossy(arg).to_uppercase();
+        let mut next = || args.next().ok_or(RedisError::Syntax);
+        match option.as_str() {
+            "FORCE" => options.force = true,
+            "JUSTID" => options.just_id = true,
+            "IDLE" => {
+                let idle = parse_integer(next()?).map_err(|_| {
+                    RedisError::other("ERR Invalid IDLE option argument for XCLAIM")
+                })?;
+                options.idle = Some(idle.max(0));
+            }
+            "TIME" => {
+                let time = parse_integer(next()?).map_err(|_| {
+                    RedisError::other("ERR Invalid TIME option argument for XCLAIM")
+                })?;
+                options.time = Some(time);
+            }
+            "RETRYCOUNT" => {
+                let count = parse_integer(next()?).map_err(|_| {
+                    RedisError::other("ERR Invalid RETRYCOUNT option argument for XCLAIM")
+                })?;
+                options.retry_count = Some(count.max(0) as u64);
+            }
+            "LASTID" => options.last_id = Some(parse_stream_id(next()?, 0)?),
+            _ => {
+                return Err(RedisError::other(format!(
+                    "ERR Unrecognized XCLAIM option '{}'",
+                    String::from_utf8_lossy(arg)
+                )))
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// Turns a command name and its arguments, as `parse_request` returns them,
+/// into the command to run, once the command table has vouched for the name
+/// and the number of arguments. The command comes with its entry in the
+/// table, whose handler runs it.
+pub fn extract_commands(
+    request: Vec<Vec<u8>>,
+) -> Result<(&'static CommandSpec, RedisCommand), RedisError> {
+    let Some((command, args)) = request.split_first() else {
+        return Err(RedisError::UnknownCommand {
+            name: Vec::new(),
+            args: Vec::new(),
+        });
+    };
+    let name = String::from_utf8_lossy(command).to_uppercase();
+    let Some(spec) = command::lookup(&name) else {
+        return Err(RedisError::UnknownCommand {
+            name: command.clone(),
+            args: args.to_vec(),
+        });
+    };
+    if !spec.accepts(request.len()) {
+        return Err(wrong_arity(&name));
+    }
+    let args: Vec<&[u8]> = args.iter().map(Vec::as_slice).collect();
+    Ok((spec, (spec.parse)(&name, &args)?))
+}
+
+pub fn parse_ping(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    if args.len() > 1 {
+        return Err(wrong_arity(name));
+    }
+    let message = match args.first() {
+        Some(message) => RedisValue::BulkString(Some(message.to_vec())),
+        None => RedisValue::SimpleString("PONG".to_string()),
+    };
+    Ok(RedisCommand::PING(message))
+}
+
+pub fn parse_echo(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::ECHO(RedisValue::BulkString(Some(
+        args[0].to_vec(),
+    ))))
+}
+
+pub fn parse_get(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::GET(args[0].to_vec()))
+}
+
+pub fn parse_set(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let options = parse_set_options(&args[2..])?;
+    Ok(RedisCommand::SET(
+        args[0].to_vec(),
+        args[1].to_vec(),
+        options,
+    ))
+}
+
+pub fn parse_expire(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let key = args[0].to_vec();
+    let when = parse_integer(args[1])?;
+    let condition = parse_expire_condition(&args[2..])?;
+    Ok(match name {
+        "EXPIRE" => RedisCommand::EXPIRE(key, when, condition),
+        "PEXPIRE" => RedisCommand::PEXPIRE(key, when, condition),
+        "EXPIREAT" => RedisCommand::EXPIREAT(key, when, condition),
+        _ => RedisCommand::PEXPIREAT(key, when, condition),
+    })
+}
+
+pub fn parse_ttl(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let key = args[0].to_vec();
+    Ok(match name {
+        "TTL" => RedisCommand::TTL(key),
+        "PTTL" => RedisCommand::PTTL(key),
+        "EXPIRETIME" => RedisCommand::EXPIRETIME(key),
+        "PEXPIRETIME" => RedisCommand::PEXPIRETIME(key),
+        _ => RedisCommand::PERSIST(key),
+    })
+}
+
+pub fn parse_incr(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let delta = if name == "INCR" { 1 } else { -1 };
+    Ok(RedisCommand::INCRBY(args[0].to_vec(), delta))
+}
+
+pub fn parse_incrby(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let delta = parse_integer(args[1])?;
+    let delta = if name == "INCRBY" {
+        delta
+    } else {
+        delta
+            .checked_neg()
+            .ok_or(RedisError::other("ERR decrement would overflow"))?
+    };
+    Ok(RedisCommand::INCRBY(args[0].to_vec(), delta))
+}
+
+pub fn parse_incrbyfloat(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::INCRBYFLOAT(
+        args[0].to_vec(),
+        parse_float(args[1])?,
+    ))
+}
+
+pub fn parse_append(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::APPEND(args[0].to_vec(), args[1].to_vec()))
+}
+
+pub fn parse_strlen(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::STRLEN(args[0].to_vec()))
+}
+
+pub fn parse_getrange(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::GETRANGE(
+        args[0].to_vec(),
+        parse_integer(args[1])?,
+        parse_integer(args[2])?,
+    ))
+}
+
+pub fn parse_setrange(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let offset = usize::try_from(parse_integer(args[1])?)
+        .map_err(|_| RedisError::other("ERR offset is out of range"))?;
+    Ok(RedisCommand::SETRANGE(
+        args[0].to_vec(),
+        offset,
+        args[2].to_vec(),
+    ))
+}
+
+pub fn parse_setbit(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let value = match args[2] {
+        b"0" => false,
+        b"1" => true,
+        _ => {
+            return Err(RedisError::other(
+                "ERR bit is not an integer or out of range",
+            ))
+        }
+    };
+    Ok(RedisCommand::SETBIT(
+        args[0].to_vec(),
+        parse_bit_offset(args[1])?,
+        value,
+    ))
+}
+
+pub fn parse_getbit(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::GETBIT(
+        args[0].to_vec(),
+        parse_bit_offset(args[1])?,
+    ))
+}
+
+pub fn parse_bitcount(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let range = match args[..] {
+        [_] => None,
+        [_, start, end] => Some((parse_integer(start)?, parse_integer(end)?, BitUnit::Byte)),
+        [_, start, end, unit] => Some((
+            parse_integer(start)?,
+            parse_integer(end)?,
+            parse_bit_unit(unit)?,
+        )),
+        _ => return Err(RedisError::Syntax),
+    };
+    Ok(RedisCommand::BITCOUNT(args[0].to_vec(), range))
+}
+
+pub fn parse_bitpos(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    if args.len() > 5 {
+        return Err(RedisError::Syntax);
+    }
+    let bit = match parse_integer(args[1])? {
+        0 => false,
+        1 => true,
+        _ => return Err(RedisError::other("ERR The bit argument must be 1 or 0.")),
+    };
+    let start = args.get(2).map(|start| parse_integer(start)).transpose()?;
+    let end = args.get(3).map(|end| parse_integer(end)).transpose()?;
+    let unit = args.get(4).map(|unit| parse_bit_unit(unit)).transpose()?;
+    Ok(RedisCommand::BITPOS(
+        args[0].to_vec(),
+        bit,
+        start.unwrap_or(0),
+        end,
+        unit.unwrap_or_default(),
+    ))
+}
+
+pub fn parse_bitop(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let op = match String::from_utf8_lossy(args[0]).to_uppercase().as_str() {
+        "AND" => BitOp::And,
+        "OR" => BitOp::Or,
+        "XOR" => BitOp::Xor,
+        "NOT" => BitOp::Not,
+        _ => return Err(RedisError::Syntax),
+    };
+    if op == BitOp::Not && args.len() != 3 {
+        return Err(RedisError::other(
+            "ERR BITOP NOT must be called with a single source key.",
+        ));
+    }
+    Ok(RedisCommand::BITOP(
+        op,
+        args[1].to_vec(),
+        args[2..].iter().map(|key| key.to_vec()).collect(),
+    ))
+}
+
+pub fn parse_bitfield(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::BITFIELD(
+        args[0].to_vec(),
+        parse_bitfield_ops(&args[1..], name == "BITFIELD_RO")?,
+    ))
+}
+
+pub fn parse_mget(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::MGET(
+        args.iter().map(|key| key.to_vec()).collect(),
+    ))
+}
+
+pub fn parse_mset(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    if !args.len().is_multiple_of(2) {
+        return Err(wrong_arity(name));
+    }
+    let pairs = args
+        .chunks(2)
+        .map(|pair| (pair[0].to_vec(), pair[1].to_vec()))
+        .collect();
+    Ok(if name == "MSET" {
+        RedisCommand::MSET(pairs)
+    } else {
+        RedisCommand::MSETNX(pairs)
+    })
+}
+
+pub fn parse_del(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let keys = args.iter().map(|key| key.to_vec()).collect();
+    Ok(match name {
+        "EXISTS" => RedisCommand::EXISTS(keys),
+        "TOUCH" => RedisCommand::TOUCH(keys),
+        // Values are freed in place either way, so UNLINK is DEL.
+        _ => RedisCommand::DEL(keys),
+    })
+}
+
+pub fn parse_type(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::TYPE(args[0].to_vec()))
+}
+
+pub fn parse_rename(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let (src, dst) = (args[0].to_vec(), args[1].to_vec());
+    Ok(if name == "RENAME" {
+        RedisCommand::RENAME(src, dst)
+    } else {
+        RedisCommand::RENAMENX(src, dst)
+    })
+}
+
+pub fn parse_copy(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let mut db = None;
+    let mut replace = false;
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        match String::from_utf8_lossy(option).to_uppercase().as_str() {
+            "REPLACE" => replace = true,
+            "DB" => {
+                let index = options.next().ok_or(RedisError::Syntax)?;
+                db = Some(parse_integer(index)?);
+            }
+            _ => return Err(RedisError::Syntax),
+        }
+    }
+    Ok(RedisCommand::COPY(
+        args[0].to_vec(),
+        args[1].to_vec(),
+        db,
+        replace,
+    ))
+}
+
+pub fn parse_randomkey(name: &str, _args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(if name == "RANDOMKEY" {
+        RedisCommand::RANDOMKEY
+    } else {
+        RedisCommand::DBSIZE
+    })
+}
+
+pub fn parse_flushdb(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    // Flushing is always synchronous, but the modes are accepted for
+    // compatibility with clients that pass them.
+    match args[..] {
+        [] => {}
+        [mode] if mode.eq_ignore_ascii_case(b"ASYNC") || mode.eq_ignore_ascii_case(b"SYNC") => {}
+        _ => return Err(RedisError::Syntax),
+    }
+    Ok(if name == "FLUSHDB" {
+        RedisCommand::FLUSHDB
+    } else {
+        RedisCommand::FLUSHALL
+    })
+}
+
+pub fn parse_keys(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::KEYS(args[0].to_vec()))
+}
+
+pub fn parse_scan(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let cursor = parse_cursor(args[0])?;
+    Ok(RedisCommand::SCAN(
+        cursor,
+        parse_scan_options(&args[1..], true)?,
+    ))
+}
+
+pub fn parse_lpush(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let key = args[0].to_vec();
+    let elements = args[1..].iter().map(|element| element.to_vec()).collect();
+    Ok(if name == "LPUSH" {
+        RedisCommand::LPUSH(key, elements)
+    } else {
+        RedisCommand::RPUSH(key, elements)
+    })
+}
+
+pub fn parse_lpop(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    if args.len() > 2 {
+        return Err(wrong_arity(name));
+    }
+    let count = match args.get(1) {
+        Some(count) => {
+            let count = parse_integer(count)?;
+            if count < 0 {
+                return Err(RedisError::other(
+                    "ERR value is out of range, must be positive",
+                ));
+            }
+            Some(count as usize)
+        }
+        None => None,
+    };
+    let key = args[0].to_vec();
+    Ok(if name == "LPOP" {
+        RedisCommand::LPOP(key, count)
+    } else {
+        RedisCommand::RPOP(key, count)
+    })
+}
+
+pub fn parse_lrange(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let key = args[0].to_vec();
+    let (start, end) = (parse_integer(args[1])?, parse_integer(args[2])?);
+    Ok(if name == "LRANGE" {
+        RedisCommand::LRANGE(key, start, end)
+    } else {
+        RedisCommand::LTRIM(key, start, end)
+    })
+}
+
+pub fn parse_lindex(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::LINDEX(
+        args[0].to_vec(),
+        parse_integer(args[1])?,
+    ))
+}
+
+pub fn parse_lset(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let (key, element) = (args[0].to_vec(), args[2].to_vec());
+    let number = parse_integer(args[1])?;
+    Ok(if name == "LSET" {
+        RedisCommand::LSET(key, number, element)
+    } else {
+        RedisCommand::LREM(key, number, element)
+    })
+}
+
+pub fn parse_linsert(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let before = match String::from_utf8_lossy(args[1]).to_uppercase().as_str() {
+        "BEFORE" => true,
+        "AFTER" => false,
+        _ => return Err(RedisError::Syntax),
+    };
+    Ok(RedisCommand::LINSERT(
+        args[0].to_vec(),
+        before,
+        args[2].to_vec(),
+        args[3].to_vec(),
+    ))
+}
+
+pub fn parse_llen(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::LLEN(args[0].to_vec()))
+}
+
+pub fn parse_lmove(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::LMOVE(
+        args[0].to_vec(),
+        args[1].to_vec(),
+        parse_list_end(args[2])?,
+        parse_list_end(args[3])?,
+    ))
+}
+
+pub fn parse_blpop(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let (timeout, keys) = args.split_last().expect("arguments were just counted");
+    let keys = keys.iter().map(|key| key.to_vec()).collect();
+    let timeout = parse_timeout(timeout)?;
+    Ok(match name {
+        "BLPOP" => RedisCommand::BLPOP(keys, timeout),
+        "BRPOP" => RedisCommand::BRPOP(keys, timeout),
+        "BZPOPMIN" => RedisCommand::BZPOPMIN(keys, timeout),
+        _ => RedisCommand::BZPOPMAX(keys, timeout),
+    })
+}
+
+pub fn parse_blmove(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::BLMOVE(
+        args[0].to_vec(),
+        args[1].to_vec(),
+        parse_list_end(args[2])?,
+        parse_list_end(args[3])?,
+        parse_timeout(args[4])?,
+    ))
+}
+
+pub fn parse_lpos(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::LPOS(
+        args[0].to_vec(),
+        args[1].to_vec(),
+        parse_list_position_options(&args[2..])?,
+    ))
+}
+
+pub fn parse_hset(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    if args.len() % 2 != 1 {
+        return Err(wrong_arity(name));
+    }
+    let pairs = args[1..]
+        .chunks(2)
+        .map(|pair| (pair[0].to_vec(), pair[1].to_vec()))
+        .collect();
+    Ok(RedisCommand::HSET(args[0].to_vec(), pairs))
+}
+
+pub fn parse_hget(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let (key, field) = (args[0].to_vec(), args[1].to_vec());
+    Ok(if name == "HGET" {
+        RedisCommand::HGET(key, field)
+    } else {
+        RedisCommand::HEXISTS(key, field)
+    })
+}
+
+pub fn parse_hmget(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let key = args[0].to_vec();
+    let fields = args[1..].iter().map(|field| field.to_vec()).collect();
+    Ok(if name == "HMGET" {
+        RedisCommand::HMGET(key, fields)
+    } else {
+        RedisCommand::HDEL(key, fields)
+    })
+}
+
+pub fn parse_hgetall(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let key = args[0].to_vec();
+    Ok(match name {
+        "HGETALL" => RedisCommand::HGETALL(key),
+        "HKEYS" => RedisCommand::HKEYS(key),
+        "HVALS" => RedisCommand::HVALS(key),
+        _ => RedisCommand::HLEN(key),
+    })
+}
+
+pub fn parse_hincrby(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::HINCRBY(
+        args[0].to_vec(),
+        args[1].to_vec(),
+        parse_integer(args[2])?,
+    ))
+}
+
+pub fn parse_hincrbyfloat(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::HINCRBYFLOAT(
+        args[0].to_vec(),
+        args[1].to_vec(),
+        parse_float(args[2])?,
+    ))
+}
+
+pub fn parse_hscan(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::HSCAN(
+        args[0].to_vec(),
+        parse_cursor(args[1])?,
+        parse_scan_options(&args[2..], false)?,
+    ))
+}
+
+pub fn parse_hrandfield(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    if args.len() > 3 {
+        return Err(wrong_arity(name));
+    }
+    let count = args
+        .get(1)
+        .map(|count| parse_random_count(count))
+        .transpose()?;
+    let with_values = match args.get(2) {
+        Some(arg) if arg.eq_ignore_ascii_case(b"WITHVALUES") => true,
+        Some(_) => return Err(RedisError::Syntax),
+        None => false,
+    };
+    Ok(RedisCommand::HRANDFIELD(
+        args[0].to_vec(),
+        count,
+        with_values,
+    ))
+}
+
+pub fn parse_sadd(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let key = args[0].to_vec();
+    let members = args[1..].iter().map(|member| member.to_vec()).collect();
+    Ok(match name {
+        "SADD" => RedisCommand::SADD(key, members),
+        "SREM" => RedisCommand::SREM(key, members),
+        _ => RedisCommand::SMISMEMBER(key, members),
+    })
+}
+
+pub fn parse_sismember(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::SISMEMBER(args[0].to_vec(), args[1].to_vec()))
+}
+
+pub fn parse_smembers(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let key = args[0].to_vec();
+    Ok(if name == "SMEMBERS" {
+        RedisCommand::SMEMBERS(key)
+    } else {
+        RedisCommand::SCARD(key)
+    })
+}
+
+pub fn parse_spop(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    if args.len() > 2 {
+        return Err(wrong_arity(name));
+    }
+    let count = match args.get(1) {
+        Some(count) => {
+            let count = parse_integer(count)?;
+            if count < 0 {
+                return Err(RedisError::other(
+                    "ERR value is out of range, must be positive",
+                ));
+            }
+            Some(count as usize)
+        }
+        None => None,
+    };
+    Ok(RedisCommand::SPOP(args[0].to_vec(), count))
+}
+
+pub fn parse_srandmember(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    if args.len() > 2 {
+        return Err(wrong_arity(name));
+    }
+    let count = args
+        .get(1)
+        .map(|count| parse_random_count(count))
+        .transpose()?;
+    Ok(RedisCommand::SRANDMEMBER(args[0].to_vec(), count))
+}
+
+pub fn parse_sinter(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let keys = args.iter().map(|key| key.to_vec()).collect();
+    Ok(match name {
+        "SINTER" => RedisCommand::SINTER(keys),
+        "SUNION" => RedisCommand::SUNION(keys),
+        _ => RedisCommand::SDIFF(keys),
+    })
+}
+
+pub fn parse_sinterstore(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let dst = args[0].to_vec();
+    let keys = args[1..].iter().map(|key| key.to_vec()).collect();
+    Ok(match name {
+        "SINTERSTORE" => RedisCommand::SINTERSTORE(dst, keys),
+        "SUNIONSTORE" => RedisCommand::SUNIONSTORE(dst, keys),
+        _ => RedisCommand::SDIFFSTORE(dst, keys),
+    })
+}
+
+pub fn parse_sintercard(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let numkeys = parse_integer(args[0])?;
+    if numkeys <= 0 {
+        return Err(RedisError::other("ERR numkeys should be greater than 0"));
+    }
+    let numkeys = numkeys as usize;
+    if numkeys > args.len() - 1 {
+        return Err(RedisError::other(
+            "ERR Number of keys can't be greater than number of args",
+        ));
+    }
+    let keys = args[1..=numkeys].iter().map(|key| key.to_vec()).collect();
+    let limit = match args[numkeys + 1..] {
+        [] => 0,
+        [option, limit] if option.eq_ignore_ascii_case(b"LIMIT") => {
+            let limit = parse_integer(limit)?;
+            if limit < 0 {
+                return Err(RedisError::other("ERR LIMIT can't be negative"));
+            }
+            limit as usize
+        }
+        _ => return Err(RedisError::Syntax),
+    };
+    Ok(RedisCommand::SINTERCARD(keys, limit))
+}
+
+pub fn parse_sscan(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::SSCAN(
+        args[0].to_vec(),
+        parse_cursor(args[1])?,
+        parse_scan_options(&args[2..], false)?,
+    ))
+}
+
+pub fn parse_zadd(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let (options, incr, taken) = parse_zadd_options(&args[1..])?;
+    let pairs = &args[1 + taken..];
+    if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+        return Err(RedisError::Syntax);
+    }
+    let key = args[0].to_vec();
+    if incr {
+        if pairs.len() != 2 {
+            return Err(RedisError::other(
+                "ERR INCR option supports a single increment-element pair",
+            ));
+        }
+        return Ok(RedisCommand::ZADDINCR(
+            key,
+            parse_score(pairs[0])?,
+            pairs[1].to_vec(),
+            options,
+        ));
+    }
+    let entries = pairs
+        .chunks(2)
+        .map(|pair| Ok((parse_score(pair[0])?, pair[1].to_vec())))
+        .collect::<Result<_, RedisError>>()?;
+    Ok(RedisCommand::ZADD(key, entries, options))
+}
+
+pub fn parse_zrange(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::ZRANGE(
+        args[0].to_vec(),
+        parse_zrange_options(&args[1..])?,
+    ))
+}
+
+pub fn parse_zrank(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let with_score = match args[..] {
+        [_, _] => false,
+        [_, _, option] if option.eq_ignore_ascii_case(b"WITHSCORE") => true,
+        [_, _, _] => return Err(RedisError::Syntax),
+        _ => return Err(wrong_arity(name)),
+    };
+    Ok(RedisCommand::ZRANK(
+        args[0].to_vec(),
+        args[1].to_vec(),
+        with_score,
+    ))
+}
+
+pub fn parse_zscore(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::ZSCORE(args[0].to_vec(), args[1].to_vec()))
+}
+
+pub fn parse_zincrby(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::ZINCRBY(
+        args[0].to_vec(),
+        parse_score(args[1])?,
+        args[2].to_vec(),
+    ))
+}
+
+pub fn parse_zrem(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::ZREM(
+        args[0].to_vec(),
+        args[1..].iter().map(|member| member.to_vec()).collect(),
+    ))
+}
+
+pub fn parse_zcard(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::ZCARD(args[0].to_vec()))
+}
+
+pub fn parse_zpopmin(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    if args.len() > 2 {
+        return Err(wrong_arity(name));
+    }
+    let count = match args.get(1) {
+        Some(count) => {
+            let count = parse_integer(count)?;
+            if count < 0 {
+                return Err(RedisError::other(
+                    "ERR value is out of range, must be positive",
+                ));
+            }
+            Some(count as usize)
+        }
+        None => None,
+    };
+    let key = args[0].to_vec();
+    Ok(if name == "ZPOPMIN" {
+        RedisCommand::ZPOPMIN(key, count)
+    } else {
+        RedisCommand::ZPOPMAX(key, count)
+    })
+}
+
+pub fn parse_zunionstore(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let numkeys = parse_integer(args[1])?;
+    if numkeys < 1 {
+        return Err(RedisError::other(format!(
+            "ERR at least 1 input key is needed for '{}' command",
+            name.to_lowercase()
+        )));
+    }
+    let numkeys = numkeys as usize;
+    if numkeys > args.len() - 2 {
+        return Err(RedisError::Syntax);
+    }
+    let dst = args[0].to_vec();
+    let keys = args[2..2 + numkeys]
+        .iter()
+        .map(|key| key.to_vec())
+        .collect();
+    let (weights, aggregate) = parse_zstore_options(&args[2 + numkeys..], numkeys)?;
+    Ok(if name == "ZUNIONSTORE" {
+        RedisCommand::ZUNIONSTORE(dst, keys, weights, aggregate)
+    } else {
+        RedisCommand::ZINTERSTORE(dst, keys, weights, aggregate)
+    })
+}
+
+pub fn parse_xadd(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let (trim, no_mkstream, taken) = parse_stream_trim(&args[1..], true)?;
+    let rest = &args[1 + taken..];
+    if rest.len() < 3 || rest.len() % 2 != 1 {
+        return Err(wrong_arity(name));
+    }
+    let id = match rest[0] {
+        b"*" => StreamAddId::Auto,
+        id => match id.strip_suffix(b"-*") {
+            Some(ms) => StreamAddId::AutoSeq(parse_stream_id_part(ms)?),
+            None => StreamAddId::Explicit(parse_stream_id(id, 0)?),
+        },
+    };
+    let pairs = rest[1..]
+        .chunks(2)
+        .map(|pair| (pair[0].to_vec(), pair[1].to_vec()))
+        .collect();
+    Ok(RedisCommand::XADD(
+        args[0].to_vec(),
+        id,
+        pairs,
+        no_mkstream,
+        trim,
+    ))
+}
+
+pub fn parse_xrange(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let (start, end) = if name == "XRANGE" {
+        (args[1], args[2])
+    } else {
+        (args[2], args[1])
+    };
+    let (start, end) = (
+        parse_stream_bound(start, true)?,
+        parse_stream_bound(end, false)?,
+    );
+    let count = match &args[3..] {
+        [] => None,
+        [option, count] if option.eq_ignore_ascii_case(b"COUNT") => {
+            // Unlike for XREAD, a count of zero or less asks for no
+            // entries at all.
+            Some(usize::try_from(parse_integer(count)?).unwrap_or(0))
+        }
+        _ => return Err(RedisError::Syntax),
+    };
+    let key = args[0].to_vec();
+    Ok(if name == "XRANGE" {
+        RedisCommand::XRANGE(key, start, end, count)
+    } else {
+        RedisCommand::XREVRANGE(key, start, end, count)
+    })
+}
+
+pub fn parse_xlen(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::XLEN(args[0].to_vec()))
+}
+
+pub fn parse_xtrim(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    match parse_stream_trim(&args[1..], false)? {
+        (Some(trim), _, taken) if taken == args.len() - 1 => {
+            Ok(RedisCommand::XTRIM(args[0].to_vec(), trim))
+        }
+        _ => Err(RedisError::Syntax),
+    }
+}
+
+pub fn parse_xdel(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let ids = args[1..]
+        .iter()
+        .map(|id| parse_stream_id(id, 0))
+        .collect::<Result<_, _>>()?;
+    Ok(RedisCommand::XDEL(args[0].to_vec(), ids))
+}
+
+pub fn parse_xread(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let read_group = name == "XREADGROUP";
+    let mut group = None;
+    let mut count = None;
+    let mut no_ack = false;
+    let mut block = None;
+    let mut index = 0;
+    let streams = loop {
+        let Some(arg) = args.get(index) else {
+            return Err(RedisError::Syntax);
+        };
+        let remaining = args.len() - index - 1;
+        match String::from_utf8_lossy(arg).to_uppercase().as_str() {
+            "COUNT" if remaining >= 1 => {
+                count = parse_stream_count(args[index + 1])?;
+                index += 2;
+            }
+            "GROUP" if read_group && remaining >= 2 => {
+                group = Some((args[index + 1].to_vec(), args[index + 2].to_vec()));
+                index += 3;
+            }
+            "NOACK" if read_group => {
+                no_ack = true;
+                index += 1;
+            }
+            "BLOCK" if remaining >= 1 => {
+                let timeout = parse_integer(args[index + 1])?;
+                if timeout < 0 {
+                    return Err(RedisError::other("ERR timeout is negative"));
+                }
+                block = Some(Duration::from_millis(timeout as u64));
+                index += 2;
+            }
+            "STREAMS" => break &args[index + 1..],
+            _ => return Err(RedisError::Syntax),
+        }
+    };
+    if streams.is_empty() || streams.len() % 2 != 0 {
+        return Err(RedisError::other(format!(
+            "ERR Unbalanced '{}' list of streams: for each stream key an ID or '{}' must be specified.",
+            name.to_lowercase(),
+            if read_group { ">" } else { "$" })));
+    }
+    let (keys, ids) = streams.split_at(streams.len() / 2);
+    let mut streams = Vec::new();
+    for (key, id) in keys.iter().zip(ids) {
+        let id = match *id {
+            b"$" if read_group => return Err(RedisError::other("ERR The $ ID is meaningless in the context of XREADGROUP: you want to read the history of this consumer by specifying a proper ID, or use the > ID to get new messages. The $ ID would just return an empty result set.")),
+            b">" if !read_group => return Err(RedisError::other("ERR The > ID can be specified only when calling XREADGROUP using the GROUP <group> <consumer> option.")),
+            b"$" | b">" => None,
+            id => Some(parse_stream_id(id, 0)?),
+        };
+        streams.push((key.to_vec(), id));
+    }
+    if !read_group {
+        return Ok(RedisCommand::XREAD(streams, count, block));
+    }
+    let (group, consumer) =
+        group.ok_or(RedisError::other("ERR Missing GROUP option for XREADGROUP"))?;
+    Ok(RedisCommand::XREADGROUP(
+        group, consumer, streams, count, no_ack, block,
+    ))
+}
+
+pub fn parse_xgroup(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let subcommand = String::from_utf8_lossy(args[0]).to_uppercase();
+    let arity = match subcommand.as_str() {
+        "CREATE" => args.len() >= 4 && args.len() <= 5,
+        "SETID" | "CREATECONSUMER" | "DELCONSUMER" => args.len() == 4,
+        "DESTROY" => args.len() == 3,
+        _ => {
+            return Err(RedisError::other(format!(
+                "ERR unknown subcommand '{}'. Try XGROUP HELP.",
+                String::from_utf8_lossy(args[0])
+            )))
+        }
+    };
+    if !arity {
+        return Err(wrong_arity(&format!("{}|{}", name, subcommand)));
+    }
+    let (key, group) = (args[1].to_vec(), args[2].to_vec());
+    let id = || match args[3] {
+        b"$" => Ok(None),
+        id => parse_stream_id(id, 0).map(Some),
+    };
+    Ok(match subcommand.as_str() {
+        "CREATE" => {
+            let mkstream = match args.get(4) {
+                Some(option) if option.eq_ignore_ascii_case(b"MKSTREAM") => true,
+                Some(_) => return Err(RedisError::Syntax),
+                None => false,
+            };
+            RedisCommand::XGROUPCREATE(key, group, id()?, mkstream)
+        }
+        "SETID" => RedisCommand::XGROUPSETID(key, group, id()?),
+        "DESTROY" => RedisCommand::XGROUPDESTROY(key, group),
+        "CREATECONSUMER" => RedisCommand::XGROUPCREATECONSUMER(key, group, args[3].to_vec()),
+        _ => RedisCommand::XGROUPDELCONSUMER(key, group, args[3].to_vec()),
+    })
+}
+
+pub fn parse_xack(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let ids = args[2..]
+        .iter()
+        .map(|id| parse_stream_id(id, 0))
+        .collect::<Result<_, _>>()?;
+    Ok(RedisCommand::XACK(args[0].to_vec(), args[1].to_vec(), ids))
+}
+
+pub fn parse_xpending(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let (key, group) = (args[0].to_vec(), args[1].to_vec());
+    let mut rest = &args[2..];
+    if rest.is_empty() {
+        return Ok(RedisCommand::XPENDING(key, group, None));
+    }
+    let mut min_idle = 0;
+    if rest[0].eq_ignore_ascii_case(b"IDLE") && rest.len() > 1 {
+        min_idle = parse_integer(rest[1])?;
+        rest = &rest[2..];
+    }
+    if rest.len() != 3 && rest.len() != 4 {
+        return Err(RedisError::Syntax);
+    }
+    let range = PendingRange {
+        min_idle,
+        start: parse_stream_bound(rest[0], true)?,
+        end: parse_stream_bound(rest[1], false)?,
+        count: usize::try_from(parse_integer(rest[2])?).unwrap_or(0),
+        consumer: rest.get(3).map(|consumer| consumer.to_vec()),
+    };
+    Ok(RedisCommand::XPENDING(key, group, Some(range)))
+}
+
+pub fn parse_xclaim(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let min_idle = parse_integer(args[3])
+        .map_err(|_| RedisError::other("ERR Invalid min-idle-time argument for XCLAIM"))?;
+    // The IDs run up to the first argument that is not one.
+    let ids: Vec<StreamId> = args[4..]
+        .iter()
+        .map_while(|id| parse_stream_id(id, 0).ok())
+        .collect();
+    if ids.is_empty() {
+        return Err(RedisError::other(ERR_STREAM_ID));
+    }
+    let options = parse_claim_options(&args[4 + ids.len()..])?;
+    Ok(RedisCommand::XCLAIM(
+        args[0].to_vec(),
+        args[1].to_vec(),
+        args[2].to_vec(),
+        min_idle.max(0),
+        ids,
+        options,
+    ))
+}
+
+pub fn parse_xautoclaim(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let min_idle = parse_integer(args[3])
+        .map_err(|_| RedisError::other("ERR Invalid min-idle-time argument for XAUTOCLAIM"))?;
+    let start = parse_stream_bound(args[4], true)?;
+    let mut count = 100;
+    let mut just_id = false;
+    let mut options = args[5..].iter();
+    while let Some(option) = options.next() {
+        match String::from_utf8_lossy(option).to_uppercase().as_str() {
+            "COUNT" => {
+                let arg = options.next().ok_or(RedisError::Syntax)?;
+                count = usize::try_from(parse_integer(arg)?)
+                    .ok()
+                    .filter(|count| *count > 0)
+                    .ok_or(RedisError::other("ERR COUNT must be > 0"))?;
+            }
+            "JUSTID" => just_id = true,
+            _ => return Err(RedisError::Syntax),
+        }
+    }
+    Ok(RedisCommand::XAUTOCLAIM(
+        args[0].to_vec(),
+        args[1].to_vec(),
+        args[2].to_vec(),
+        min_idle.max(0),
+        start,
+        count,
+        just_id,
+    ))
+}
+
+pub fn parse_select(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::SELECT(parse_integer(args[0])?))
+}
+
+pub fn parse_move(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::MOVE(
+        args[0].to_vec(),
+        parse_integer(args[1])?,
+    ))
+}
+
+pub fn parse_swapdb(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let first =
+        parse_integer(args[0]).map_err(|_| RedisError::other("ERR invalid first DB index"))?;
+    let second =
+        parse_integer(args[1]).map_err(|_| RedisError::other("ERR invalid second DB index"))?;
+    Ok(RedisCommand::SWAPDB(first, second))
+}
+
+pub fn parse_hello(_name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let Some((version, options)) = args.split_first() else {
+        return Ok(RedisCommand::HELLO(None));
+    };
+    let protocol = match parse_integer(version) {
+        Ok(2) => Protocol::Resp2,
+        Ok(3) => Protocol::Resp3,
+        Ok(_) => return Err(RedisError::other("NOPROTO unsupported protocol version")),
+        Err(_) => {
+            return Err(RedisError::other(
+                "ERR Protocol version is not an integer or out of range",
+            ))
+        }
+    };
+    // There are no users to authenticate as nor client names to keep,
+    // so AUTH and SETNAME are only checked, like redis does with the
+    // default user which needs no password.
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match String::from_utf8_lossy(option).to_uppercase().as_str() {
+            "AUTH" if options.len() >= 2 => {
+                options.nth(1);
             }
-            b'P' => {
-                // Pre resp PING_INLINE
-                let mut result = Vec::new();
-                for _ in 0..6 {
-                    result.push(*iter.next().ok_or(anyhow!("Unexpected end of input"))?);
-                }
-                let parsed = String::from_utf8(result)?;
-                if parsed == "PING\r\n" {
-                    return Ok(Some(RedisValue::Array(Some(vec![RedisValue::SimpleString(
-                        "PING".to_string(),
-                    )]))));
+            "SETNAME" if !options.as_slice().is_empty() => {
+                let name = options.next().expect("checked above");
+                if name.iter().any(|&byte| !(b'!'..=b'~').contains(&byte)) {
+                    return Err(RedisError::other(
+                        "ERR Client names cannot contain spaces, newlines or special characters.",
+                    ));
                 }
             }
             _ => {
-                return Ok(None);
+                return Err(RedisError::other(format!(
+                    "ERR Syntax error in HELLO option '{}'",
+                    String::from_utf8_lossy(option)
+                )))
             }
         }
     }
-    Ok(None)
-}
-
-pub fn extract_commands(buffer: &[u8]) -> Result<RedisCommand, anyhow::Error> {
-    let parsed = parse_resp(buffer)?;
-    match parsed {
-        Some(RedisValue::Array(Some(array))) => {
-            let command = &array[0];
-            let args = &array[1..];
-            match command {
-                RedisValue::SimpleString(s) | RedisValue::BulkString(Some(s)) => {
-                    match s.to_uppercase().as_str() {
-                        "PING" => {
-                            if args.len() > 1 {
-                                return Err(anyhow!("Invalid number of arguments for PING"));
-                            }
-                            let message = if args.len() == 1 {
-                                match &args[0] {
-                                    RedisValue::SimpleString(s)
-                                    | RedisValue::BulkString(Some(s)) => {
-                                        RedisValue::BulkString(Some(s.clone()))
-                                    }
-                                    _ => return Err(anyhow!("Invalid argument for PING")),
-                                }
-                            } else {
-                                RedisValue::SimpleString("PONG".to_string())
-                            };
-                            Ok(RedisCommand::PING(message))
-                        }
-                        "ECHO" => {
-                            if args.len() != 1 {
-                                return Err(anyhow!("Invalid number of arguments for ECHO"));
-                            }
-                            let message = match &args[0] {
-                                RedisValue::SimpleString(s)
-                                | RedisValue::BulkString(Some(s)) => RedisValue::BulkString(Some(s.clone())),
-                                _ => return Err(anyhow!("Invalid argument for ECHO")),
-                            };
-                            Ok(RedisCommand::ECHO(message))
-                        }
-                        "GET" => {
-                            if args.len() != 1 {
-                                return Err(anyhow!("Invalid number of arguments for GET"));
-                            }
-                            let key = match &args[0] {
-                                RedisValue::SimpleString(s)
-                                | RedisValue::BulkString(Some(s)) => RedisValue::BulkString(Some(s.clone())),
-                                _ => return Err(anyhow!("Invalid argument for GET")),
-                            };
-                            Ok(RedisCommand::GET(key))
-                        }
-                        "SET" => {
-                            if args.len() < 2 {
-                                return Err(anyhow!("Invalid number of arguments for SET"));
-                            }
-                            let key = match &args[0] {
-                                RedisValue::SimpleString(s)
-                                | RedisValue::BulkString(Some(s)) => RedisValue::BulkString(Some(s.clone())),
-                                _ => return Err(anyhow!("Invalid argument for SET")),
-                            };
-                            let value = match &args[1] {
-                                RedisValue::SimpleString(s)
-                                | RedisValue::BulkString(Some(s)) => RedisValue::BulkString(Some(s.clone())),
-                                _ => return Err(anyhow!("Invalid argument for SET")),
-                            };
-                            let additional_args = &mut args[2..].iter();
-                            let mut expiry = None;
-                            while let Some(arg) = additional_args.next() {
-                              match arg {
-                                  RedisValue::SimpleString(s) | RedisValue::BulkString(Some(s)) => {
-                                    match s.to_uppercase().as_str() {
-                                        "EX" => {
-                                            let arg = additional_args.next().ok_or(anyhow!("Invalid number of arguments for SET"))?;
-                                            match arg {
-                                                RedisValue::SimpleString(s) | RedisValue::BulkString(Some(s)) => {
-                                                    expiry = Some(s.parse::<u64>()? * 1000);
-                                                }
-                                                _ => return Err(anyhow!("Invalid argument for SET")),
-                                            }
-                                        }
-                                        "PX" => {
-                                            let arg = additional_args.next().ok_or(anyhow!("Invalid number of arguments for SET"))?;
-                                            match arg {
-                                                RedisValue::SimpleString(s) | RedisValue::BulkString(Some(s)) => {
-                                                    expiry = Some(s.parse::<u64>()?);
-                                                }
-                                                _ => return Err(anyhow!("Invalid argument for SET")),
-                                            }
-                                        }
-                                        _ => return Err(anyhow!("Invalid argument for SET")),
-                                    }
-                                  }
-                                  _ => return Err(anyhow!("Invalid argument for SET")),
-                              }
-                            }
-                            Ok(RedisCommand::SET(key, value, expiry))
-                        }
-                        "CONFIG" => Ok(RedisCommand::CONFIG),
-                        "COMMAND" => Ok(RedisCommand::COMMAND),
-                        _ => Err(anyhow!("Unknown command")),
-                    }
-                }
-                _ => {
-                    Err(anyhow!("Invalid command in matching"))
-                }
-            }
+    Ok(RedisCommand::HELLO(Some(protocol)))
+}
+
+pub fn parse_config(_name: &str, _args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    Ok(RedisCommand::CONFIG)
+}
+
+pub fn parse_command(name: &str, args: &[&[u8]]) -> Result<RedisCommand, RedisError> {
+    let Some(subcommand) = args.first() else {
+        return Ok(RedisCommand::COMMANDINFO(Vec::new()));
+    };
+    let subcommand = String::from_utf8_lossy(subcommand).to_uppercase();
+    let arity = match subcommand.as_str() {
+        "COUNT" => args.len() == 1,
+        "INFO" | "DOCS" | "LIST" => true,
+        "GETKEYS" => args.len() >= 2,
+        _ => {
+            return Err(RedisError::other(format!(
+                "ERR unknown subcommand '{}'. Try COMMAND HELP.",
+                String::from_utf8_lossy(args[0])
+            )))
         }
-        None => Err(anyhow!("Invalid command parsed a None")),
-        _ => Err(anyhow!("Invalid command couldnt match")),
+    };
+    if !arity {
+        return Err(wrong_arity(&format!("{}|{}", name, subcommand)));
     }
+    let rest = || args[1..].iter().map(|arg| arg.to_vec()).collect();
+    let command = match subcommand.as_str() {
+        "COUNT" => RedisCommand::COMMANDCOUNT,
+        "INFO" => RedisCommand::COMMANDINFO(rest()),
+        "DOCS" => RedisCommand::COMMANDDOCS(rest()),
+        "GETKEYS" => RedisCommand::COMMANDGETKEYS(rest()),
+        _ => {
+            let filter = match args[1..] {
+                [] => None,
+                [filterby, kind, value] if filterby.eq_ignore_ascii_case(b"FILTERBY") => Some(
+                    match String::from_utf8_lossy(kind).to_uppercase().as_str() {
+                        "MODULE" => CommandFilter::Module(value.to_vec()),
+                        "ACLCAT" => CommandFilter::AclCategory(value.to_vec()),
+                        "PATTERN" => CommandFilter::Pattern(value.to_vec()),
+                        _ => return Err(RedisError::Syntax),
+                    },
+                ),
+                _ => return Err(RedisError::Syntax),
+            };
+            RedisCommand::COMMANDLIST(filter)
+        }
+    };
+    Ok(command)
 }
 
 #[cfg(test)]
 mod tests {
+    use super::decode::parse_resp;
     use super::*;
 
     fn test_parse_resp(input: &[u8], expected: Option<RedisValue>) {
-        assert_eq!(parse_resp(input).unwrap(), expected);
+        assert_eq!(
+            parse_resp(input).unwrap(),
+            expected.map(|value| (value, input.len()))
+        );
     }
 
     #[test]
@@ -381,15 +2357,27 @@ mod tests {
     fn test_parse_resp_bulk_string() {
         test_parse_resp(
             b"$6\r\nfoobar\r\n",
-            Some(RedisValue::BulkString(Some("foobar".to_string()))),
+            Some(RedisValue::BulkString(Some(b"foobar".to_vec()))),
         );
         test_parse_resp(
             b"$0\r\n\r\n",
-            Some(RedisValue::BulkString(Some("".to_string()))),
+            Some(RedisValue::BulkString(Some(b"".to_vec()))),
         );
         test_parse_resp(b"$-1\r\n", Some(RedisValue::BulkString(None)));
     }
 
+    #[test]
+    fn test_parse_resp_binary_bulk_string() {
+        test_parse_resp(
+            b"$4\r\n\x00\xff\r\n\r\n",
+            Some(RedisValue::BulkString(Some(b"\x00\xff\r\n".to_vec()))),
+        );
+        assert_eq!(
+            RedisValue::BulkString(Some(b"\x00\xff\r\n".to_vec())).to_resp_bytes(Protocol::Resp2),
+            b"$4\r\n\x00\xff\r\n\r\n".to_vec()
+        );
+    }
+
     #[test]
     fn test_parse_resp_array() {
         test_parse_resp(
@@ -402,8 +2390,8 @@ mod tests {
         test_parse_resp(
             b"*2\r\n$5\r\nhello\r\n$5\r\nworld\r\n",
             Some(RedisValue::Array(Some(vec![
-                RedisValue::BulkString(Some("hello".to_string())),
-                RedisValue::BulkString(Some("world".to_string())),
+                RedisValue::BulkString(Some(b"hello".to_vec())),
+                RedisValue::BulkString(Some(b"world".to_vec())),
             ]))),
         );
         test_parse_resp(b"*-1\r\n", Some(RedisValue::Array(None)));
@@ -422,38 +2410,38 @@ mod tests {
                 RedisValue::Integer(2),
                 RedisValue::Integer(3),
                 RedisValue::Integer(4),
-                RedisValue::BulkString(Some("hello".to_string())),
+                RedisValue::BulkString(Some(b"hello".to_vec())),
             ]))),
         );
         test_parse_resp(
             b"*3\r\n$5\r\nhello\r\n$-1\r\n$5\r\nworld\r\n",
             Some(RedisValue::Array(Some(vec![
-                RedisValue::BulkString(Some("hello".to_string())),
+                RedisValue::BulkString(Some(b"hello".to_vec())),
                 RedisValue::BulkString(None),
-                RedisValue::BulkString(Some("world".to_string())),
+                RedisValue::BulkString(Some(b"world".to_vec())),
             ]))),
         );
         test_parse_resp(
             b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n",
             Some(RedisValue::Array(Some(vec![
-                RedisValue::BulkString(Some("SET".to_string())),
-                RedisValue::BulkString(Some("key".to_string())),
-                RedisValue::BulkString(Some("value".to_string())),
+                RedisValue::BulkString(Some(b"SET".to_vec())),
+                RedisValue::BulkString(Some(b"key".to_vec())),
+                RedisValue::BulkString(Some(b"value".to_vec())),
             ]))),
         );
         test_parse_resp(
             b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$-1\r\n",
             Some(RedisValue::Array(Some(vec![
-                RedisValue::BulkString(Some("SET".to_string())),
-                RedisValue::BulkString(Some("key".to_string())),
+                RedisValue::BulkString(Some(b"SET".to_vec())),
+                RedisValue::BulkString(Some(b"key".to_vec())),
                 RedisValue::BulkString(None),
             ]))),
         );
         test_parse_resp(
             b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n",
             Some(RedisValue::Array(Some(vec![
-                RedisValue::BulkString(Some("GET".to_string())),
-                RedisValue::BulkString(Some("key".to_string())),
+                RedisValue::BulkString(Some(b"GET".to_vec())),
+                RedisValue::BulkString(Some(b"key".to_vec())),
             ]))),
         );
     }
@@ -469,37 +2457,894 @@ mod tests {
         test_parse_resp(b"_\r\n", Some(RedisValue::Null));
     }
 
+    #[test]
+    fn test_parse_resp3() {
+        test_parse_resp(b",1.5\r\n", Some(RedisValue::Double(1.5)));
+        test_parse_resp(b",-inf\r\n", Some(RedisValue::Double(f64::NEG_INFINITY)));
+        test_parse_resp(
+            b"(3492890328409238509324850943850943825024385\r\n",
+            Some(RedisValue::BigNumber("3492890328409238509324850943850943825024385".to_string())),
+        );
+        test_parse_resp(
+            b"=15\r\ntxt:Some string\r\n",
+            Some(RedisValue::VerbatimString("txt".to_string(), b"Some string".to_vec())),
+        );
+        test_parse_resp(
+            b"%2\r\n+first\r\n:1\r\n+second\r\n:2\r\n",
+            Some(RedisValue::Map(vec![
+                (RedisValue::SimpleString("first".to_string()), RedisValue::Integer(1)),
+                (RedisValue::SimpleString("second".to_string()), RedisValue::Integer(2)),
+            ])),
+        );
+        test_parse_resp(
+            b"~2\r\n+a\r\n#t\r\n",
+            Some(RedisValue::Set(vec![
+                RedisValue::SimpleString("a".to_string()),
+                RedisValue::Boolean(true),
+            ])),
+        );
+        test_parse_resp(
+            b">2\r\n+message\r\n_\r\n",
+            Some(RedisValue::Push(vec![
+                RedisValue::SimpleString("message".to_string()),
+                RedisValue::Null,
+            ])),
+        );
+        test_parse_resp(
+            b"|1\r\n+ttl\r\n:3600\r\n*1\r\n:2\r\n",
+            Some(RedisValue::Attribute(
+                vec![(RedisValue::SimpleString("ttl".to_string()), RedisValue::Integer(3600))],
+                Box::new(RedisValue::Array(Some(vec![RedisValue::Integer(2)]))),
+            )),
+        );
+        // An attribute is incomplete until the value it annotates arrives.
+        assert_eq!(parse_resp(b"|1\r\n+ttl\r\n:3600\r\n").unwrap(), None);
+        assert!(parse_resp(b"(12a\r\n").is_err());
+        assert!(parse_resp(b"=3\r\ntxt\r\n").is_err());
+        assert!(parse_resp(b"~-1\r\n").is_err());
+    }
+
+    #[test]
+    fn test_to_resp_bytes_protocols() {
+        let map = RedisValue::Map(vec![
+            (RedisValue::BulkString(Some(b"a".to_vec())), RedisValue::Double(0.5)),
+            (RedisValue::BulkString(Some(b"b".to_vec())), RedisValue::Null),
+        ]);
+        assert_eq!(map.to_resp_bytes(Protocol::Resp3), b"%2\r\n$1\r\na\r\n,0.5\r\n$1\r\nb\r\n_\r\n".to_vec());
+        assert_eq!(map.to_resp_bytes(Protocol::Resp2), b"*4\r\n$1\r\na\r\n$3\r\n0.5\r\n$1\r\nb\r\n$-1\r\n".to_vec());
+        let set = RedisValue::Set(vec![RedisValue::Boolean(true), RedisValue::Array(None)]);
+        assert_eq!(set.to_resp_bytes(Protocol::Resp3), b"~2\r\n#t\r\n_\r\n".to_vec());
+        assert_eq!(set.to_resp_bytes(Protocol::Resp2), b"*2\r\n:1\r\n*-1\r\n".to_vec());
+        let verbatim = RedisValue::VerbatimString("txt".to_string(), b"hi".to_vec());
+        assert_eq!(verbatim.to_resp_bytes(Protocol::Resp3), b"=6\r\ntxt:hi\r\n".to_vec());
+        assert_eq!(verbatim.to_resp_bytes(Protocol::Resp2), b"$2\r\nhi\r\n".to_vec());
+        let attribute = RedisValue::Attribute(
+            vec![(RedisValue::SimpleString("key".to_string()), RedisValue::BigNumber("-12".to_string()))],
+            Box::new(RedisValue::Push(vec![RedisValue::Double(f64::INFINITY)])),
+        );
+        assert_eq!(attribute.to_resp_bytes(Protocol::Resp3), b"|1\r\n+key\r\n(-12\r\n>1\r\n,inf\r\n".to_vec());
+        assert_eq!(attribute.to_resp_bytes(Protocol::Resp2), b"*1\r\n$3\r\ninf\r\n".to_vec());
+        // Whatever is encoded in RESP3 parses back to the same value.
+        let (parsed, _) = parse_resp(&attribute.to_resp_bytes(Protocol::Resp3)).unwrap().unwrap();
+        assert_eq!(parsed, attribute);
+    }
+
+    #[test]
+    fn test_encode_appends() {
+        let values = RedisValue::Array(Some(
+            (0..3).map(|i| RedisValue::BulkString(Some(vec![b'a' + i]))).collect(),
+        ));
+        let mut buffer = b"+OK\r\n".to_vec();
+        values.encode(Protocol::Resp2, &mut buffer);
+        assert_eq!(buffer, b"+OK\r\n*3\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\nc\r\n".to_vec());
+        let mut writer = io::Cursor::new(Vec::new());
+        values.write_to(Protocol::Resp2, &mut writer).unwrap();
+        assert_eq!(writer.into_inner(), values.to_resp_bytes(Protocol::Resp2));
+    }
+
     #[test]
     fn test_parse_pre_resp_ping() {
-        test_parse_resp(b"PING\r\n", Some(RedisValue::Array(Some(vec![RedisValue::SimpleString(
-            "PING".to_string(),
-        )]))));
+        assert_eq!(parse_request(b"PING\r\n").unwrap(), Some((vec![b"PING".to_vec()], 6)));
+    }
+
+    #[test]
+    fn test_parse_resp_pipelined() {
+        let input = b"*1\r\n$4\r\nPING\r\n*2\r\n$3\r\nGET\r\n$1\r\na\r\n";
+        let (first, consumed) = parse_resp(input).unwrap().unwrap();
+        assert_eq!(
+            first,
+            RedisValue::Array(Some(vec![RedisValue::BulkString(Some(b"PING".to_vec()))]))
+        );
+        assert_eq!(consumed, 14);
+        let (second, consumed) = parse_resp(&input[14..]).unwrap().unwrap();
+        assert_eq!(
+            second,
+            RedisValue::Array(Some(vec![
+                RedisValue::BulkString(Some(b"GET".to_vec())),
+                RedisValue::BulkString(Some(b"a".to_vec())),
+            ]))
+        );
+        assert_eq!(consumed, input.len() - 14);
+    }
+
+    #[test]
+    fn test_parse_resp_incomplete() {
+        let input = b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n";
+        for split in 0..input.len() {
+            assert_eq!(parse_resp(&input[..split]).unwrap(), None);
+            assert_eq!(parse_request(&input[..split]).unwrap(), None);
+        }
+        assert_eq!(parse_request(b"PI").unwrap(), None);
+        assert_eq!(parse_resp(b"$5\r\nhello\r").unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_resp_protocol_errors() {
+        assert!(parse_resp(b"$5\r\nhelloXX").is_err());
+        assert!(parse_resp(b"$-2\r\n").is_err());
+        assert!(parse_resp(b"*abc\r\n").is_err());
+        assert!(parse_resp(b"?\r\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let input = b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n*0\r\n";
+        assert_eq!(
+            parse_request(input).unwrap(),
+            Some((vec![b"GET".to_vec(), b"a".to_vec()], 20))
+        );
+        assert_eq!(parse_request(&input[20..]).unwrap(), Some((Vec::new(), 4)));
+        let error = |input: &[u8]| parse_request(input).unwrap_err();
+        assert_eq!(error(b"*1\r\n:1\r\n"), RedisError::protocol("expected '$', got ':'", 4));
+        assert_eq!(
+            error(b"*2\r\n$3\r\nGET\r\n$x\r\n"),
+            RedisError::protocol("invalid bulk length", 13)
+        );
+        assert_eq!(error(b"*x\r\n"), RedisError::protocol("invalid multibulk length", 0));
+        assert_eq!(error(b"*1\r\n$-1\r\n").to_string(), "ERR Protocol error: invalid bulk length");
     }
 
     fn test_extract_commands(input: &[u8], expected: RedisCommand) {
-        assert_eq!(extract_commands(input).unwrap(), expected);
+        let (request, _) = parse_request(input).unwrap().unwrap();
+        assert_eq!(extract_commands(request).unwrap().1, expected);
     }
 
     #[test]
     fn test_extract_commands_ping() {
         test_extract_commands(b"*1\r\n$4\r\nPING\r\n", RedisCommand::PING(RedisValue::SimpleString("PONG".to_string())));
-        test_extract_commands(b"*2\r\n$4\r\nPING\r\n$4\r\nPING\r\n", RedisCommand::PING(RedisValue::BulkString(Some("PING".to_string()))));
+        test_extract_commands(b"*2\r\n$4\r\nPING\r\n$4\r\nPING\r\n", RedisCommand::PING(RedisValue::BulkString(Some(b"PING".to_vec()))));
     }
 
     #[test]
     fn test_extract_commands_echo() {
-        test_extract_commands(b"*2\r\n$4\r\nECHO\r\n$5\r\nhello\r\n", RedisCommand::ECHO(RedisValue::BulkString(Some("hello".to_string()))));
+        test_extract_commands(b"*2\r\n$4\r\nECHO\r\n$5\r\nhello\r\n", RedisCommand::ECHO(RedisValue::BulkString(Some(b"hello".to_vec()))));
     }
 
     #[test]
     fn test_extract_commands_get() {
-        test_extract_commands(b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n", RedisCommand::GET(RedisValue::BulkString(Some("key".to_string()))));
+        test_extract_commands(b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n", RedisCommand::GET(b"key".to_vec()));
+    }
+
+    #[test]
+    fn test_extract_commands_binary() {
+        test_extract_commands(
+            b"*3\r\n$3\r\nSET\r\n$2\r\n\xc3\x28\r\n$3\r\n\x00\x01\x02\r\n",
+            RedisCommand::SET(
+                b"\xc3\x28".to_vec(),
+                b"\x00\x01\x02".to_vec(),
+                SetOptions::default(),
+            ),
+        );
     }
 
     #[test]
     fn test_extract_commands_set() {
-        test_extract_commands(b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n", RedisCommand::SET(RedisValue::BulkString(Some("key".to_string())), RedisValue::BulkString(Some("value".to_string())), None));
-        test_extract_commands(b"*5\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n$2\r\nEX\r\n$1\r\n5\r\n", RedisCommand::SET(RedisValue::BulkString(Some("key".to_string())), RedisValue::BulkString(Some("value".to_string())), Some(5000)));
-        test_extract_commands(b"*5\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n$2\r\nPX\r\n$3\r\n100\r\n", RedisCommand::SET(RedisValue::BulkString(Some("key".to_string())), RedisValue::BulkString(Some("value".to_string())), Some(100)));
+        test_extract_commands(b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n", RedisCommand::SET(b"key".to_vec(), b"value".to_vec(), SetOptions::default()));
+        test_extract_commands(b"*5\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n$2\r\nEX\r\n$1\r\n5\r\n", RedisCommand::SET(b"key".to_vec(), b"value".to_vec(), SetOptions { expiry: SetExpiry::In(5000), ..Default::default() }));
+        test_extract_commands(b"*5\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n$2\r\nPX\r\n$3\r\n100\r\n", RedisCommand::SET(b"key".to_vec(), b"value".to_vec(), SetOptions { expiry: SetExpiry::In(100), ..Default::default() }));
+    }
+
+    #[test]
+    fn test_extract_commands_expire() {
+        test_extract_commands(
+            b"*3\r\n$6\r\nEXPIRE\r\n$3\r\nkey\r\n$2\r\n10\r\n",
+            RedisCommand::EXPIRE(b"key".to_vec(), 10, ExpireCondition::default()),
+        );
+        test_extract_commands(
+            b"*5\r\n$7\r\nPEXPIRE\r\n$3\r\nkey\r\n$3\r\n-10\r\n$2\r\nxx\r\n$2\r\nGT\r\n",
+            RedisCommand::PEXPIRE(
+                b"key".to_vec(),
+                -10,
+                ExpireCondition {
+                    xx: true,
+                    gt: true,
+                    ..Default::default()
+                },
+            ),
+        );
+    }
+
+    fn test_extract_commands_error(input: &[u8], expected: &str) {
+        let (request, _) = parse_request(input).unwrap().unwrap();
+        assert_eq!(extract_commands(request).unwrap_err().to_string(), expected);
+    }
+
+    #[test]
+    fn test_extract_commands_expire_errors() {
+        test_extract_commands_error(
+            b"*2\r\n$6\r\nEXPIRE\r\n$3\r\nkey\r\n",
+            "ERR wrong number of arguments for 'expire' command",
+        );
+        test_extract_commands_error(
+            b"*5\r\n$6\r\nEXPIRE\r\n$3\r\nkey\r\n$2\r\n10\r\n$2\r\nNX\r\n$2\r\nGT\r\n",
+            "ERR NX and XX, GT or LT options at the same time are not compatible",
+        );
+        test_extract_commands_error(
+            b"*5\r\n$6\r\nEXPIRE\r\n$3\r\nkey\r\n$2\r\n10\r\n$2\r\nLT\r\n$2\r\nGT\r\n",
+            "ERR GT and LT options at the same time are not compatible",
+        );
+        test_extract_commands_error(
+            b"*4\r\n$6\r\nEXPIRE\r\n$3\r\nkey\r\n$2\r\n10\r\n$3\r\nFOO\r\n",
+            "ERR Unsupported option FOO",
+        );
+        test_extract_commands_error(
+            b"*3\r\n$6\r\nEXPIRE\r\n$3\r\nkey\r\n$3\r\n010\r\n",
+            "ERR value is not an integer or out of range",
+        );
+    }
+
+    #[test]
+    fn test_parse_integer() {
+        assert_eq!(parse_integer(b"0").unwrap(), 0);
+        assert_eq!(parse_integer(b"-42").unwrap(), -42);
+        assert_eq!(parse_integer(b"9223372036854775807").unwrap(), i64::MAX);
+        for invalid in [&b""[..], b"-", b"-0", b"+1", b"01", b" 1", b"1.5", b"9223372036854775808"] {
+            assert!(parse_integer(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn test_extract_commands_set_options() {
+        test_extract_commands(
+            b"*7\r\n$3\r\nSET\r\n$4\r\nlock\r\n$5\r\ntoken\r\n$2\r\nNX\r\n$2\r\nPX\r\n$5\r\n30000\r\n$3\r\nGET\r\n",
+            RedisCommand::SET(
+                b"lock".to_vec(),
+                b"token".to_vec(),
+                SetOptions {
+                    condition: SetCondition::NX,
+                    expiry: SetExpiry::In(30000),
+                    get: true,
+                },
+            ),
+        );
+        test_extract_commands(
+            b"*5\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n$4\r\nEXAT\r\n$2\r\n10\r\n",
+            RedisCommand::SET(
+                b"k".to_vec(),
+                b"v".to_vec(),
+                SetOptions {
+                    expiry: SetExpiry::At(10_000),
+                    ..Default::default()
+                },
+            ),
+        );
+    }
+
+    #[test]
+    fn test_extract_commands_set_errors() {
+        let cases: [(&[u8], &str); 6] = [
+            (
+                b"*5\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n$2\r\nNX\r\n$2\r\nXX\r\n",
+                "ERR syntax error",
+            ),
+            (
+                b"*6\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n$2\r\nEX\r\n$1\r\n1\r\n$7\r\nKEEPTTL\r\n",
+                "ERR syntax error",
+            ),
+            (
+                b"*7\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n$2\r\nEX\r\n$1\r\n1\r\n$2\r\nPX\r\n$1\r\n1\r\n",
+                "ERR syntax error",
+            ),
+            (
+                b"*4\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n$2\r\nEX\r\n",
+                "ERR syntax error",
+            ),
+            (
+                b"*5\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n$2\r\nEX\r\n$1\r\n0\r\n",
+                "ERR invalid expire time in 'set' command",
+            ),
+            (
+                b"*5\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n$2\r\nPX\r\n$3\r\nabc\r\n",
+                "ERR value is not an integer or out of range",
+            ),
+        ];
+        for (input, expected) in cases {
+            test_extract_commands_error(input, expected);
+        }
+    }
+
+    #[test]
+    fn test_extract_commands_multi_key() {
+        test_extract_commands(
+            b"*3\r\n$4\r\nMGET\r\n$1\r\na\r\n$1\r\nb\r\n",
+            RedisCommand::MGET(vec![b"a".to_vec(), b"b".to_vec()]),
+        );
+        test_extract_commands(
+            b"*5\r\n$4\r\nMSET\r\n$1\r\na\r\n$1\r\n1\r\n$1\r\nb\r\n$1\r\n2\r\n",
+            RedisCommand::MSET(vec![
+                (b"a".to_vec(), b"1".to_vec()),
+                (b"b".to_vec(), b"2".to_vec()),
+            ]),
+        );
+        test_extract_commands_error(
+            b"*4\r\n$6\r\nMSETNX\r\n$1\r\na\r\n$1\r\n1\r\n$1\r\nb\r\n",
+            "ERR wrong number of arguments for 'msetnx' command",
+        );
+    }
+
+    #[test]
+    fn test_extract_commands_keyspace() {
+        test_extract_commands(
+            b"*3\r\n$6\r\nUNLINK\r\n$1\r\na\r\n$1\r\nb\r\n",
+            RedisCommand::DEL(vec![b"a".to_vec(), b"b".to_vec()]),
+        );
+        test_extract_commands(
+            b"*6\r\n$4\r\nCOPY\r\n$1\r\na\r\n$1\r\nb\r\n$7\r\nREPLACE\r\n$2\r\nDB\r\n$1\r\n0\r\n",
+            RedisCommand::COPY(b"a".to_vec(), b"b".to_vec(), Some(0), true),
+        );
+        test_extract_commands(
+            b"*2\r\n$8\r\nFLUSHALL\r\n$5\r\nasync\r\n",
+            RedisCommand::FLUSHALL,
+        );
+        test_extract_commands_error(
+            b"*2\r\n$7\r\nFLUSHDB\r\n$4\r\nLAZY\r\n",
+            "ERR syntax error",
+        );
+    }
+
+    #[test]
+    fn test_extract_commands_scan() {
+        test_extract_commands(
+            b"*6\r\n$4\r\nSCAN\r\n$2\r\n17\r\n$5\r\nmatch\r\n$2\r\nu*\r\n$5\r\nCOUNT\r\n$3\r\n100\r\n",
+            RedisCommand::SCAN(
+                17,
+                ScanOptions {
+                    pattern: Some(b"u*".to_vec()),
+                    count: 100,
+                    key_type: None,
+                },
+            ),
+        );
+        test_extract_commands_error(
+            b"*2\r\n$4\r\nSCAN\r\n$2\r\n-1\r\n",
+            "ERR invalid cursor",
+        );
+        test_extract_commands_error(
+            b"*4\r\n$4\r\nSCAN\r\n$1\r\n0\r\n$5\r\nCOUNT\r\n$1\r\n0\r\n",
+            "ERR syntax error",
+        );
+    }
+
+    #[test]
+    fn test_extract_commands_databases() {
+        test_extract_commands(
+            b"*2\r\n$6\r\nSELECT\r\n$1\r\n3\r\n",
+            RedisCommand::SELECT(3),
+        );
+        test_extract_commands(
+            b"*3\r\n$4\r\nMOVE\r\n$1\r\nk\r\n$1\r\n1\r\n",
+            RedisCommand::MOVE(b"k".to_vec(), 1),
+        );
+        test_extract_commands_error(
+            b"*3\r\n$6\r\nSWAPDB\r\n$1\r\n0\r\n$1\r\nx\r\n",
+            "ERR invalid second DB index",
+        );
+    }
+
+    #[test]
+    fn test_extract_commands_lists() {
+        test_extract_commands(
+            b"*4\r\n$5\r\nRPUSH\r\n$1\r\nl\r\n$1\r\na\r\n$1\r\nb\r\n",
+            RedisCommand::RPUSH(b"l".to_vec(), vec![b"a".to_vec(), b"b".to_vec()]),
+        );
+        test_extract_commands(
+            b"*3\r\n$4\r\nLPOP\r\n$1\r\nl\r\n$1\r\n2\r\n",
+            RedisCommand::LPOP(b"l".to_vec(), Some(2)),
+        );
+        test_extract_commands(
+            b"*5\r\n$5\r\nLMOVE\r\n$1\r\na\r\n$1\r\nb\r\n$4\r\nleft\r\n$5\r\nRIGHT\r\n",
+            RedisCommand::LMOVE(b"a".to_vec(), b"b".to_vec(), ListEnd::Left, ListEnd::Right),
+        );
+        test_extract_commands(
+            b"*7\r\n$4\r\nLPOS\r\n$1\r\nl\r\n$1\r\na\r\n$4\r\nRANK\r\n$2\r\n-1\r\n$5\r\nCOUNT\r\n$1\r\n0\r\n",
+            RedisCommand::LPOS(
+                b"l".to_vec(),
+                b"a".to_vec(),
+                ListPositionOptions {
+                    rank: -1,
+                    count: Some(0),
+                    maxlen: 0,
+                },
+            ),
+        );
+        test_extract_commands_error(
+            b"*5\r\n$7\r\nLINSERT\r\n$1\r\nl\r\n$6\r\nBESIDE\r\n$1\r\na\r\n$1\r\nb\r\n",
+            "ERR syntax error",
+        );
+    }
+
+    #[test]
+    fn test_extract_commands_hashes() {
+        test_extract_commands(
+            b"*4\r\n$4\r\nHSET\r\n$1\r\nh\r\n$1\r\nf\r\n$1\r\nv\r\n",
+            RedisCommand::HSET(b"h".to_vec(), vec![(b"f".to_vec(), b"v".to_vec())]),
+        );
+        test_extract_commands_error(
+            b"*3\r\n$4\r\nHSET\r\n$1\r\nh\r\n$1\r\nf\r\n",
+            "ERR wrong number of arguments for 'hset' command",
+        );
+        test_extract_commands(
+            b"*4\r\n$10\r\nHRANDFIELD\r\n$1\r\nh\r\n$2\r\n-5\r\n$10\r\nwithvalues\r\n",
+            RedisCommand::HRANDFIELD(b"h".to_vec(), Some(-5), true),
+        );
+        test_extract_commands_error(
+            b"*3\r\n$10\r\nHRANDFIELD\r\n$1\r\nh\r\n$20\r\n-9223372036854775808\r\n",
+            "ERR value is out of range",
+        );
+        test_extract_commands_error(
+            b"*3\r\n$10\r\nHRANDFIELD\r\n$1\r\nh\r\n$19\r\n4611686018427387904\r\n",
+            "ERR value is out of range",
+        );
+        test_extract_commands_error(
+            b"*5\r\n$5\r\nHSCAN\r\n$1\r\nh\r\n$1\r\n0\r\n$4\r\nTYPE\r\n$4\r\nhash\r\n",
+            "ERR syntax error",
+        );
+    }
+
+    #[test]
+    fn test_extract_commands_bits() {
+        test_extract_commands(
+            b"*4\r\n$6\r\nSETBIT\r\n$1\r\nk\r\n$2\r\n10\r\n$1\r\n1\r\n",
+            RedisCommand::SETBIT(b"k".to_vec(), 10, true),
+        );
+        test_extract_commands_error(
+            b"*4\r\n$6\r\nSETBIT\r\n$1\r\nk\r\n$10\r\n4294967296\r\n$1\r\n1\r\n",
+            "ERR bit offset is not an integer or out of range",
+        );
+        test_extract_commands_error(
+            b"*4\r\n$6\r\nSETBIT\r\n$1\r\nk\r\n$1\r\n0\r\n$1\r\n2\r\n",
+            "ERR bit is not an integer or out of range",
+        );
+        test_extract_commands(
+            b"*5\r\n$8\r\nBITCOUNT\r\n$1\r\nk\r\n$1\r\n1\r\n$2\r\n-1\r\n$3\r\nBIT\r\n",
+            RedisCommand::BITCOUNT(b"k".to_vec(), Some((1, -1, BitUnit::Bit))),
+        );
+        test_extract_commands_error(
+            b"*3\r\n$8\r\nBITCOUNT\r\n$1\r\nk\r\n$1\r\n1\r\n",
+            "ERR syntax error",
+        );
+        test_extract_commands(
+            b"*4\r\n$6\r\nBITPOS\r\n$1\r\nk\r\n$1\r\n0\r\n$1\r\n2\r\n",
+            RedisCommand::BITPOS(b"k".to_vec(), false, 2, None, BitUnit::Byte),
+        );
+        test_extract_commands_error(
+            b"*5\r\n$5\r\nBITOP\r\n$3\r\nNOT\r\n$1\r\nd\r\n$1\r\na\r\n$1\r\nb\r\n",
+            "ERR BITOP NOT must be called with a single source key.",
+        );
+    }
+
+    #[test]
+    fn test_extract_commands_bitfield() {
+        let u8 = BitfieldType { signed: false, bits: 8 };
+        let i5 = BitfieldType { signed: true, bits: 5 };
+        test_extract_commands(
+            b"*11\r\n$8\r\nBITFIELD\r\n$1\r\nk\r\n$3\r\nGET\r\n$2\r\nu8\r\n$1\r\n0\r\n$8\r\nOVERFLOW\r\n$3\r\nSAT\r\n$6\r\nINCRBY\r\n$2\r\ni5\r\n$2\r\n#2\r\n$2\r\n-3\r\n",
+            RedisCommand::BITFIELD(
+                b"k".to_vec(),
+                vec![
+                    BitfieldOp::Get(u8, 0),
+                    BitfieldOp::Overflow(Overflow::Sat),
+                    BitfieldOp::IncrBy(i5, 10, -3),
+                ],
+            ),
+        );
+        test_extract_commands_error(
+            b"*5\r\n$8\r\nBITFIELD\r\n$1\r\nk\r\n$3\r\nGET\r\n$3\r\nu64\r\n$1\r\n0\r\n",
+            "ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.",
+        );
+        test_extract_commands_error(
+            b"*4\r\n$8\r\nBITFIELD\r\n$1\r\nk\r\n$3\r\nGET\r\n$2\r\ni8\r\n",
+            "ERR syntax error",
+        );
+        test_extract_commands_error(
+            b"*6\r\n$11\r\nBITFIELD_RO\r\n$1\r\nk\r\n$3\r\nSET\r\n$2\r\ni8\r\n$1\r\n0\r\n$1\r\n1\r\n",
+            "ERR BITFIELD_RO only supports the GET subcommand",
+        );
+    }
+
+    #[test]
+    fn test_extract_commands_sets() {
+        test_extract_commands(
+            b"*4\r\n$4\r\nSADD\r\n$1\r\ns\r\n$1\r\na\r\n$1\r\nb\r\n",
+            RedisCommand::SADD(b"s".to_vec(), vec![b"a".to_vec(), b"b".to_vec()]),
+        );
+        test_extract_commands(
+            b"*4\r\n$11\r\nSUNIONSTORE\r\n$1\r\nd\r\n$1\r\na\r\n$1\r\nb\r\n",
+            RedisCommand::SUNIONSTORE(b"d".to_vec(), vec![b"a".to_vec(), b"b".to_vec()]),
+        );
+        test_extract_commands(
+            b"*6\r\n$10\r\nSINTERCARD\r\n$1\r\n2\r\n$1\r\na\r\n$1\r\nb\r\n$5\r\nlimit\r\n$1\r\n5\r\n",
+            RedisCommand::SINTERCARD(vec![b"a".to_vec(), b"b".to_vec()], 5),
+        );
+        test_extract_commands_error(
+            b"*3\r\n$10\r\nSINTERCARD\r\n$1\r\n3\r\n$1\r\na\r\n",
+            "ERR Number of keys can't be greater than number of args",
+        );
+        test_extract_commands_error(
+            b"*3\r\n$4\r\nSPOP\r\n$1\r\ns\r\n$2\r\n-1\r\n",
+            "ERR value is out of range, must be positive",
+        );
+        test_extract_commands(
+            b"*3\r\n$11\r\nSRANDMEMBER\r\n$1\r\ns\r\n$20\r\n-4611686018427387903\r\n",
+            RedisCommand::SRANDMEMBER(b"s".to_vec(), Some(-4611686018427387903)),
+        );
+        test_extract_commands_error(
+            b"*3\r\n$11\r\nSRANDMEMBER\r\n$1\r\ns\r\n$20\r\n-9223372036854775808\r\n",
+            "ERR value is out of range",
+        );
+    }
+
+    #[test]
+    fn test_extract_commands_zadd() {
+        test_extract_commands(
+            b"*8\r\n$4\r\nZADD\r\n$1\r\nz\r\n$2\r\nXX\r\n$2\r\nch\r\n$1\r\n1\r\n$1\r\na\r\n$4\r\n-inf\r\n$1\r\nb\r\n",
+            RedisCommand::ZADD(
+                b"z".to_vec(),
+                vec![(1.0, b"a".to_vec()), (f64::NEG_INFINITY, b"b".to_vec())],
+                ZAddOptions { condition: SetCondition::XX, ch: true, ..Default::default() },
+            ),
+        );
+        test_extract_commands(
+            b"*5\r\n$4\r\nZADD\r\n$1\r\nz\r\n$4\r\nINCR\r\n$3\r\n2.5\r\n$1\r\na\r\n",
+            RedisCommand::ZADDINCR(b"z".to_vec(), 2.5, b"a".to_vec(), ZAddOptions::default()),
+        );
+        test_extract_commands_error(
+            b"*6\r\n$4\r\nZADD\r\n$1\r\nz\r\n$2\r\nNX\r\n$2\r\nGT\r\n$1\r\n1\r\n$1\r\na\r\n",
+            "ERR GT, LT, and/or NX options at the same time are not compatible",
+        );
+        for flags in [b"$2\r\nXX\r\n$2\r\nNX\r\n", b"$2\r\nNX\r\n$2\r\nXX\r\n"] {
+            test_extract_commands_error(
+                &[b"*6\r\n$4\r\nZADD\r\n$1\r\nz\r\n", &flags[..], b"$1\r\n1\r\n$1\r\nm\r\n"].concat(),
+                "ERR XX and NX options at the same time are not compatible",
+            );
+        }
+        test_extract_commands_error(
+            b"*5\r\n$4\r\nZADD\r\n$1\r\nz\r\n$1\r\n1\r\n$1\r\na\r\n$1\r\n2\r\n",
+            "ERR syntax error",
+        );
+        test_extract_commands_error(
+            b"*4\r\n$4\r\nZADD\r\n$1\r\nz\r\n$3\r\nnan\r\n$1\r\na\r\n",
+            "ERR value is not a valid float",
+        );
+    }
+
+    #[test]
+    fn test_extract_commands_zrange() {
+        test_extract_commands(
+            b"*9\r\n$6\r\nZRANGE\r\n$1\r\nz\r\n$2\r\n(5\r\n$4\r\n-inf\r\n$7\r\nBYSCORE\r\n$3\r\nREV\r\n$5\r\nLIMIT\r\n$1\r\n1\r\n$1\r\n2\r\n",
+            RedisCommand::ZRANGE(
+                b"z".to_vec(),
+                ZRangeOptions {
+                    by: ZRangeBy::Score(
+                        ScoreBound { score: f64::NEG_INFINITY, exclusive: false },
+                        ScoreBound { score: 5.0, exclusive: true },
+                    ),
+                    rev: true,
+                    limit: Some((1, 2)),
+                    with_scores: false,
+                },
+            ),
+        );
+        test_extract_commands(
+            b"*5\r\n$6\r\nZRANGE\r\n$1\r\nz\r\n$2\r\n[a\r\n$1\r\n+\r\n$5\r\nBYLEX\r\n",
+            RedisCommand::ZRANGE(
+                b"z".to_vec(),
+                ZRangeOptions {
+                    by: ZRangeBy::Lex(LexBound::Inclusive(b"a".to_vec()), LexBound::PositiveInfinity),
+                    rev: false,
+                    limit: None,
+                    with_scores: false,
+                },
+            ),
+        );
+        test_extract_commands_error(
+            b"*5\r\n$6\r\nZRANGE\r\n$1\r\nz\r\n$1\r\na\r\n$1\r\n+\r\n$5\r\nBYLEX\r\n",
+            "ERR min or max not valid string range item",
+        );
+        test_extract_commands_error(
+            b"*7\r\n$6\r\nZRANGE\r\n$1\r\nz\r\n$1\r\n0\r\n$2\r\n-1\r\n$5\r\nLIMIT\r\n$1\r\n0\r\n$1\r\n1\r\n",
+            "ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX",
+        );
+    }
+
+    #[test]
+    fn test_extract_commands_zstore() {
+        test_extract_commands(
+            b"*10\r\n$11\r\nZINTERSTORE\r\n$1\r\nd\r\n$1\r\n2\r\n$1\r\na\r\n$1\r\nb\r\n$7\r\nWEIGHTS\r\n$1\r\n2\r\n$1\r\n3\r\n$9\r\nAGGREGATE\r\n$3\r\nmax\r\n",
+            RedisCommand::ZINTERSTORE(
+                b"d".to_vec(),
+                vec![b"a".to_vec(), b"b".to_vec()],
+                vec![2.0, 3.0],
+                Aggregate::Max,
+            ),
+        );
+        test_extract_commands_error(
+            b"*4\r\n$11\r\nZUNIONSTORE\r\n$1\r\nd\r\n$1\r\n0\r\n$1\r\na\r\n",
+            "ERR at least 1 input key is needed for 'zunionstore' command",
+        );
+        test_extract_commands_error(
+            b"*6\r\n$11\r\nZUNIONSTORE\r\n$1\r\nd\r\n$1\r\n1\r\n$1\r\na\r\n$7\r\nWEIGHTS\r\n$1\r\nx\r\n",
+            "ERR weight value is not a float",
+        );
+    }
+
+    #[test]
+    fn test_extract_commands_xadd() {
+        test_extract_commands(
+            b"*11\r\n$4\r\nXADD\r\n$1\r\ns\r\n$10\r\nNOMKSTREAM\r\n$6\r\nMAXLEN\r\n$1\r\n~\r\n$2\r\n10\r\n$5\r\nLIMIT\r\n$1\r\n5\r\n$3\r\n5-*\r\n$1\r\nf\r\n$1\r\nv\r\n",
+            RedisCommand::XADD(
+                b"s".to_vec(),
+                StreamAddId::AutoSeq(5),
+                vec![(b"f".to_vec(), b"v".to_vec())],
+                true,
+                Some(StreamTrim {
+                    strategy: TrimStrategy::MaxLen(10),
+                    approximate: true,
+                    limit: Some(5),
+                }),
+            ),
+        );
+        test_extract_commands(
+            b"*5\r\n$4\r\nXADD\r\n$1\r\ns\r\n$3\r\n1-2\r\n$1\r\nf\r\n$1\r\nv\r\n",
+            RedisCommand::XADD(
+                b"s".to_vec(),
+                StreamAddId::Explicit(StreamId { ms: 1, seq: 2 }),
+                vec![(b"f".to_vec(), b"v".to_vec())],
+                false,
+                None,
+            ),
+        );
+        test_extract_commands_error(
+            b"*5\r\n$4\r\nXADD\r\n$1\r\ns\r\n$3\r\n1-x\r\n$1\r\nf\r\n$1\r\nv\r\n",
+            "ERR Invalid stream ID specified as stream command argument",
+        );
+        test_extract_commands_error(
+            b"*4\r\n$4\r\nXADD\r\n$1\r\ns\r\n$1\r\n*\r\n$1\r\nf\r\n",
+            "ERR wrong number of arguments for 'xadd' command",
+        );
+        test_extract_commands_error(
+            b"*6\r\n$5\r\nXTRIM\r\n$1\r\ns\r\n$6\r\nMAXLEN\r\n$2\r\n10\r\n$5\r\nLIMIT\r\n$1\r\n5\r\n",
+            "ERR syntax error, LIMIT cannot be used without the special ~ option",
+        );
+    }
+
+    #[test]
+    fn test_extract_commands_xrange() {
+        test_extract_commands(
+            b"*6\r\n$6\r\nXRANGE\r\n$1\r\ns\r\n$1\r\n5\r\n$4\r\n(6-0\r\n$5\r\nCOUNT\r\n$1\r\n2\r\n",
+            RedisCommand::XRANGE(
+                b"s".to_vec(),
+                StreamId { ms: 5, seq: 0 },
+                StreamId { ms: 5, seq: u64::MAX },
+                Some(2),
+            ),
+        );
+        test_extract_commands(
+            b"*4\r\n$9\r\nXREVRANGE\r\n$1\r\ns\r\n$1\r\n+\r\n$1\r\n-\r\n",
+            RedisCommand::XREVRANGE(b"s".to_vec(), StreamId::MIN, StreamId::MAX, None),
+        );
+        test_extract_commands_error(
+            b"*4\r\n$6\r\nXRANGE\r\n$1\r\ns\r\n$1\r\n-\r\n$4\r\n(0-0\r\n",
+            "ERR invalid end ID for the interval",
+        );
+    }
+
+    #[test]
+    fn test_extract_commands_xread() {
+        test_extract_commands(
+            b"*8\r\n$5\r\nXREAD\r\n$5\r\nCOUNT\r\n$1\r\n0\r\n$7\r\nSTREAMS\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\n$\r\n$1\r\n1\r\n",
+            RedisCommand::XREAD(
+                vec![
+                    (b"a".to_vec(), None),
+                    (b"b".to_vec(), Some(StreamId { ms: 1, seq: 0 })),
+                ],
+                None,
+                None,
+            ),
+        );
+        test_extract_commands(
+            b"*10\r\n$10\r\nXREADGROUP\r\n$5\r\nGROUP\r\n$1\r\ng\r\n$1\r\nc\r\n$5\r\nNOACK\r\n$5\r\nCOUNT\r\n$1\r\n3\r\n$7\r\nSTREAMS\r\n$1\r\na\r\n$1\r\n>\r\n",
+            RedisCommand::XREADGROUP(
+                b"g".to_vec(),
+                b"c".to_vec(),
+                vec![(b"a".to_vec(), None)],
+                Some(3),
+                true,
+                None,
+            ),
+        );
+        test_extract_commands_error(
+            b"*5\r\n$5\r\nXREAD\r\n$7\r\nSTREAMS\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\n0\r\n",
+            "ERR Unbalanced 'xread' list of streams: for each stream key an ID or '$' must be specified.",
+        );
+        test_extract_commands_error(
+            b"*7\r\n$10\r\nXREADGROUP\r\n$5\r\nCOUNT\r\n$1\r\n1\r\n$5\r\nNOACK\r\n$7\r\nSTREAMS\r\n$1\r\na\r\n$1\r\n>\r\n",
+            "ERR Missing GROUP option for XREADGROUP",
+        );
+    }
+
+    #[test]
+    fn test_extract_commands_stream_groups() {
+        test_extract_commands(
+            b"*6\r\n$6\r\nXGROUP\r\n$6\r\nCREATE\r\n$1\r\ns\r\n$1\r\ng\r\n$1\r\n$\r\n$8\r\nMKSTREAM\r\n",
+            RedisCommand::XGROUPCREATE(b"s".to_vec(), b"g".to_vec(), None, true),
+        );
+        test_extract_commands(
+            b"*9\r\n$8\r\nXPENDING\r\n$1\r\ns\r\n$1\r\ng\r\n$4\r\nIDLE\r\n$2\r\n10\r\n$1\r\n-\r\n$1\r\n+\r\n$1\r\n5\r\n$1\r\nc\r\n",
+            RedisCommand::XPENDING(
+                b"s".to_vec(),
+                b"g".to_vec(),
+                Some(PendingRange {
+                    min_idle: 10,
+                    start: StreamId::MIN,
+                    end: StreamId::MAX,
+                    count: 5,
+                    consumer: Some(b"c".to_vec()),
+                }),
+            ),
+        );
+        test_extract_commands(
+            b"*10\r\n$6\r\nXCLAIM\r\n$1\r\ns\r\n$1\r\ng\r\n$1\r\nc\r\n$3\r\n100\r\n$3\r\n1-1\r\n$1\r\n2\r\n$10\r\nRETRYCOUNT\r\n$1\r\n3\r\n$6\r\nJUSTID\r\n",
+            RedisCommand::XCLAIM(
+                b"s".to_vec(),
+                b"g".to_vec(),
+                b"c".to_vec(),
+                100,
+                vec![StreamId { ms: 1, seq: 1 }, StreamId { ms: 2, seq: 0 }],
+                ClaimOptions {
+                    retry_count: Some(3),
+                    just_id: true,
+                    ..Default::default()
+                },
+            ),
+        );
+        test_extract_commands_error(
+            b"*7\r\n$6\r\nXCLAIM\r\n$1\r\ns\r\n$1\r\ng\r\n$1\r\nc\r\n$3\r\n100\r\n$3\r\n1-1\r\n$3\r\nFOO\r\n",
+            "ERR Unrecognized XCLAIM option 'FOO'",
+        );
+        test_extract_commands_error(
+            b"*3\r\n$6\r\nXGROUP\r\n$7\r\nDESTROY\r\n$1\r\ns\r\n",
+            "ERR wrong number of arguments for 'xgroup|destroy' command",
+        );
+    }
+
+    #[test]
+    fn test_extract_commands_blocking() {
+        test_extract_commands(
+            b"*4\r\n$5\r\nBLPOP\r\n$1\r\na\r\n$1\r\nb\r\n$3\r\n1.5\r\n",
+            RedisCommand::BLPOP(vec![b"a".to_vec(), b"b".to_vec()], Duration::from_millis(1500)),
+        );
+        test_extract_commands(
+            b"*6\r\n$6\r\nBLMOVE\r\n$1\r\na\r\n$1\r\nb\r\n$5\r\nRIGHT\r\n$4\r\nLEFT\r\n$1\r\n0\r\n",
+            RedisCommand::BLMOVE(b"a".to_vec(), b"b".to_vec(), ListEnd::Right, ListEnd::Left, Duration::ZERO),
+        );
+        test_extract_commands(
+            b"*3\r\n$8\r\nBZPOPMIN\r\n$1\r\nz\r\n$1\r\n2\r\n",
+            RedisCommand::BZPOPMIN(vec![b"z".to_vec()], Duration::from_secs(2)),
+        );
+        test_extract_commands(
+            b"*6\r\n$5\r\nXREAD\r\n$5\r\nBLOCK\r\n$3\r\n100\r\n$7\r\nSTREAMS\r\n$1\r\ns\r\n$1\r\n$\r\n",
+            RedisCommand::XREAD(vec![(b"s".to_vec(), None)], None, Some(Duration::from_millis(100))),
+        );
+        test_extract_commands_error(b"*3\r\n$5\r\nBRPOP\r\n$1\r\na\r\n$2\r\n-1\r\n", "ERR timeout is negative");
+        test_extract_commands_error(b"*3\r\n$5\r\nBRPOP\r\n$1\r\na\r\n$1\r\nx\r\n", "ERR timeout is not a float or out of range");
+        test_extract_commands_error(b"*2\r\n$5\r\nBLPOP\r\n$1\r\na\r\n", "ERR wrong number of arguments for 'blpop' command");
+        test_extract_commands_error(b"*6\r\n$5\r\nXREAD\r\n$5\r\nBLOCK\r\n$2\r\n-5\r\n$7\r\nSTREAMS\r\n$1\r\ns\r\n$1\r\n$\r\n", "ERR timeout is negative");
+    }
+
+    #[test]
+    fn test_extract_commands_hello() {
+        test_extract_commands(b"*1\r\n$5\r\nHELLO\r\n", RedisCommand::HELLO(None));
+        test_extract_commands(b"*2\r\n$5\r\nHELLO\r\n$1\r\n3\r\n", RedisCommand::HELLO(Some(Protocol::Resp3)));
+        test_extract_commands(
+            b"*7\r\n$5\r\nhello\r\n$1\r\n2\r\n$4\r\nAUTH\r\n$7\r\ndefault\r\n$6\r\nsecret\r\n$7\r\nSETNAME\r\n$8\r\nworker-1\r\n",
+            RedisCommand::HELLO(Some(Protocol::Resp2)),
+        );
+        test_extract_commands_error(b"*2\r\n$5\r\nHELLO\r\n$1\r\n4\r\n", "NOPROTO unsupported protocol version");
+        test_extract_commands_error(b"*2\r\n$5\r\nHELLO\r\n$5\r\nthree\r\n", "ERR Protocol version is not an integer or out of range");
+        test_extract_commands_error(b"*4\r\n$5\r\nHELLO\r\n$1\r\n3\r\n$4\r\nAUTH\r\n$7\r\ndefault\r\n", "ERR Syntax error in HELLO option 'AUTH'");
+        test_extract_commands_error(
+            b"*4\r\n$5\r\nHELLO\r\n$1\r\n3\r\n$7\r\nSETNAME\r\n$3\r\na b\r\n",
+            "ERR Client names cannot contain spaces, newlines or special characters.",
+        );
+    }
+
+    #[test]
+    fn test_extract_commands_unknown() {
+        test_extract_commands_error(
+            b"*3\r\n$3\r\nfoo\r\n$3\r\nbar\r\n$1\r\n1\r\n",
+            "ERR unknown command 'foo', with args beginning with: 'bar' '1' ",
+        );
+        let (request, _) = parse_request(b"*1\r\n$3\r\nGET\r\n").unwrap().unwrap();
+        assert_eq!(
+            extract_commands(request).unwrap_err(),
+            RedisError::WrongArity("GET".to_string())
+        );
+    }
+
+    #[test]
+    fn test_extract_commands_command() {
+        test_extract_commands(b"*1\r\n$7\r\nCOMMAND\r\n", RedisCommand::COMMANDINFO(vec![]));
+        test_extract_commands(b"*2\r\n$7\r\ncommand\r\n$5\r\ncount\r\n", RedisCommand::COMMANDCOUNT);
+        test_extract_commands(b"*4\r\n$7\r\nCOMMAND\r\n$4\r\nINFO\r\n$3\r\nget\r\n$3\r\nfoo\r\n", RedisCommand::COMMANDINFO(vec![b"get".to_vec(), b"foo".to_vec()]));
+        test_extract_commands(b"*2\r\n$7\r\nCOMMAND\r\n$4\r\nDOCS\r\n", RedisCommand::COMMANDDOCS(vec![]));
+        test_extract_commands(b"*4\r\n$7\r\nCOMMAND\r\n$7\r\nGETKEYS\r\n$3\r\nGET\r\n$1\r\na\r\n", RedisCommand::COMMANDGETKEYS(vec![b"GET".to_vec(), b"a".to_vec()]));
+        test_extract_commands(b"*2\r\n$7\r\nCOMMAND\r\n$4\r\nLIST\r\n", RedisCommand::COMMANDLIST(None));
+        test_extract_commands(b"*5\r\n$7\r\nCOMMAND\r\n$4\r\nLIST\r\n$8\r\nFILTERBY\r\n$6\r\nACLCAT\r\n$4\r\nlist\r\n", RedisCommand::COMMANDLIST(Some(CommandFilter::AclCategory(b"list".to_vec()))));
+        test_extract_commands_error(b"*2\r\n$7\r\nCOMMAND\r\n$3\r\nfoo\r\n", "ERR unknown subcommand 'foo'. Try COMMAND HELP.");
+        test_extract_commands_error(b"*3\r\n$7\r\nCOMMAND\r\n$5\r\nCOUNT\r\n$1\r\n1\r\n", "ERR wrong number of arguments for 'command|count' command");
+        test_extract_commands_error(b"*2\r\n$7\r\nCOMMAND\r\n$7\r\nGETKEYS\r\n", "ERR wrong number of arguments for 'command|getkeys' command");
+        test_extract_commands_error(b"*5\r\n$7\r\nCOMMAND\r\n$4\r\nLIST\r\n$8\r\nFILTERBY\r\n$5\r\ngroup\r\n$4\r\nlist\r\n", "ERR syntax error");
+    }
+
+    #[test]
+    fn test_parse_inline_request() {
+        let request = |args: &[&[u8]]| args.iter().map(|arg| arg.to_vec()).collect::<Vec<_>>();
+        assert_eq!(parse_request(b"SET a 1\n").unwrap(), Some((request(&[b"SET", b"a", b"1"]), 8)));
+        assert_eq!(parse_request(b"  get\t a \r\nPING").unwrap(), Some((request(&[b"get", b"a"]), 11)));
+        assert_eq!(parse_request(b"\r\n").unwrap(), Some((Vec::new(), 2)));
+        assert_eq!(parse_request(b"SET a").unwrap(), None);
+        assert_eq!(
+            parse_request(b"SET \"a b\" 'it''s'\n").unwrap_err(),
+            RedisError::protocol("unbalanced quotes in request", 0)
+        );
+        assert_eq!(
+            parse_request(b"SET \"a b\\n\\x41\\\"\" 'it\\'s \\n' \"\"\n").unwrap(),
+            Some((request(&[b"SET", b"a b\nA\"", b"it's \\n", b""]), 32))
+        );
+        let error = |input: &[u8]| parse_request(input).unwrap_err().to_string();
+        assert_eq!(error(b"SET \"a 1\n"), "ERR Protocol error: unbalanced quotes in request");
+        assert_eq!(error(b"SET 'a'b 1\n"), "ERR Protocol error: unbalanced quotes in request");
+        assert_eq!(error(&vec![b'a'; 64 * 1024 + 1]), "ERR Protocol error: too big inline request");
+    }
+
+    #[test]
+    fn test_parse_request_header_limit() {
+        let long = vec![b'1'; 64 * 1024 + 1];
+        let error = |input: &[u8]| parse_request(input).unwrap_err();
+        assert_eq!(error(&[b"*", &long[..]].concat()), RedisError::protocol("too big mbulk count string", 0));
+        assert_eq!(
+            error(&[b"*1\r\n$", &long[..]].concat()),
+            RedisError::protocol("too big bulk count string", 4)
+        );
+        // Only the header is limited, not the payload following it.
+        let input = [b"*1\r\n$65537\r\n", &long[..], b"\r\n"].concat();
+        assert_eq!(parse_request(&input[..input.len() - 2]).unwrap(), None);
+        assert_eq!(parse_request(&input).unwrap(), Some((vec![long], input.len())));
+    }
+
+    #[test]
+    fn test_request_parser_resumes() {
+        let input = b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n*1\r\n$4\r\nPING\r\n";
+        let mut parser = RequestParser::default();
+        for split in 0..33 {
+            assert_eq!(parser.parse(&input[..split]).unwrap(), None);
+        }
+        let request = vec![b"SET".to_vec(), b"key".to_vec(), b"value".to_vec()];
+        assert_eq!(parser.parse(input).unwrap(), Some((request, 33)));
+        assert_eq!(parser.parse(&input[33..]).unwrap(), Some((vec![b"PING".to_vec()], 14)));
+        // An error leaves the parser ready for a new request.
+        assert!(parser.parse(b"*2\r\n$1\r\na\r\n$x\r\n").is_err());
+        assert_eq!(parser.parse(b"PING\r\n").unwrap(), Some((vec![b"PING".to_vec()], 6)));
     }
 }
@@ -0,0 +1,229 @@
+use super::{parse_length, read_line, RedisValue, MAX_ARRAY_LENGTH, MAX_BULK_LENGTH};
+use crate::error::RedisError;
+
+/// The outcome of parsing a single element: the value and the offset just past
+/// it, or `None` when the buffer ends before the element does.
+type Parsed = Option<(RedisValue, usize)>;
+
+fn utf8_line(line: &[u8], pos: usize) -> Result<String, RedisError> {
+    String::from_utf8(line.to_vec()).map_err(|_| RedisError::protocol("invalid UTF-8 in line", pos))
+}
+
+fn pick_simple_string(buffer: &[u8], pos: usize) -> Result<Parsed, RedisError> {
+    let Some((line, next)) = read_line(buffer, pos + 1) else {
+        return Ok(None);
+    };
+    let value = RedisValue::SimpleString(utf8_line(line, pos)?);
+    Ok(Some((value, next)))
+}
+
+fn pick_error(buffer: &[u8], pos: usize) -> Result<Parsed, RedisError> {
+    let Some((line, next)) = read_line(buffer, pos + 1) else {
+        return Ok(None);
+    };
+    let value = RedisValue::Error(utf8_line(line, pos)?);
+    Ok(Some((value, next)))
+}
+
+fn pick_integer(buffer: &[u8], pos: usize) -> Result<Parsed, RedisError> {
+    let Some((line, next)) = read_line(buffer, pos + 1) else {
+        return Ok(None);
+    };
+    let value = parse_length(line).ok_or(RedisError::protocol("invalid integer", pos))?;
+    Ok(Some((RedisValue::Integer(value), next)))
+}
+
+fn pick_bulk_string(buffer: &[u8], pos: usize) -> Result<Parsed, RedisError> {
+    let Some((line, next)) = read_line(buffer, pos + 1) else {
+        return Ok(None);
+    };
+    let len = parse_length(line).ok_or(RedisError::protocol("invalid bulk length", pos))?;
+    if len == -1 {
+        return Ok(Some((RedisValue::BulkString(None), next)));
+    }
+    if !(0..=MAX_BULK_LENGTH).contains(&len) {
+        return Err(RedisError::protocol("invalid bulk length", pos));
+    }
+    let end = next + len as usize;
+    // The payload is skipped by length rather than scanned, so re-parsing a
+    // partially received frame stays cheap no matter how large it is.
+    if buffer.len() < end + 2 {
+        return Ok(None);
+    }
+    if &buffer[end..end + 2] != b"\r\n" {
+        return Err(RedisError::protocol("expected CRLF after bulk string", end));
+    }
+    let value = RedisValue::BulkString(Some(buffer[next..end].to_vec()));
+    Ok(Some((value, end + 2)))
+}
+
+/// Parses the length line of the aggregate at `pos`, which is `None` for the
+/// RESP2 null array.
+fn aggregate_length(line: &[u8], pos: usize) -> Result<Option<usize>, RedisError> {
+    match parse_length(line) {
+        Some(-1) => Ok(None),
+        Some(len) if (0..=MAX_ARRAY_LENGTH).contains(&len) => Ok(Some(len as usize)),
+        _ => Err(RedisError::protocol("invalid multibulk length", pos)),
+    }
+}
+
+/// Parses `len` values one after another starting at `pos`.
+fn pick_values(
+    buffer: &[u8],
+    mut pos: usize,
+    len: usize,
+) -> Result<Option<(Vec<RedisValue>, usize)>, RedisError> {
+    let mut values = Vec::with_capacity(len.min(1024));
+    for _ in 0..len {
+        let Some((value, after)) = pick_value(buffer, pos)? else {
+            return Ok(None);
+        };
+        values.push(value);
+        pos = after;
+    }
+    Ok(Some((values, pos)))
+}
+
+/// Parses the arrays, sets and pushes, which differ only in their type byte.
+fn pick_array(buffer: &[u8], pos: usize) -> Result<Parsed, RedisError> {
+    let Some((line, next)) = read_line(buffer, pos + 1) else {
+        return Ok(None);
+    };
+    let len = match aggregate_length(line, pos)? {
+        Some(len) => len,
+        // Only RESP2 has a null array, RESP3 sets and pushes have no null.
+        None if buffer[pos] == b'*' => return Ok(Some((RedisValue::Array(None), next))),
+        None => return Err(RedisError::protocol("invalid multibulk length", pos)),
+    };
+    let Some((values, next)) = pick_values(buffer, next, len)? else {
+        return Ok(None);
+    };
+    let value = match buffer[pos] {
+        b'~' => RedisValue::Set(values),
+        b'>' => RedisValue::Push(values),
+        _ => RedisValue::Array(Some(values)),
+    };
+    Ok(Some((value, next)))
+}
+
+/// Parses a map, or an attribute along with the value it annotates.
+fn pick_map(buffer: &[u8], pos: usize) -> Result<Parsed, RedisError> {
+    let Some((line, next)) = read_line(buffer, pos + 1) else {
+        return Ok(None);
+    };
+    let len = aggregate_length(line, pos)?
+        .ok_or(RedisError::protocol("invalid multibulk length", pos))?;
+    let Some((values, next)) = pick_values(buffer, next, len * 2)? else {
+        return Ok(None);
+    };
+    let mut values = values.into_iter();
+    let mut pairs = Vec::with_capacity(len);
+    while let (Some(key), Some(value)) = (values.next(), values.next()) {
+        pairs.push((key, value));
+    }
+    if buffer[pos] == b'%' {
+        return Ok(Some((RedisValue::Map(pairs), next)));
+    }
+    let Some((value, next)) = pick_value(buffer, next)? else {
+        return Ok(None);
+    };
+    Ok(Some((RedisValue::Attribute(pairs, Box::new(value)), next)))
+}
+
+fn pick_boolean(buffer: &[u8], pos: usize) -> Result<Parsed, RedisError> {
+    let Some((line, next)) = read_line(buffer, pos + 1) else {
+        return Ok(None);
+    };
+    match line {
+        b"t" => Ok(Some((RedisValue::Boolean(true), next))),
+        b"f" => Ok(Some((RedisValue::Boolean(false), next))),
+        _ => Err(RedisError::protocol("invalid boolean", pos)),
+    }
+}
+
+fn pick_null(buffer: &[u8], pos: usize) -> Result<Parsed, RedisError> {
+    let Some((line, next)) = read_line(buffer, pos + 1) else {
+        return Ok(None);
+    };
+    if !line.is_empty() {
+        return Err(RedisError::protocol("invalid null", pos));
+    }
+    Ok(Some((RedisValue::Null, next)))
+}
+
+fn pick_double(buffer: &[u8], pos: usize) -> Result<Parsed, RedisError> {
+    let Some((line, next)) = read_line(buffer, pos + 1) else {
+        return Ok(None);
+    };
+    let value = match line {
+        b"inf" => f64::INFINITY,
+        b"-inf" => f64::NEG_INFINITY,
+        b"nan" => f64::NAN,
+        _ => std::str::from_utf8(line)
+            .ok()
+            .and_then(|line| line.parse().ok())
+            .ok_or(RedisError::protocol("invalid double", pos))?,
+    };
+    Ok(Some((RedisValue::Double(value), next)))
+}
+
+fn pick_big_number(buffer: &[u8], pos: usize) -> Result<Parsed, RedisError> {
+    let Some((line, next)) = read_line(buffer, pos + 1) else {
+        return Ok(None);
+    };
+    let digits = line.strip_prefix(b"-").unwrap_or(line);
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return Err(RedisError::protocol("invalid big number", pos));
+    }
+    let value = RedisValue::BigNumber(utf8_line(line, pos)?);
+    Ok(Some((value, next)))
+}
+
+fn pick_verbatim_string(buffer: &[u8], pos: usize) -> Result<Parsed, RedisError> {
+    let Some((value, next)) = pick_bulk_string(buffer, pos)? else {
+        return Ok(None);
+    };
+    let RedisValue::BulkString(Some(payload)) = value else {
+        return Err(RedisError::protocol("invalid bulk length", pos));
+    };
+    if payload.len() < 4 || payload[3] != b':' {
+        return Err(RedisError::protocol(
+            "expected a format in verbatim string",
+            pos,
+        ));
+    }
+    let format = utf8_line(&payload[..3], pos)?;
+    let value = RedisValue::VerbatimString(format, payload[4..].to_vec());
+    Ok(Some((value, next)))
+}
+
+fn pick_value(buffer: &[u8], pos: usize) -> Result<Parsed, RedisError> {
+    match buffer.get(pos) {
+        None => Ok(None),
+        Some(b'+') => pick_simple_string(buffer, pos),
+        Some(b'-') => pick_error(buffer, pos),
+        Some(b':') => pick_integer(buffer, pos),
+        Some(b'$') => pick_bulk_string(buffer, pos),
+        Some(b'*' | b'~' | b'>') => pick_array(buffer, pos),
+        Some(b'%' | b'|') => pick_map(buffer, pos),
+        Some(b'#') => pick_boolean(buffer, pos),
+        Some(b'_') => pick_null(buffer, pos),
+        Some(b',') => pick_double(buffer, pos),
+        Some(b'(') => pick_big_number(buffer, pos),
+        Some(b'=') => pick_verbatim_string(buffer, pos),
+        Some(&byte) => Err(RedisError::protocol(
+            format!("unexpected type byte '{}'", byte as char),
+            pos,
+        )),
+    }
+}
+
+/// Parses the first frame in `buffer`.
+///
+/// Returns `Ok(None)` when the buffer holds only part of a frame, in which case
+/// the caller should keep the bytes and try again once more data has arrived.
+/// Otherwise returns the frame along with the number of bytes it occupied, so
+/// that pipelined frames can be parsed one after another.
+pub fn parse_resp(buffer: &[u8]) -> Result<Option<(RedisValue, usize)>, RedisError> {
+    pick_value(buffer, 0)
+}
@@ -1,56 +1,3450 @@
-use std::collections::HashMap;
-use std::time::{Duration, Instant};
+mod bits;
+mod dict;
+mod expire;
+pub mod glob;
+mod list;
+mod rand;
+mod set;
+mod stream;
+mod zset;
 
-use crate::resp::RedisValue;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+pub use bits::{BitOp, BitUnit, BitfieldOp, BitfieldType, Overflow};
+use dict::Dict;
+use expire::VolatileKeys;
+use list::normalize_range;
+pub use list::{List, ListEnd};
+use rand::Rng;
+pub use set::Set;
+use stream::Stream;
+pub use stream::{
+    AutoClaimed, ClaimOptions, DeliveredEntry, StreamEntry, StreamId, StreamTrim, TrimStrategy,
+};
+pub use zset::{LexBound, ScoreBound, ZSet};
+
+use crate::error::RedisError;
+use crate::resp::{parse_float, parse_integer};
+
+// Tuning for the active expiry cycle, following redis: keys are sampled in
+// batches and another batch is taken only while more than a quarter of the
+// previous one turned out to be expired.
+const ACTIVE_EXPIRE_KEYS_PER_LOOP: usize = 20;
+const ACTIVE_EXPIRE_ACCEPTABLE_STALE_PERCENT: usize = 25;
+
+// Same as redis' default proto-max-bulk-len, the largest string a command may
+// grow a value to.
+const MAX_STRING_LENGTH: usize = 512 * 1024 * 1024;
+const ERR_STRING_TOO_LONG: &str = "ERR string exceeds maximum allowed size (proto-max-bulk-len)";
+const ERR_STREAM_ID_TOO_SMALL: &str =
+    "ERR The ID specified in XADD is equal or smaller than the target stream top item";
+const ERR_XGROUP_NO_KEY: &str = "ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.";
+
+/// The NX/XX/GT/LT flags of the EXPIRE family. Several may be combined, in
+/// which case all of them have to hold for the new TTL to be applied.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ExpireCondition {
+    pub nx: bool,
+    pub xx: bool,
+    pub gt: bool,
+    pub lt: bool,
+}
+
+impl ExpireCondition {
+    /// Checks the flags against the key's current deadline, `None` when it has
+    /// no TTL, and the requested one, `None` when it lies in the past. A key
+    /// without a TTL counts as expiring infinitely late.
+    fn allows(&self, current: Option<Instant>, requested: Option<Instant>) -> bool {
+        if self.nx && current.is_some() || self.xx && current.is_none() {
+            return false;
+        }
+        match current {
+            Some(current) => {
+                let later = requested.is_some_and(|requested| requested > current);
+                let earlier = requested.is_none_or(|requested| requested < current);
+                (later || !self.gt) && (earlier || !self.lt)
+            }
+            None => !self.gt,
+        }
+    }
+}
+
+/// What SET does when the key already exists, or does not.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SetCondition {
+    #[default]
+    Always,
+    NX,
+    XX,
+}
+
+/// What SET does with the key's TTL.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SetExpiry {
+    /// Drop any TTL the key had.
+    #[default]
+    Persist,
+    /// Keep whatever TTL the key had.
+    KeepTtl,
+    /// Expire after the given number of milliseconds.
+    In(i64),
+    /// Expire at the given unix time in milliseconds.
+    At(i64),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SetOptions {
+    pub condition: SetCondition,
+    pub expiry: SetExpiry,
+    /// Reply with the previous value instead of OK.
+    pub get: bool,
+}
+
+pub struct SetOutcome {
+    /// Whether the value was written, which only fails for NX and XX.
+    pub written: bool,
+    pub previous: Option<Vec<u8>>,
+}
+
+/// The MATCH, COUNT and TYPE arguments of SCAN.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanOptions {
+    pub pattern: Option<Vec<u8>>,
+    /// How much work to do per call. It bounds the number of keys looked at,
+    /// not the number returned once MATCH and TYPE have filtered them.
+    pub count: usize,
+    pub key_type: Option<String>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            pattern: None,
+            count: 10,
+            key_type: None,
+        }
+    }
+}
+
+/// The RANK, COUNT and MAXLEN arguments of LPOS.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ListPositionOptions {
+    pub rank: i64,
+    /// `None` when no COUNT was given, which makes LPOS reply with a single
+    /// position rather than an array.
+    pub count: Option<usize>,
+    pub maxlen: usize,
+}
+
+impl Default for ListPositionOptions {
+    fn default() -> Self {
+        Self {
+            rank: 1,
+            count: None,
+            maxlen: 0,
+        }
+    }
+}
+
+/// The set algebra of SINTER, SUNION and SDIFF and their STORE variants.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetOp {
+    Inter,
+    Union,
+    Diff,
+}
+
+/// The flags of ZADD. NX and XX reuse the conditions of SET.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ZAddOptions {
+    pub condition: SetCondition,
+    pub gt: bool,
+    pub lt: bool,
+    /// Count changed scores along with added members.
+    pub ch: bool,
+}
+
+/// What ZRANGE selects by: ranks, which may count from the end, scores or
+/// members.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ZRangeBy {
+    Rank(i64, i64),
+    Score(ScoreBound, ScoreBound),
+    Lex(LexBound, LexBound),
+}
+
+/// The arguments of ZRANGE. Score and lex bounds are always stored as min
+/// then max, even though REV takes them the other way around.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZRangeOptions {
+    pub by: ZRangeBy,
+    pub rev: bool,
+    /// Offset and count of LIMIT, a negative count meaning no limit.
+    pub limit: Option<(i64, i64)>,
+    pub with_scores: bool,
+}
+
+/// How ZUNIONSTORE and ZINTERSTORE combine the scores of a member.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Aggregate {
+    #[default]
+    Sum,
+    Min,
+    Max,
+}
+
+/// The ID given to XADD: `*` to generate one, `ms-*` to generate only the
+/// sequence number, or an explicit one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StreamAddId {
+    Auto,
+    AutoSeq(u64),
+    Explicit(StreamId),
+}
+
+/// The extended form of XPENDING, which lists pending entries instead of
+/// summing them up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingRange {
+    pub min_idle: i64,
+    pub start: StreamId,
+    pub end: StreamId,
+    pub count: usize,
+    pub consumer: Option<Vec<u8>>,
+}
+
+/// What XPENDING replies with when no range is given: the number of pending
+/// entries, the lowest and highest of their IDs, and how many each consumer
+/// has.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingSummary {
+    pub count: usize,
+    pub bounds: Option<(StreamId, StreamId)>,
+    pub consumers: Vec<(Vec<u8>, usize)>,
+}
+
+/// A pending entry as XPENDING lists it, with the milliseconds since it was
+/// last delivered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingEntryInfo {
+    pub id: StreamId,
+    pub consumer: Vec<u8>,
+    pub idle: i64,
+    pub deliveries: u64,
+}
+
+/// The entries read from each stream by XREAD or XREADGROUP, along with the
+/// stream's key.
+pub type StreamsRead<T> = Vec<(Vec<u8>, Vec<T>)>;
+
+pub fn unix_time_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+fn no_group_error(key: &[u8], group: &[u8]) -> RedisError {
+    RedisError::other(format!(
+        "NOGROUP No such key '{}' or consumer group '{}'",
+        String::from_utf8_lossy(key),
+        String::from_utf8_lossy(group)
+    ))
+}
+
+/// Turns an index where negative values count from the end into one within
+/// `0..len`, if it is in range.
+fn normalize_index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { index + len as i64 } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+/// Walks `dict` from `cursor` for SCAN and its relatives. Like redis, buckets
+/// are visited until about `count` entries have been seen, but no more than
+/// ten times that many buckets, so that a sparse table does not make a single
+/// call slow. Returns the cursor to continue from.
+fn scan_dict<K: std::hash::Hash + Eq, V>(
+    dict: &Dict<K, V>,
+    cursor: u64,
+    count: usize,
+    mut visit: impl FnMut(&K, &V),
+) -> u64 {
+    let count = count.max(1);
+    let mut visited = 0;
+    let mut cursor = cursor;
+    for _ in 0..count.saturating_mul(10) {
+        cursor = dict.scan(cursor, |key, value| {
+            visited += 1;
+            visit(key, value);
+        });
+        if cursor == 0 || visited >= count {
+            break;
+        }
+    }
+    cursor
+}
+
+/// Converts a relative TTL into a deadline, `None` meaning that it is already
+/// in the past.
+/// Picks random entries for HRANDFIELD and SRANDMEMBER out of a collection
+/// of `len` entries, which `random` draws from and `all` walks. A positive
+/// `count` asks for that many distinct entries, or all of them if there are
+/// fewer, while a negative one asks for exactly `-count` entries that may
+/// repeat.
+fn random_sample<V: Clone>(
+    len: usize,
+    count: i64,
+    rng: &mut Rng,
+    mut random: impl FnMut(&mut Rng) -> (Vec<u8>, V),
+    all: impl Iterator<Item = (Vec<u8>, V)>,
+) -> Vec<(Vec<u8>, V)> {
+    let wanted = count.unsigned_abs() as usize;
+    if count < 0 {
+        return (0..wanted).map(|_| random(rng)).collect();
+    }
+    if wanted >= len {
+        return all.collect();
+    }
+    // Sample until enough distinct entries have turned up. Asking for most of
+    // the collection makes that slow, so then start from all of it and drop
+    // random entries instead.
+    let mut picked = Dict::new();
+    if wanted * 3 > len {
+        for (key, value) in all {
+            picked.insert(key, value);
+        }
+        while picked.len() > wanted {
+            let key = picked.random(rng).unwrap().0.clone();
+            picked.remove(key.as_slice());
+        }
+    } else {
+        while picked.len() < wanted {
+            let (key, value) = random(rng);
+            picked.insert(key, value);
+        }
+    }
+    picked
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+fn deadline_after(now: Instant, millis: i64) -> Option<Instant> {
+    if millis <= 0 {
+        return None;
+    }
+    now.checked_add(Duration::from_millis(millis as u64))
+}
+
+/// Converts an absolute unix time into a deadline, `None` meaning that it is
+/// already in the past.
+fn deadline_at(now: Instant, unix_millis: i64) -> Option<Instant> {
+    deadline_after(now, unix_millis.saturating_sub(unix_time_millis()))
+}
+
+/// Adds two floats the way INCRBYFLOAT does, giving the sum as text rounded
+/// to 17 significant digits and written like redis' `%.17Lf`, so without an
+/// exponent and with trailing zeros trimmed. Redis adds in long double
+/// precision, where 0.1 + 0.2 still comes out as 0.3, so rather than add in
+/// binary the shortest decimal forms of both operands are added exactly.
+fn float_sum(a: f64, b: f64) -> Vec<u8> {
+    const DIGITS: i32 = 17;
+    // The operand as an integer and the power of ten it is scaled by.
+    let decimal = |value: f64| {
+        let scientific = format!("{:e}", value);
+        let (mantissa, exponent) = scientific.split_once('e').expect("{:e} has an exponent");
+        let (int, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let digits: i128 = format!("{}{}", int, fraction)
+            .parse()
+            .expect("{:e} has a decimal mantissa");
+        let exponent: i32 = exponent.parse().expect("{:e} has an integer exponent");
+        (digits, exponent - fraction.len() as i32)
+    };
+    let (x, x_exponent) = decimal(a);
+    let (y, y_exponent) = decimal(b);
+    let x_exponent = if x == 0 { y_exponent } else { x_exponent };
+    let y_exponent = if y == 0 { x_exponent } else { y_exponent };
+    // Both are brought to a common exponent, no more than 20 places below the
+    // larger one so that the sum fits. Digits of the smaller operand further
+    // down than that are too far down to reach the 17 that are kept.
+    let exponent = x_exponent
+        .min(y_exponent)
+        .max(x_exponent.max(y_exponent) - 20);
+    let scale = |digits: i128, from: i32| match from - exponent {
+        shift if shift >= 0 => digits * 10i128.pow(shift as u32),
+        shift => digits / 10i128.pow(shift.unsigned_abs().min(38)),
+    };
+    let sum = scale(x, x_exponent) + scale(y, y_exponent);
+    // Digits are dropped past the 17 significant ones, and past the 17th
+    // decimal where %.17Lf stops.
+    let magnitude = sum.unsigned_abs();
+    let length = magnitude.checked_ilog10().map_or(0, |log| log as i32 + 1);
+    let dropped = (length - DIGITS).max(-DIGITS - exponent);
+    let (mut kept, mut exponent) = (magnitude, exponent);
+    if dropped > 0 {
+        kept = match 10u128.checked_pow(dropped as u32) {
+            Some(unit) => magnitude / unit + u128::from(magnitude % unit * 2 >= unit),
+            None => 0,
+        };
+        exponent += dropped;
+    }
+    if kept == 0 {
+        return b"0".to_vec();
+    }
+    let mut digits = kept.to_string();
+    let significant = digits.trim_end_matches('0').len();
+    exponent += (digits.len() - significant) as i32;
+    digits.truncate(significant);
+    // Where the decimal point goes, counting from the first digit.
+    let point = digits.len() as i32 + exponent;
+    let text = if exponent >= 0 {
+        format!("{}{}", digits, "0".repeat(exponent as usize))
+    } else if point > 0 {
+        let (int, fraction) = digits.split_at(point as usize);
+        format!("{}.{}", int, fraction)
+    } else {
+        format!("0.{}{}", "0".repeat(point.unsigned_abs() as usize), digits)
+    };
+    match sum < 0 {
+        true => format!("-{}", text).into_bytes(),
+        false => text.into_bytes(),
+    }
+}
+
+/// Yields the members common to all `sets`, a missing one counting as empty.
+/// Only the smallest set is walked and the others probed, so the cost follows
+/// its size however large the rest are.
+fn intersect<'a>(sets: Vec<Option<&'a Set>>) -> impl Iterator<Item = Vec<u8>> + 'a {
+    let mut sets: Vec<&Set> = sets.into_iter().collect::<Option<_>>().unwrap_or_default();
+    sets.sort_by_key(|set| set.len());
+    let smallest = (!sets.is_empty()).then(|| sets.remove(0));
+    smallest
+        .into_iter()
+        .flat_map(|set| set.iter())
+        .filter(move |member| sets.iter().all(|set| set.contains(member)))
+}
+
+/// An input of ZUNIONSTORE and ZINTERSTORE, which also accept plain sets as
+/// if every member had a score of 1.
+enum ScoredSource<'a> {
+    Set(&'a Set),
+    ZSet(&'a ZSet),
+}
+
+impl<'a> ScoredSource<'a> {
+    fn len(&self) -> usize {
+        match self {
+            ScoredSource::Set(set) => set.len(),
+            ScoredSource::ZSet(zset) => zset.len(),
+        }
+    }
+
+    fn score(&self, member: &[u8]) -> Option<f64> {
+        match self {
+            ScoredSource::Set(set) => set.contains(member).then_some(1.0),
+            ScoredSource::ZSet(zset) => zset.score(member),
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (Vec<u8>, f64)> + 'a> {
+        match *self {
+            ScoredSource::Set(set) => Box::new(set.iter().map(|member| (member, 1.0))),
+            ScoredSource::ZSet(zset) => {
+                Box::new(zset.iter().map(|(member, score)| (member.to_vec(), score)))
+            }
+        }
+    }
+}
+
+/// A hash maps fields to values.
+type Hash = Dict<Vec<u8>, Vec<u8>>;
+
+/// Fields of a hash along with their values.
+pub type FieldValues = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// The value a key holds, one variant per data type.
+#[derive(Clone)]
+pub enum Value {
+    String(Vec<u8>),
+    List(List),
+    Hash(Hash),
+    Set(Set),
+    ZSet(ZSet),
+    Stream(Stream),
+}
+
+impl Value {
+    /// The name TYPE reports for the value.
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::ZSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// Whether the value is a container with nothing left in it, in which
+    /// case its key has to be deleted, as redis never keeps empty ones.
+    fn is_empty_container(&self) -> bool {
+        match self {
+            Value::String(_) => false,
+            Value::List(list) => list.is_empty(),
+            Value::Hash(hash) => hash.is_empty(),
+            Value::Set(set) => set.is_empty(),
+            Value::ZSet(zset) => zset.is_empty(),
+            // Streams stay around when emptied, along with their last ID and
+            // consumer groups.
+            Value::Stream(_) => false,
+        }
+    }
+}
+
+/// A type a key's value can be accessed as, so that commands get the
+/// WRONGTYPE error when the key holds any other type.
+trait ValueType: Default {
+    fn from_value(value: &Value) -> Option<&Self>;
+    fn from_value_mut(value: &mut Value) -> Option<&mut Self>;
+    fn into_value(self) -> Value;
+}
+
+macro_rules! value_type {
+    ($type:ty, $variant:ident) => {
+        impl ValueType for $type {
+            fn from_value(value: &Value) -> Option<&Self> {
+                match value {
+                    Value::$variant(inner) => Some(inner),
+                    _ => None,
+                }
+            }
+
+            fn from_value_mut(value: &mut Value) -> Option<&mut Self> {
+                match value {
+                    Value::$variant(inner) => Some(inner),
+                    _ => None,
+                }
+            }
+
+            fn into_value(self) -> Value {
+                Value::$variant(self)
+            }
+        }
+    };
+}
+
+value_type!(Vec<u8>, String);
+value_type!(List, List);
+value_type!(Hash, Hash);
+value_type!(Set, Set);
+value_type!(ZSet, ZSet);
+value_type!(Stream, Stream);
+
+#[derive(Clone)]
 pub struct DataValue {
-    value: RedisValue,
+    value: Value,
     expiry: Option<Duration>,
     inserted_at: Instant,
 }
 
 impl DataValue {
-    pub fn value(&self) -> &RedisValue {
-        &self.value
+    fn new(value: Value) -> Self {
+        Self {
+            value,
+            expiry: None,
+            inserted_at: Instant::now(),
+        }
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        match self.expiry {
+            Some(expiry) => now.duration_since(self.inserted_at) > expiry,
+            None => false,
+        }
+    }
+
+    fn expires_at(&self) -> Option<Instant> {
+        self.expiry.map(|expiry| self.inserted_at + expiry)
+    }
+
+    fn set_expires_at(&mut self, deadline: Option<Instant>) {
+        self.expiry = deadline.map(|deadline| deadline.duration_since(self.inserted_at));
+    }
+
+    /// The time left before the value expires, or `None` if it has no TTL.
+    pub fn ttl(&self) -> Option<Duration> {
+        self.expires_at()
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// The absolute unix time in milliseconds at which the value expires.
+    pub fn expire_time_millis(&self) -> Option<i64> {
+        self.ttl()
+            .map(|ttl| unix_time_millis().saturating_add(ttl.as_millis() as i64))
     }
 }
 
+#[derive(Default)]
 pub struct Storage {
-    data: HashMap<String, DataValue>,
+    data: Dict<Vec<u8>, DataValue>,
+    volatile: VolatileKeys,
+    rng: Rng,
+    // How many blocked clients wait on each key, and those of the keys that
+    // have been written since `take_ready_keys` was last called.
+    blocking_keys: Dict<Vec<u8>, usize>,
+    ready_keys: Vec<Vec<u8>>,
 }
 
 impl Storage {
     pub fn new() -> Self {
         Self {
-            data: HashMap::new(),
+            data: Dict::new(),
+            volatile: VolatileKeys::default(),
+            rng: Rng::new(),
+            blocking_keys: Dict::new(),
+            ready_keys: Vec::new(),
+        }
+    }
+
+    /// Looks up `key`, deleting it on the spot if its TTL has passed.
+    pub fn get(&mut self, key: &[u8]) -> Option<&DataValue> {
+        if self
+            .data
+            .get(key)
+            .is_some_and(|d| d.is_expired(Instant::now()))
+        {
+            self.remove(key);
+            return None;
+        }
+        self.data.get(key)
+    }
+
+    fn get_mut(&mut self, key: &[u8]) -> Option<&mut DataValue> {
+        self.get(key)?;
+        self.data.get_mut(key)
+    }
+
+    /// Looks up the value of `key` as a `T`, failing if it holds another type.
+    fn lookup<T: ValueType>(&mut self, key: &[u8]) -> Result<Option<&T>, RedisError> {
+        match self.get(key) {
+            Some(data) => T::from_value(&data.value)
+                .map(Some)
+                .ok_or(RedisError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    fn lookup_mut<T: ValueType>(&mut self, key: &[u8]) -> Result<Option<&mut T>, RedisError> {
+        match self.get_mut(key) {
+            Some(data) => T::from_value_mut(&mut data.value)
+                .map(Some)
+                .ok_or(RedisError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Like `lookup_mut`, but creates an empty `T` first if the key does not
+    /// exist.
+    fn lookup_or_insert<T: ValueType>(&mut self, key: &[u8]) -> Result<&mut T, RedisError> {
+        self.signal_ready(key);
+        if self.get(key).is_none() {
+            self.data
+                .insert(key.to_vec(), DataValue::new(T::default().into_value()));
+        }
+        let data = self.data.get_mut(key).expect("key was just inserted");
+        T::from_value_mut(&mut data.value).ok_or(RedisError::WrongType)
+    }
+
+    /// Deletes `key` if a command just took the last element out of it.
+    fn remove_if_empty(&mut self, key: &[u8]) {
+        if self
+            .data
+            .get(key)
+            .is_some_and(|data| data.value.is_empty_container())
+        {
+            self.remove(key);
+        }
+    }
+
+    /// Returns the string stored at `key`.
+    pub fn get_string(&mut self, key: &[u8]) -> Result<Option<&[u8]>, RedisError> {
+        Ok(self.lookup::<Vec<u8>>(key)?.map(Vec::as_slice))
+    }
+
+    /// Stores `value` under `key` following the SET options. The previous
+    /// value is handed back whether or not the write happened.
+    pub fn set(&mut self, key: Vec<u8>, value: Vec<u8>, options: SetOptions) -> SetOutcome {
+        let now = Instant::now();
+        let existing = self.get(&key).map(|data| data.expires_at());
+        let allowed = match options.condition {
+            SetCondition::Always => true,
+            SetCondition::NX => existing.is_none(),
+            SetCondition::XX => existing.is_some(),
+        };
+        if !allowed {
+            return SetOutcome {
+                written: false,
+                previous: self
+                    .data
+                    .get(&key)
+                    .and_then(|data| Vec::from_value(&data.value))
+                    .cloned(),
+            };
+        }
+        let deadline = match options.expiry {
+            SetExpiry::Persist => None,
+            SetExpiry::KeepTtl => existing.flatten().map(Some),
+            SetExpiry::In(millis) => Some(deadline_after(now, millis)),
+            SetExpiry::At(unix_millis) => Some(deadline_at(now, unix_millis)),
+        };
+        let mut data = DataValue {
+            value: Value::String(value),
+            expiry: None,
+            inserted_at: now,
+        };
+        let previous = match deadline {
+            // A deadline that already passed, as EXAT and PXAT can ask for,
+            // leaves the key deleted rather than stored.
+            Some(None) => self.remove(&key),
+            Some(Some(deadline)) => {
+                data.set_expires_at(Some(deadline));
+                self.insert(key, data)
+            }
+            None => self.insert(key, data),
+        };
+        SetOutcome {
+            written: true,
+            previous: previous.and_then(|data| match data.value {
+                Value::String(value) => Some(value),
+                _ => None,
+            }),
+        }
+    }
+
+    /// Stores every pair, dropping any TTL the keys had.
+    pub fn mset(&mut self, pairs: Vec<(Vec<u8>, Vec<u8>)>) {
+        for (key, value) in pairs {
+            self.set(key, value, SetOptions::default());
+        }
+    }
+
+    /// Stores every pair if none of the keys exist, and nothing otherwise.
+    /// Returns whether the pairs were stored.
+    pub fn msetnx(&mut self, pairs: Vec<(Vec<u8>, Vec<u8>)>) -> bool {
+        if pairs.iter().any(|(key, _)| self.get(key).is_some()) {
+            return false;
+        }
+        self.mset(pairs);
+        true
+    }
+
+    /// Sets the TTL of `key` to end at `unix_millis`, subject to `condition`.
+    /// A deadline that has already passed deletes the key, like redis does.
+    /// Returns whether the key existed and the condition held.
+    pub fn expire_at(&mut self, key: &[u8], unix_millis: i64, condition: ExpireCondition) -> bool {
+        let deadline = deadline_at(Instant::now(), unix_millis);
+        let Some(data) = self.get_mut(key) else {
+            return false;
+        };
+        if !condition.allows(data.expires_at(), deadline) {
+            return false;
+        }
+        match deadline {
+            Some(deadline) => {
+                data.set_expires_at(Some(deadline));
+                self.volatile.insert(key);
+            }
+            None => {
+                self.remove(key);
+            }
+        }
+        true
+    }
+
+    /// Drops the TTL of `key`. Returns whether there was one to drop.
+    pub fn persist(&mut self, key: &[u8]) -> bool {
+        match self.get_mut(key) {
+            Some(data) if data.expiry.is_some() => {
+                data.set_expires_at(None);
+                self.volatile.remove(key);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Adds `delta` to the integer stored at `key`, treating a missing key as
+    /// zero, and returns the result. The TTL of the key is left untouched.
+    pub fn incr_by(&mut self, key: &[u8], delta: i64) -> Result<i64, RedisError> {
+        let current = match self.lookup::<Vec<u8>>(key)? {
+            Some(value) => parse_integer(value)?,
+            None => 0,
+        };
+        let value = current.checked_add(delta).ok_or(RedisError::other(
+            "ERR increment or decrement would overflow",
+        ))?;
+        *self.lookup_or_insert::<Vec<u8>>(key)? = value.to_string().into_bytes();
+        Ok(value)
+    }
+
+    /// Adds `delta` to the float stored at `key` and returns the new value in
+    /// the textual form it is stored in.
+    pub fn incr_by_float(&mut self, key: &[u8], delta: f64) -> Result<Vec<u8>, RedisError> {
+        let current = match self.lookup::<Vec<u8>>(key)? {
+            Some(value) => parse_float(value)?,
+            None => 0.0,
+        };
+        let value = current + delta;
+        if !value.is_finite() {
+            return Err(RedisError::other(
+                "ERR increment would produce NaN or Infinity",
+            ));
+        }
+        let value = float_sum(current, delta);
+        *self.lookup_or_insert::<Vec<u8>>(key)? = value.clone();
+        Ok(value)
+    }
+
+    /// Appends `value` to the string at `key` and returns its new length.
+    pub fn append(&mut self, key: &[u8], value: &[u8]) -> Result<usize, RedisError> {
+        let current = self.lookup::<Vec<u8>>(key)?.map_or(0, Vec::len);
+        if current + value.len() > MAX_STRING_LENGTH {
+            return Err(RedisError::other(ERR_STRING_TOO_LONG));
+        }
+        let string = self.lookup_or_insert::<Vec<u8>>(key)?;
+        string.extend_from_slice(value);
+        Ok(string.len())
+    }
+
+    /// Returns the bytes between `start` and `end` inclusive, where negative
+    /// offsets count from the end of the string.
+    pub fn get_range(&mut self, key: &[u8], start: i64, end: i64) -> Result<Vec<u8>, RedisError> {
+        let Some(string) = self.lookup::<Vec<u8>>(key)? else {
+            return Ok(Vec::new());
+        };
+        // Unlike list ranges, an end before the start of the string still
+        // selects the first byte, unless both count from the end and are
+        // reversed, which redis 7 checks before clamping.
+        if start < 0 && end < 0 && start > end {
+            return Ok(Vec::new());
+        }
+        let len = string.len() as i64;
+        let start = if start < 0 { len + start } else { start }.max(0);
+        let end = if end < 0 { len + end } else { end }.max(0).min(len - 1);
+        if len == 0 || start > end {
+            return Ok(Vec::new());
+        }
+        Ok(string[start as usize..=end as usize].to_vec())
+    }
+
+    /// Overwrites the string at `key` with `value` starting at `offset`,
+    /// padding with zero bytes as needed, and returns the new length.
+    pub fn set_range(
+        &mut self,
+        key: &[u8],
+        offset: usize,
+        value: &[u8],
+    ) -> Result<usize, RedisError> {
+        if value.is_empty() {
+            // Nothing to write, so a missing key is not created either.
+            return Ok(self.lookup::<Vec<u8>>(key)?.map_or(0, Vec::len));
+        }
+        if offset.saturating_add(value.len()) > MAX_STRING_LENGTH {
+            return Err(RedisError::other(ERR_STRING_TOO_LONG));
+        }
+        let string = self.lookup_or_insert::<Vec<u8>>(key)?;
+        let end = offset + value.len();
+        if string.len() < end {
+            string.resize(end, 0);
+        }
+        string[offset..end].copy_from_slice(value);
+        Ok(string.len())
+    }
+
+    /// Sets the bit at `offset` of the string at `key`, growing it with zero
+    /// bytes as needed, and returns the previous value of the bit.
+    pub fn set_bit(&mut self, key: &[u8], offset: u64, value: bool) -> Result<bool, RedisError> {
+        let string = self.lookup_or_insert::<Vec<u8>>(key)?;
+        Ok(bits::set_bit(string, offset, value))
+    }
+
+    pub fn get_bit(&mut self, key: &[u8], offset: u64) -> Result<bool, RedisError> {
+        Ok(self
+            .lookup::<Vec<u8>>(key)?
+            .is_some_and(|string| bits::get_bit(string, offset)))
+    }
+
+    /// Counts the set bits of the string at `key`, optionally only within
+    /// an inclusive range of bytes or bits.
+    pub fn bit_count(
+        &mut self,
+        key: &[u8],
+        range: Option<(i64, i64, BitUnit)>,
+    ) -> Result<u64, RedisError> {
+        let Some(string) = self.lookup::<Vec<u8>>(key)? else {
+            return Ok(0);
+        };
+        let (start, end, unit) = range.unwrap_or((0, -1, BitUnit::Byte));
+        Ok(match bits::bit_range(string, start, end, unit) {
+            Some((start, end)) => bits::count_bits(string, start, end),
+            None => 0,
+        })
+    }
+
+    /// Returns the offset of the first bit equal to `bit` in the string at
+    /// `key`, or -1 if there is none. Without an explicit `end`, the string
+    /// is treated as if followed by zero bits, so a clear bit is always found.
+    pub fn bit_position(
+        &mut self,
+        key: &[u8],
+        bit: bool,
+        start: i64,
+        end: Option<i64>,
+        unit: BitUnit,
+    ) -> Result<i64, RedisError> {
+        let Some(string) = self.lookup::<Vec<u8>>(key)? else {
+            return Ok(if bit { -1 } else { 0 });
+        };
+        let Some((start, last)) = bits::bit_range(string, start, end.unwrap_or(-1), unit) else {
+            return Ok(-1);
+        };
+        Ok(match bits::find_bit(string, bit, start, last) {
+            Some(offset) => offset as i64,
+            None if !bit && end.is_none() => last as i64 + 1,
+            None => -1,
+        })
+    }
+
+    /// Stores the result of `op` over the strings at `sources` in `dst` and
+    /// returns its length. An empty result deletes `dst`.
+    pub fn bit_op(
+        &mut self,
+        op: BitOp,
+        dst: &[u8],
+        sources: &[Vec<u8>],
+    ) -> Result<usize, RedisError> {
+        let mut strings = Vec::with_capacity(sources.len());
+        for source in sources {
+            strings.push(self.lookup::<Vec<u8>>(source)?.cloned().unwrap_or_default());
+        }
+        let strings: Vec<&[u8]> = strings.iter().map(Vec::as_slice).collect();
+        let result = bits::bit_op(op, &strings);
+        let len = result.len();
+        if result.is_empty() {
+            self.remove(dst);
+        } else {
+            self.insert(dst.to_vec(), DataValue::new(Value::String(result)));
         }
+        Ok(len)
     }
 
-    pub fn get(&self, key: String) -> Option<&DataValue> {
-        match self.data.get(&key) {
-            Some(d) => {
-                if let Some(expiry) = d.expiry {
-                    if d.inserted_at.elapsed().as_millis() > expiry.as_millis() {
-                        return None;
+    /// Runs the BITFIELD `ops` in order against the string at `key`. Each
+    /// GET, SET and INCRBY gets an entry in the result, `None` where an
+    /// overflow failed it.
+    pub fn bit_field(
+        &mut self,
+        key: &[u8],
+        ops: &[BitfieldOp],
+    ) -> Result<Vec<Option<i64>>, RedisError> {
+        if !ops.iter().any(BitfieldOp::writes) {
+            // Reading never creates the key.
+            let string = self.lookup::<Vec<u8>>(key)?.map_or(&[][..], Vec::as_slice);
+            return Ok(ops
+                .iter()
+                .filter_map(|op| match *op {
+                    BitfieldOp::Get(field, offset) => {
+                        Some(Some(bits::get_field(string, offset, field)))
+                    }
+                    _ => None,
+                })
+                .collect());
+        }
+        let string = self.lookup_or_insert::<Vec<u8>>(key)?;
+        // Like redis, grow the string up front to fit every write, including
+        // ones that end up failing.
+        let needed = ops
+            .iter()
+            .filter_map(|op| match *op {
+                BitfieldOp::Set(field, offset, _) | BitfieldOp::IncrBy(field, offset, _) => {
+                    Some(((offset + field.bits as u64 - 1) / 8 + 1) as usize)
+                }
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        if string.len() < needed {
+            string.resize(needed, 0);
+        }
+        let mut overflow = Overflow::default();
+        let mut results = Vec::new();
+        for op in ops {
+            match *op {
+                BitfieldOp::Overflow(behavior) => overflow = behavior,
+                BitfieldOp::Get(field, offset) => {
+                    results.push(Some(bits::get_field(string, offset, field)));
+                }
+                BitfieldOp::Set(field, offset, value) => {
+                    let value = match field.signed {
+                        true => value as i128,
+                        false => value as u64 as i128,
+                    };
+                    let previous = bits::get_field(string, offset, field);
+                    let result = bits::fit_field(value, field, overflow).map(|value| {
+                        bits::set_field(string, offset, field, value);
+                        previous
+                    });
+                    results.push(result);
+                }
+                BitfieldOp::IncrBy(field, offset, increment) => {
+                    let value = bits::get_field(string, offset, field) as i128 + increment as i128;
+                    let result = bits::fit_field(value, field, overflow);
+                    if let Some(value) = result {
+                        bits::set_field(string, offset, field, value);
                     }
+                    results.push(result);
                 }
-                Some(d)
             }
-            None => None,
         }
+        Ok(results)
     }
 
-    pub fn set(&mut self, key: String, value: RedisValue, expiry: Option<u64>) {
-        self.data.insert(
-            key,
-            DataValue {
-                value,
-                expiry: match expiry {
-                    Some(e) => Some(Duration::from_millis(e)),
-                    None => None,
-                },
-                inserted_at: Instant::now(),
-            },
+    /// Pushes `elements` one at a time onto `end` of the list at `key`,
+    /// creating it if needed, and returns the new length.
+    pub fn push(
+        &mut self,
+        key: &[u8],
+        end: ListEnd,
+        elements: &[Vec<u8>],
+    ) -> Result<usize, RedisError> {
+        let list = self.lookup_or_insert::<List>(key)?;
+        for element in elements {
+            list.push(end, element);
+        }
+        Ok(list.len())
+    }
+
+    /// Pops up to `count` elements from `end` of the list at `key`, or
+    /// returns `None` if there is no such list.
+    pub fn pop(
+        &mut self,
+        key: &[u8],
+        end: ListEnd,
+        count: usize,
+    ) -> Result<Option<Vec<Vec<u8>>>, RedisError> {
+        let Some(list) = self.lookup_mut::<List>(key)? else {
+            return Ok(None);
+        };
+        let popped = (0..count).map_while(|_| list.pop(end)).collect();
+        self.remove_if_empty(key);
+        Ok(Some(popped))
+    }
+
+    /// Returns the elements between `start` and `end` inclusive, where
+    /// negative indexes count from the end of the list.
+    pub fn list_range(
+        &mut self,
+        key: &[u8],
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<Vec<u8>>, RedisError> {
+        let Some(list) = self.lookup::<List>(key)? else {
+            return Ok(Vec::new());
+        };
+        let Some((start, end)) = normalize_range(start, end, list.len()) else {
+            return Ok(Vec::new());
+        };
+        Ok(list
+            .iter()
+            .skip(start)
+            .take(end - start + 1)
+            .map(<[u8]>::to_vec)
+            .collect())
+    }
+
+    pub fn list_index(&mut self, key: &[u8], index: i64) -> Result<Option<Vec<u8>>, RedisError> {
+        let Some(list) = self.lookup::<List>(key)? else {
+            return Ok(None);
+        };
+        Ok(normalize_index(index, list.len())
+            .and_then(|index| list.get(index))
+            .map(<[u8]>::to_vec))
+    }
+
+    pub fn list_set(&mut self, key: &[u8], index: i64, element: &[u8]) -> Result<(), RedisError> {
+        let list = self
+            .lookup_mut::<List>(key)?
+            .ok_or(RedisError::other("ERR no such key"))?;
+        let index = normalize_index(index, list.len())
+            .ok_or(RedisError::other("ERR index out of range"))?;
+        list.set(index, element);
+        Ok(())
+    }
+
+    /// Removes elements equal to `element`: the first `count` from the head
+    /// for a positive count, the last `-count` for a negative one, and all of
+    /// them for zero. Returns how many were removed.
+    pub fn list_remove(
+        &mut self,
+        key: &[u8],
+        count: i64,
+        element: &[u8],
+    ) -> Result<usize, RedisError> {
+        let Some(list) = self.lookup_mut::<List>(key)? else {
+            return Ok(0);
+        };
+        let limit = match count {
+            0 => usize::MAX,
+            _ => count.unsigned_abs() as usize,
+        };
+        let len = list.len();
+        let mut matches: Vec<usize> = if count >= 0 {
+            list.iter()
+                .enumerate()
+                .filter(|(_, candidate)| *candidate == element)
+                .map(|(index, _)| index)
+                .take(limit)
+                .collect()
+        } else {
+            list.iter()
+                .rev()
+                .enumerate()
+                .filter(|(_, candidate)| *candidate == element)
+                .map(|(index, _)| len - 1 - index)
+                .take(limit)
+                .collect()
+        };
+        // Remove from the back so that the indexes left to remove stay valid.
+        matches.sort_unstable_by(|a, b| b.cmp(a));
+        for &index in &matches {
+            list.remove(index);
+        }
+        self.remove_if_empty(key);
+        Ok(matches.len())
+    }
+
+    /// Trims the list at `key` down to the elements between `start` and
+    /// `end` inclusive.
+    pub fn list_trim(&mut self, key: &[u8], start: i64, end: i64) -> Result<(), RedisError> {
+        let Some(list) = self.lookup_mut::<List>(key)? else {
+            return Ok(());
+        };
+        match normalize_range(start, end, list.len()) {
+            Some((start, end)) => list.retain_range(start, end),
+            None => *list = List::default(),
+        }
+        self.remove_if_empty(key);
+        Ok(())
+    }
+
+    /// Inserts `element` next to the first occurrence of `pivot`. Returns the
+    /// new length, -1 if there is no pivot, or 0 if there is no list.
+    pub fn list_insert(
+        &mut self,
+        key: &[u8],
+        before: bool,
+        pivot: &[u8],
+        element: &[u8],
+    ) -> Result<i64, RedisError> {
+        let Some(list) = self.lookup_mut::<List>(key)? else {
+            return Ok(0);
+        };
+        let Some(index) = list.iter().position(|candidate| candidate == pivot) else {
+            return Ok(-1);
+        };
+        list.insert(if before { index } else { index + 1 }, element);
+        Ok(list.len() as i64)
+    }
+
+    pub fn list_len(&mut self, key: &[u8]) -> Result<usize, RedisError> {
+        Ok(self.lookup::<List>(key)?.map_or(0, List::len))
+    }
+
+    /// Pops an element from `from` of the list at `src` and pushes it onto
+    /// `to` of the list at `dst`, returning the element, or `None` if there
+    /// is no list at `src`.
+    pub fn list_move(
+        &mut self,
+        src: &[u8],
+        dst: &[u8],
+        from: ListEnd,
+        to: ListEnd,
+    ) -> Result<Option<Vec<u8>>, RedisError> {
+        if self.lookup::<List>(src)?.is_none() {
+            return Ok(None);
+        }
+        // Check the destination before anything is popped.
+        self.lookup::<List>(dst)?;
+        let element = self
+            .lookup_mut::<List>(src)?
+            .and_then(|list| list.pop(from))
+            .expect("source list was just looked up");
+        self.remove_if_empty(src);
+        self.lookup_or_insert::<List>(dst)?.push(to, &element);
+        Ok(Some(element))
+    }
+
+    /// Returns the indexes of elements equal to `element`, skipping the
+    /// first `rank - 1` matches, or scanning from the tail and skipping
+    /// `-rank - 1` for a negative rank. At most `count` indexes are returned
+    /// and `maxlen` elements compared, zero meaning no limit for either.
+    pub fn list_position(
+        &mut self,
+        key: &[u8],
+        element: &[u8],
+        rank: i64,
+        count: usize,
+        maxlen: usize,
+    ) -> Result<Vec<usize>, RedisError> {
+        let Some(list) = self.lookup::<List>(key)? else {
+            return Ok(Vec::new());
+        };
+        let count = if count == 0 { usize::MAX } else { count };
+        let maxlen = if maxlen == 0 { usize::MAX } else { maxlen };
+        let skip = rank.unsigned_abs() as usize - 1;
+        let matching = |(index, candidate): (usize, &[u8])| (candidate == element).then_some(index);
+        Ok(if rank > 0 {
+            list.iter()
+                .enumerate()
+                .take(maxlen)
+                .filter_map(matching)
+                .skip(skip)
+                .take(count)
+                .collect()
+        } else {
+            let last = list.len() - 1;
+            list.iter()
+                .rev()
+                .enumerate()
+                .take(maxlen)
+                .filter_map(|(index, candidate)| matching((last - index, candidate)))
+                .skip(skip)
+                .take(count)
+                .collect()
+        })
+    }
+
+    /// Removes `key`, returning whether it existed.
+    pub fn delete(&mut self, key: &[u8]) -> bool {
+        self.get(key).is_some() && self.remove(key).is_some()
+    }
+
+    pub fn exists(&mut self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// The name TYPE reports for the value at `key`.
+    pub fn key_type(&mut self, key: &[u8]) -> Option<&'static str> {
+        self.get(key).map(|data| data.value.type_name())
+    }
+
+    /// Moves the value at `src`, along with its TTL, to `dst`. With `nx` the
+    /// move only happens if `dst` does not exist. Returns whether it happened.
+    pub fn rename(&mut self, src: &[u8], dst: &[u8], nx: bool) -> Result<bool, RedisError> {
+        if self.get(src).is_none() {
+            return Err(RedisError::other("ERR no such key"));
+        }
+        if src == dst {
+            return Ok(!nx);
+        }
+        if nx && self.get(dst).is_some() {
+            return Ok(false);
+        }
+        let data = self.remove(src).expect("source key was just looked up");
+        self.insert(dst.to_vec(), data);
+        Ok(true)
+    }
+
+    /// Copies the value at `src`, along with its TTL, to `dst` in `target`,
+    /// or in this database when `target` is `None`. Without `replace` an
+    /// existing `dst` is left alone. Returns whether the copy happened.
+    pub fn copy(
+        &mut self,
+        src: &[u8],
+        dst: &[u8],
+        target: Option<&mut Storage>,
+        replace: bool,
+    ) -> Result<bool, RedisError> {
+        if target.is_none() && src == dst {
+            return Err(RedisError::other(
+                "ERR source and destination objects are the same",
+            ));
+        }
+        let Some(data) = self.get(src).cloned() else {
+            return Ok(false);
+        };
+        let target = target.unwrap_or(self);
+        if !replace && target.get(dst).is_some() {
+            return Ok(false);
+        }
+        target.insert(dst.to_vec(), data);
+        Ok(true)
+    }
+
+    /// Moves `key`, along with its TTL, to `target` unless it already exists
+    /// there. Returns whether the move happened.
+    pub fn move_to(&mut self, key: &[u8], target: &mut Storage) -> bool {
+        if self.get(key).is_none() || target.get(key).is_some() {
+            return false;
+        }
+        let data = self.remove(key).expect("key was just looked up");
+        target.insert(key.to_vec(), data);
+        true
+    }
+
+    /// Sets every field to its value, creating the hash if needed. Returns
+    /// the number of fields that did not exist before.
+    pub fn hash_set(&mut self, key: &[u8], pairs: FieldValues) -> Result<usize, RedisError> {
+        let hash = self.lookup_or_insert::<Hash>(key)?;
+        Ok(pairs
+            .into_iter()
+            .filter(|(field, value)| hash.insert(field.clone(), value.clone()).is_none())
+            .count())
+    }
+
+    pub fn hash_get(&mut self, key: &[u8], field: &[u8]) -> Result<Option<Vec<u8>>, RedisError> {
+        Ok(self
+            .lookup::<Hash>(key)?
+            .and_then(|hash| hash.get(field))
+            .cloned())
+    }
+
+    pub fn hash_mget(
+        &mut self,
+        key: &[u8],
+        fields: &[Vec<u8>],
+    ) -> Result<Vec<Option<Vec<u8>>>, RedisError> {
+        let hash = self.lookup::<Hash>(key)?;
+        Ok(fields
+            .iter()
+            .map(|field| hash.and_then(|hash| hash.get(field.as_slice())).cloned())
+            .collect())
+    }
+
+    /// Removes the given fields, and the hash once it is empty. Returns the
+    /// number of fields removed.
+    pub fn hash_delete(&mut self, key: &[u8], fields: &[Vec<u8>]) -> Result<usize, RedisError> {
+        let Some(hash) = self.lookup_mut::<Hash>(key)? else {
+            return Ok(0);
+        };
+        let removed = fields
+            .iter()
+            .filter(|field| hash.remove(field.as_slice()).is_some())
+            .count();
+        self.remove_if_empty(key);
+        Ok(removed)
+    }
+
+    pub fn hash_get_all(&mut self, key: &[u8]) -> Result<FieldValues, RedisError> {
+        Ok(self.lookup::<Hash>(key)?.map_or(Vec::new(), |hash| {
+            hash.iter()
+                .map(|(field, value)| (field.clone(), value.clone()))
+                .collect()
+        }))
+    }
+
+    /// Adds `delta` to the integer in `field`, treating a missing field as
+    /// zero, and returns the result.
+    pub fn hash_incr_by(
+        &mut self,
+        key: &[u8],
+        field: &[u8],
+        delta: i64,
+    ) -> Result<i64, RedisError> {
+        let current = match self.hash_get(key, field)? {
+            Some(value) => parse_integer(&value)
+                .map_err(|_| RedisError::other("ERR hash value is not an integer"))?,
+            None => 0,
+        };
+        let value = current.checked_add(delta).ok_or(RedisError::other(
+            "ERR increment or decrement would overflow",
+        ))?;
+        self.lookup_or_insert::<Hash>(key)?
+            .insert(field.to_vec(), value.to_string().into_bytes());
+        Ok(value)
+    }
+
+    /// Adds `delta` to the float in `field` and returns the new value in the
+    /// textual form it is stored in.
+    pub fn hash_incr_by_float(
+        &mut self,
+        key: &[u8],
+        field: &[u8],
+        delta: f64,
+    ) -> Result<Vec<u8>, RedisError> {
+        let current = match self.hash_get(key, field)? {
+            Some(value) => parse_float(&value)
+                .map_err(|_| RedisError::other("ERR hash value is not a float"))?,
+            None => 0.0,
+        };
+        let value = current + delta;
+        if !value.is_finite() {
+            return Err(RedisError::other(
+                "ERR increment would produce NaN or Infinity",
+            ));
+        }
+        let value = float_sum(current, delta);
+        self.lookup_or_insert::<Hash>(key)?
+            .insert(field.to_vec(), value.clone());
+        Ok(value)
+    }
+
+    pub fn hash_len(&mut self, key: &[u8]) -> Result<usize, RedisError> {
+        Ok(self.lookup::<Hash>(key)?.map_or(0, Hash::len))
+    }
+
+    pub fn hash_exists(&mut self, key: &[u8], field: &[u8]) -> Result<bool, RedisError> {
+        Ok(self
+            .lookup::<Hash>(key)?
+            .is_some_and(|hash| hash.contains_key(field)))
+    }
+
+    /// Like `scan`, for the fields of the hash at `key`.
+    pub fn hash_scan(
+        &mut self,
+        key: &[u8],
+        cursor: u64,
+        options: &ScanOptions,
+    ) -> Result<(u64, FieldValues), RedisError> {
+        let Some(hash) = self.lookup::<Hash>(key)? else {
+            return Ok((0, Vec::new()));
+        };
+        let mut pairs = Vec::new();
+        let cursor = scan_dict(hash, cursor, options.count, |field, value| {
+            if options
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| glob::matches(pattern, field))
+            {
+                pairs.push((field.clone(), value.clone()));
+            }
+        });
+        Ok((cursor, pairs))
+    }
+
+    /// Returns random fields with their values, chosen as `random_sample`
+    /// describes for `count`.
+    pub fn hash_random(&mut self, key: &[u8], count: i64) -> Result<FieldValues, RedisError> {
+        if self.lookup::<Hash>(key)?.is_none() {
+            return Ok(Vec::new());
+        }
+        // Look the hash up again through the field, so that it can be
+        // borrowed alongside the random number generator.
+        let hash = self
+            .data
+            .get(key)
+            .and_then(|data| Hash::from_value(&data.value))
+            .expect("hash was just looked up");
+        let pair = |(field, value): (&Vec<u8>, &Vec<u8>)| (field.clone(), value.clone());
+        Ok(random_sample(
+            hash.len(),
+            count,
+            &mut self.rng,
+            |rng| hash.random(rng).map(pair).expect("hash is not empty"),
+            hash.iter().map(pair),
+        ))
+    }
+
+    /// Adds `members` to the set at `key`, creating it if needed, and returns
+    /// how many were not already there.
+    pub fn set_add(&mut self, key: &[u8], members: &[Vec<u8>]) -> Result<usize, RedisError> {
+        let set = self.lookup_or_insert::<Set>(key)?;
+        Ok(members.iter().filter(|member| set.insert(member)).count())
+    }
+
+    pub fn set_remove(&mut self, key: &[u8], members: &[Vec<u8>]) -> Result<usize, RedisError> {
+        let Some(set) = self.lookup_mut::<Set>(key)? else {
+            return Ok(0);
+        };
+        let removed = members.iter().filter(|member| set.remove(member)).count();
+        self.remove_if_empty(key);
+        Ok(removed)
+    }
+
+    /// Tells for each of `members` whether it is in the set at `key`.
+    pub fn set_contains(
+        &mut self,
+        key: &[u8],
+        members: &[Vec<u8>],
+    ) -> Result<Vec<bool>, RedisError> {
+        let set = self.lookup::<Set>(key)?;
+        Ok(members
+            .iter()
+            .map(|member| set.is_some_and(|set| set.contains(member)))
+            .collect())
+    }
+
+    pub fn set_members(&mut self, key: &[u8]) -> Result<Vec<Vec<u8>>, RedisError> {
+        Ok(self
+            .lookup::<Set>(key)?
+            .map(|set| set.iter().collect())
+            .unwrap_or_default())
+    }
+
+    pub fn set_len(&mut self, key: &[u8]) -> Result<usize, RedisError> {
+        Ok(self.lookup::<Set>(key)?.map_or(0, Set::len))
+    }
+
+    /// Removes and returns up to `count` random members of the set at `key`.
+    pub fn set_pop(&mut self, key: &[u8], count: usize) -> Result<Vec<Vec<u8>>, RedisError> {
+        if self.lookup::<Set>(key)?.is_none() {
+            return Ok(Vec::new());
+        }
+        // Look the set up again through the field, so that it can be
+        // borrowed alongside the random number generator.
+        let set = self
+            .data
+            .get_mut(key)
+            .and_then(|data| Set::from_value_mut(&mut data.value))
+            .expect("set was just looked up");
+        let popped = if count >= set.len() {
+            std::mem::take(set).iter().collect()
+        } else {
+            (0..count)
+                .map(|_| {
+                    let member = set.random(&mut self.rng).unwrap();
+                    set.remove(&member);
+                    member
+                })
+                .collect()
+        };
+        self.remove_if_empty(key);
+        Ok(popped)
+    }
+
+    /// Returns random members of the set at `key`, chosen as `random_sample`
+    /// describes for `count`.
+    pub fn set_random(&mut self, key: &[u8], count: i64) -> Result<Vec<Vec<u8>>, RedisError> {
+        if self.lookup::<Set>(key)?.is_none() {
+            return Ok(Vec::new());
+        }
+        let set = self
+            .data
+            .get(key)
+            .and_then(|data| Set::from_value(&data.value))
+            .expect("set was just looked up");
+        let sample = random_sample(
+            set.len(),
+            count,
+            &mut self.rng,
+            |rng| (set.random(rng).expect("set is not empty"), ()),
+            set.iter().map(|member| (member, ())),
+        );
+        Ok(sample.into_iter().map(|(member, ())| member).collect())
+    }
+
+    /// Returns the result of `op` over the sets at `keys`.
+    pub fn set_combine(&mut self, op: SetOp, keys: &[Vec<u8>]) -> Result<Vec<Vec<u8>>, RedisError> {
+        Ok(self.combine_sets(op, keys)?.iter().collect())
+    }
+
+    /// Stores the result of `op` over the sets at `keys` in `dst` and returns
+    /// its size. An empty result deletes `dst`.
+    pub fn set_combine_store(
+        &mut self,
+        op: SetOp,
+        dst: &[u8],
+        keys: &[Vec<u8>],
+    ) -> Result<usize, RedisError> {
+        let result = self.combine_sets(op, keys)?;
+        let len = result.len();
+        if result.is_empty() {
+            self.remove(dst);
+        } else {
+            self.insert(dst.to_vec(), DataValue::new(Value::Set(result)));
+        }
+        Ok(len)
+    }
+
+    /// Counts the members common to the sets at `keys`, stopping at `limit`
+    /// unless it is zero.
+    pub fn set_inter_card(&mut self, keys: &[Vec<u8>], limit: usize) -> Result<usize, RedisError> {
+        let common = intersect(self.lookup_sets(keys)?);
+        Ok(match limit {
+            0 => common.count(),
+            _ => common.take(limit).count(),
+        })
+    }
+
+    pub fn set_scan(
+        &mut self,
+        key: &[u8],
+        cursor: u64,
+        options: &ScanOptions,
+    ) -> Result<(u64, Vec<Vec<u8>>), RedisError> {
+        let Some(set) = self.lookup::<Set>(key)? else {
+            return Ok((0, Vec::new()));
+        };
+        let mut members = Vec::new();
+        let cursor = set.scan(cursor, options.count, |member| {
+            if options
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| glob::matches(pattern, member))
+            {
+                members.push(member.to_vec());
+            }
+        });
+        Ok((cursor, members))
+    }
+
+    /// Looks up the sets at `keys` all at once, with `None` for missing keys.
+    fn lookup_sets(&mut self, keys: &[Vec<u8>]) -> Result<Vec<Option<&Set>>, RedisError> {
+        // Expire and type check every key before borrowing them together.
+        for key in keys {
+            self.lookup::<Set>(key)?;
+        }
+        Ok(keys
+            .iter()
+            .map(|key| {
+                self.data
+                    .get(key)
+                    .and_then(|data| Set::from_value(&data.value))
+            })
+            .collect())
+    }
+
+    fn combine_sets(&mut self, op: SetOp, keys: &[Vec<u8>]) -> Result<Set, RedisError> {
+        let sets = self.lookup_sets(keys)?;
+        let mut result = Set::default();
+        let members: Box<dyn Iterator<Item = Vec<u8>>> = match op {
+            SetOp::Inter => Box::new(intersect(sets)),
+            SetOp::Union => Box::new(sets.into_iter().flatten().flat_map(Set::iter)),
+            SetOp::Diff => {
+                let (first, others) = sets.split_first().expect("at least one key");
+                let others: Vec<&Set> = others.iter().flatten().copied().collect();
+                Box::new(
+                    first
+                        .iter()
+                        .flat_map(|set| set.iter())
+                        .filter(move |member| !others.iter().any(|set| set.contains(member))),
+                )
+            }
+        };
+        for member in members {
+            result.insert(&member);
+        }
+        Ok(result)
+    }
+
+    /// Sets the scores of the members in `entries` following the ZADD flags,
+    /// and returns how many members were added, along with how many changed
+    /// with CH.
+    pub fn zset_add(
+        &mut self,
+        key: &[u8],
+        entries: &[(f64, Vec<u8>)],
+        options: ZAddOptions,
+    ) -> Result<usize, RedisError> {
+        let zset = self.lookup_or_insert::<ZSet>(key)?;
+        let (mut added, mut changed) = (0, 0);
+        for (score, member) in entries {
+            match zset.score(member) {
+                Some(_) if options.condition == SetCondition::NX => {}
+                Some(current) => {
+                    if *score == current
+                        || options.gt && *score < current
+                        || options.lt && *score > current
+                    {
+                        continue;
+                    }
+                    zset.insert(member, *score);
+                    changed += 1;
+                }
+                None if options.condition == SetCondition::XX => {}
+                None => {
+                    zset.insert(member, *score);
+                    added += 1;
+                }
+            }
+        }
+        // XX may leave a newly created set empty.
+        self.remove_if_empty(key);
+        Ok(if options.ch { added + changed } else { added })
+    }
+
+    /// Adds `delta` to the score of `member`, as ZINCRBY and ZADD INCR do,
+    /// and returns the new score, or `None` if the ZADD flags ruled it out.
+    pub fn zset_incr_by(
+        &mut self,
+        key: &[u8],
+        member: &[u8],
+        delta: f64,
+        options: ZAddOptions,
+    ) -> Result<Option<f64>, RedisError> {
+        let zset = self.lookup_or_insert::<ZSet>(key)?;
+        let current = zset.score(member);
+        let score = current.unwrap_or(0.0) + delta;
+        let allowed = match current {
+            Some(current) => {
+                options.condition != SetCondition::NX
+                    && !(options.gt && score <= current)
+                    && !(options.lt && score >= current)
+            }
+            None => options.condition != SetCondition::XX,
+        };
+        let result = match allowed {
+            true if score.is_nan() => Err(RedisError::other(
+                "ERR resulting score is not a number (NaN)",
+            )),
+            true => {
+                zset.insert(member, score);
+                Ok(Some(score))
+            }
+            false => Ok(None),
+        };
+        self.remove_if_empty(key);
+        result
+    }
+
+    /// Returns the members selected by ZRANGE along with their scores.
+    pub fn zset_range(
+        &mut self,
+        key: &[u8],
+        options: &ZRangeOptions,
+    ) -> Result<Vec<(Vec<u8>, f64)>, RedisError> {
+        let Some(zset) = self.lookup::<ZSet>(key)? else {
+            return Ok(Vec::new());
+        };
+        let (offset, count) = match options.limit {
+            Some((offset, _)) if offset < 0 => return Ok(Vec::new()),
+            Some((offset, count)) => (offset as usize, usize::try_from(count).ok()),
+            None => (0, None),
+        };
+        Ok(match &options.by {
+            ZRangeBy::Rank(start, end) => match normalize_range(*start, *end, zset.len()) {
+                Some((start, end)) => zset.range_by_rank(start, end, options.rev),
+                None => Vec::new(),
+            },
+            ZRangeBy::Score(min, max) => {
+                zset.range_by_score(*min, *max, options.rev, offset, count)
+            }
+            ZRangeBy::Lex(min, max) => zset.range_by_lex(min, max, options.rev, offset, count),
+        })
+    }
+
+    /// Returns the rank of `member` from the lowest score up, along with its
+    /// score.
+    pub fn zset_rank(
+        &mut self,
+        key: &[u8],
+        member: &[u8],
+    ) -> Result<Option<(usize, f64)>, RedisError> {
+        Ok(self.lookup::<ZSet>(key)?.and_then(|zset| {
+            let rank = zset.rank(member, false)?;
+            Some((rank, zset.score(member)?))
+        }))
+    }
+
+    pub fn zset_score(&mut self, key: &[u8], member: &[u8]) -> Result<Option<f64>, RedisError> {
+        Ok(self
+            .lookup::<ZSet>(key)?
+            .and_then(|zset| zset.score(member)))
+    }
+
+    pub fn zset_remove(&mut self, key: &[u8], members: &[Vec<u8>]) -> Result<usize, RedisError> {
+        let Some(zset) = self.lookup_mut::<ZSet>(key)? else {
+            return Ok(0);
+        };
+        let removed = members.iter().filter(|member| zset.remove(member)).count();
+        self.remove_if_empty(key);
+        Ok(removed)
+    }
+
+    pub fn zset_len(&mut self, key: &[u8]) -> Result<usize, RedisError> {
+        Ok(self.lookup::<ZSet>(key)?.map_or(0, ZSet::len))
+    }
+
+    /// Removes and returns up to `count` members with the lowest scores, or
+    /// the highest when `max` is set.
+    pub fn zset_pop(
+        &mut self,
+        key: &[u8],
+        count: usize,
+        max: bool,
+    ) -> Result<Vec<(Vec<u8>, f64)>, RedisError> {
+        let Some(zset) = self.lookup_mut::<ZSet>(key)? else {
+            return Ok(Vec::new());
+        };
+        let popped = zset.pop(count, max);
+        self.remove_if_empty(key);
+        Ok(popped)
+    }
+
+    /// Stores the result of `op` over the sorted sets or sets at `keys` in
+    /// `dst`, with the scores from each key multiplied by its weight and
+    /// those of a member combined by `aggregate`. Returns the size of the
+    /// result, and deletes `dst` if it is empty.
+    pub fn zset_combine_store(
+        &mut self,
+        op: SetOp,
+        dst: &[u8],
+        keys: &[Vec<u8>],
+        weights: &[f64],
+        aggregate: Aggregate,
+    ) -> Result<usize, RedisError> {
+        for key in keys {
+            if let Some(data) = self.get(key) {
+                if !matches!(data.value, Value::Set(_) | Value::ZSet(_)) {
+                    return Err(RedisError::WrongType);
+                }
+            }
+        }
+        let sources: Vec<Option<ScoredSource>> = keys
+            .iter()
+            .map(|key| {
+                self.data.get(key).map(|data| match &data.value {
+                    Value::Set(set) => ScoredSource::Set(set),
+                    Value::ZSet(zset) => ScoredSource::ZSet(zset),
+                    _ => unreachable!("types were just checked"),
+                })
+            })
+            .collect();
+        // As in redis, products and sums that come out as NaN, from infinite
+        // scores, count as zero.
+        let weigh = |score: f64, weight: f64| match score * weight {
+            product if product.is_nan() => 0.0,
+            product => product,
+        };
+        let combine = |a: f64, b: f64| match aggregate {
+            Aggregate::Sum if (a + b).is_nan() => 0.0,
+            Aggregate::Sum => a + b,
+            Aggregate::Min => a.min(b),
+            Aggregate::Max => a.max(b),
+        };
+        let mut scores: Dict<Vec<u8>, f64> = Dict::new();
+        match op {
+            SetOp::Union => {
+                for (source, &weight) in sources.iter().zip(weights) {
+                    for (member, score) in source.iter().flat_map(ScoredSource::iter) {
+                        let score = weigh(score, weight);
+                        match scores.get_mut(&member) {
+                            Some(current) => *current = combine(*current, score),
+                            None => {
+                                scores.insert(member, score);
+                            }
+                        }
+                    }
+                }
+            }
+            SetOp::Inter => {
+                // A missing key is an empty set, which empties the result.
+                let mut sources: Vec<(ScoredSource, f64)> = sources
+                    .into_iter()
+                    .zip(weights.iter().copied())
+                    .map(|(source, weight)| source.map(|source| (source, weight)))
+                    .collect::<Option<_>>()
+                    .unwrap_or_default();
+                // Walk the smallest input and probe the others.
+                sources.sort_by_key(|(source, _)| source.len());
+                if let Some(((smallest, weight), others)) = sources.split_first() {
+                    'members: for (member, score) in smallest.iter() {
+                        let mut score = weigh(score, *weight);
+                        for (other, weight) in others {
+                            match other.score(&member) {
+                                Some(other) => score = combine(score, weigh(other, *weight)),
+                                None => continue 'members,
+                            }
+                        }
+                        scores.insert(member, score);
+                    }
+                }
+            }
+            SetOp::Diff => {
+                if let Some((Some(first), others)) = sources.split_first() {
+                    for (member, score) in first.iter() {
+                        if others
+                            .iter()
+                            .flatten()
+                            .all(|other| other.score(&member).is_none())
+                        {
+                            scores.insert(member, weigh(score, weights[0]));
+                        }
+                    }
+                }
+            }
+        }
+        let mut result = ZSet::default();
+        for (member, score) in scores.iter() {
+            result.insert(member, *score);
+        }
+        let len = result.len();
+        if result.is_empty() {
+            self.remove(dst);
+        } else {
+            self.insert(dst.to_vec(), DataValue::new(Value::ZSet(result)));
+        }
+        Ok(len)
+    }
+
+    /// Appends an entry to the stream at `key` like XADD, trimming the
+    /// stream afterwards if asked to, and returns the entry's ID. Returns
+    /// `None` instead of creating the stream with NOMKSTREAM.
+    pub fn stream_add(
+        &mut self,
+        key: &[u8],
+        id: StreamAddId,
+        fields: &FieldValues,
+        no_mkstream: bool,
+        trim: Option<StreamTrim>,
+    ) -> Result<Option<StreamId>, RedisError> {
+        let last = match self.lookup::<Stream>(key)? {
+            Some(stream) => stream.last_id(),
+            None if no_mkstream => return Ok(None),
+            None => StreamId::MIN,
+        };
+        let id = match id {
+            StreamAddId::Auto => {
+                let now = StreamId {
+                    ms: unix_time_millis() as u64,
+                    seq: 0,
+                };
+                let next = last.next().ok_or(RedisError::other(
+                    "ERR The stream has exhausted the last possible ID, unable to add more items",
+                ))?;
+                // Stay ordered even if the clock went backwards.
+                now.max(next)
+            }
+            StreamAddId::AutoSeq(ms) if ms > last.ms => StreamId { ms, seq: 0 },
+            StreamAddId::AutoSeq(ms) => match last.seq.checked_add(1) {
+                Some(seq) if ms == last.ms => StreamId { ms, seq },
+                _ => return Err(RedisError::other(ERR_STREAM_ID_TOO_SMALL)),
+            },
+            StreamAddId::Explicit(StreamId::MIN) => {
+                return Err(RedisError::other(
+                    "ERR The ID specified in XADD must be greater than 0-0",
+                ))
+            }
+            StreamAddId::Explicit(id) if id <= last => {
+                return Err(RedisError::other(ERR_STREAM_ID_TOO_SMALL))
+            }
+            StreamAddId::Explicit(id) => id,
+        };
+        let stream = self.lookup_or_insert::<Stream>(key)?;
+        stream.append(id, fields);
+        if let Some(trim) = trim {
+            stream.trim(trim);
+        }
+        Ok(Some(id))
+    }
+
+    /// Returns up to `count` entries of the stream at `key` with IDs from
+    /// `start` to `end`, from the last one down when `rev` is set.
+    pub fn stream_range(
+        &mut self,
+        key: &[u8],
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+        rev: bool,
+    ) -> Result<Vec<StreamEntry>, RedisError> {
+        Ok(self
+            .lookup::<Stream>(key)?
+            .map_or_else(Vec::new, |stream| stream.range(start, end, count, rev)))
+    }
+
+    pub fn stream_last_id(&mut self, key: &[u8]) -> Result<Option<StreamId>, RedisError> {
+        Ok(self.lookup::<Stream>(key)?.map(Stream::last_id))
+    }
+
+    pub fn stream_len(&mut self, key: &[u8]) -> Result<usize, RedisError> {
+        Ok(self.lookup::<Stream>(key)?.map_or(0, Stream::len))
+    }
+
+    pub fn stream_trim(&mut self, key: &[u8], trim: StreamTrim) -> Result<usize, RedisError> {
+        Ok(self
+            .lookup_mut::<Stream>(key)?
+            .map_or(0, |stream| stream.trim(trim)))
+    }
+
+    pub fn stream_delete(&mut self, key: &[u8], ids: &[StreamId]) -> Result<usize, RedisError> {
+        let Some(stream) = self.lookup_mut::<Stream>(key)? else {
+            return Ok(0);
+        };
+        Ok(ids.iter().filter(|id| stream.delete(**id)).count())
+    }
+
+    /// Reads the entries after the given IDs from each stream like XREAD,
+    /// where `None` stands for `$`, the last ID of the stream. Streams with
+    /// nothing new are left out.
+    pub fn stream_read(
+        &mut self,
+        streams: &[(Vec<u8>, Option<StreamId>)],
+        count: Option<usize>,
+    ) -> Result<StreamsRead<StreamEntry>, RedisError> {
+        let mut result = Vec::new();
+        for (key, after) in streams {
+            let Some(stream) = self.lookup::<Stream>(key)? else {
+                continue;
+            };
+            let Some(start) = after.unwrap_or(stream.last_id()).next() else {
+                continue;
+            };
+            let entries = stream.range(start, StreamId::MAX, count, false);
+            if !entries.is_empty() {
+                result.push((key.clone(), entries));
+            }
+        }
+        Ok(result)
+    }
+
+    /// Creates a consumer group on the stream at `key` that has read up to
+    /// `id`, or to the end of the stream if it is `None`. With `mkstream`,
+    /// a missing stream is created empty.
+    pub fn stream_group_create(
+        &mut self,
+        key: &[u8],
+        group: &[u8],
+        id: Option<StreamId>,
+        mkstream: bool,
+    ) -> Result<(), RedisError> {
+        if !mkstream && self.lookup::<Stream>(key)?.is_none() {
+            return Err(RedisError::other(ERR_XGROUP_NO_KEY));
+        }
+        let stream = self.lookup_or_insert::<Stream>(key)?;
+        let id = id.unwrap_or(stream.last_id());
+        match stream.create_group(group, id) {
+            true => Ok(()),
+            false => Err(RedisError::other(
+                "BUSYGROUP Consumer Group name already exists",
+            )),
+        }
+    }
+
+    pub fn stream_group_set_id(
+        &mut self,
+        key: &[u8],
+        group: &[u8],
+        id: Option<StreamId>,
+    ) -> Result<(), RedisError> {
+        let stream = self.lookup_group(key, group)?;
+        let id = id.unwrap_or(stream.last_id());
+        stream
+            .group_mut(group)
+            .expect("group was just found")
+            .last_delivered = id;
+        Ok(())
+    }
+
+    pub fn stream_group_destroy(&mut self, key: &[u8], group: &[u8]) -> Result<bool, RedisError> {
+        let Some(stream) = self.lookup_mut::<Stream>(key)? else {
+            return Err(RedisError::other(ERR_XGROUP_NO_KEY));
+        };
+        Ok(stream.destroy_group(group))
+    }
+
+    /// Adds `consumer` to a group, returning whether it was not there yet.
+    pub fn stream_group_create_consumer(
+        &mut self,
+        key: &[u8],
+        group: &[u8],
+        consumer: &[u8],
+    ) -> Result<bool, RedisError> {
+        let stream = self.lookup_group(key, group)?;
+        let group = stream.group_mut(group).expect("group was just found");
+        if group.consumers.contains_key(consumer) {
+            return Ok(false);
+        }
+        let seen_at = unix_time_millis();
+        group
+            .consumers
+            .insert(consumer.to_vec(), stream::Consumer { seen_at });
+        Ok(true)
+    }
+
+    /// Removes `consumer` from a group along with its pending entries, and
+    /// returns how many it had.
+    pub fn stream_group_delete_consumer(
+        &mut self,
+        key: &[u8],
+        group: &[u8],
+        consumer: &[u8],
+    ) -> Result<usize, RedisError> {
+        let stream = self.lookup_group(key, group)?;
+        let group = stream.group_mut(group).expect("group was just found");
+        if group.consumers.remove(consumer).is_none() {
+            return Ok(0);
+        }
+        let before = group.pending.len();
+        group
+            .pending
+            .retain(|_, pending| pending.consumer != consumer);
+        Ok(before - group.pending.len())
+    }
+
+    /// Reads from each stream for `consumer` of `group` like XREADGROUP,
+    /// where an ID of `None` stands for `>`, the entries never delivered to
+    /// the group. Those are left out for streams with nothing new, while
+    /// the history of pending entries is always replied with, with `None`
+    /// for entries deleted since.
+    pub fn stream_read_group(
+        &mut self,
+        group: &[u8],
+        consumer: &[u8],
+        streams: &[(Vec<u8>, Option<StreamId>)],
+        count: Option<usize>,
+        no_ack: bool,
+    ) -> Result<StreamsRead<DeliveredEntry>, RedisError> {
+        // Check every stream before reading any, so that an error leaves
+        // nothing marked as delivered.
+        for (key, _) in streams {
+            if self
+                .lookup::<Stream>(key)?
+                .and_then(|s| s.group(group))
+                .is_none()
+            {
+                return Err(RedisError::other(format!(
+                    "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                    String::from_utf8_lossy(key),
+                    String::from_utf8_lossy(group))));
+            }
+        }
+        let now = unix_time_millis();
+        let mut result = Vec::new();
+        for (key, after) in streams {
+            let stream = self
+                .lookup_mut::<Stream>(key)?
+                .expect("stream was just found");
+            let entries = stream
+                .read_group(group, consumer, *after, count, no_ack, now)
+                .expect("group was just found");
+            if after.is_some() || !entries.is_empty() {
+                result.push((key.clone(), entries));
+            }
+        }
+        Ok(result)
+    }
+
+    /// Acknowledges pending entries of a group, and returns how many of
+    /// them were pending.
+    pub fn stream_ack(
+        &mut self,
+        key: &[u8],
+        group: &[u8],
+        ids: &[StreamId],
+    ) -> Result<usize, RedisError> {
+        let Some(group) = self
+            .lookup_mut::<Stream>(key)?
+            .and_then(|stream| stream.group_mut(group))
+        else {
+            return Ok(0);
+        };
+        Ok(ids
+            .iter()
+            .filter(|id| group.pending.remove(id).is_some())
+            .count())
+    }
+
+    pub fn stream_pending_summary(
+        &mut self,
+        key: &[u8],
+        group: &[u8],
+    ) -> Result<PendingSummary, RedisError> {
+        let stream = self.lookup_group(key, group)?;
+        let group = stream.group(group).expect("group was just found");
+        let mut consumers: Vec<(Vec<u8>, usize)> = Vec::new();
+        for pending in group.pending.values() {
+            match consumers
+                .iter_mut()
+                .find(|(name, _)| *name == pending.consumer)
+            {
+                Some((_, count)) => *count += 1,
+                None => consumers.push((pending.consumer.clone(), 1)),
+            }
+        }
+        consumers.sort();
+        let first = group.pending.first_key_value().map(|(id, _)| *id);
+        let last = group.pending.last_key_value().map(|(id, _)| *id);
+        Ok(PendingSummary {
+            count: group.pending.len(),
+            bounds: first.zip(last),
+            consumers,
+        })
+    }
+
+    /// Lists the pending entries of a group selected by `range`.
+    pub fn stream_pending_range(
+        &mut self,
+        key: &[u8],
+        group: &[u8],
+        range: &PendingRange,
+    ) -> Result<Vec<PendingEntryInfo>, RedisError> {
+        let stream = self.lookup_group(key, group)?;
+        let group = stream.group(group).expect("group was just found");
+        if range.start > range.end {
+            return Ok(Vec::new());
+        }
+        let now = unix_time_millis();
+        Ok(group
+            .pending
+            .range(range.start..=range.end)
+            .map(|(id, pending)| (id, pending, (now - pending.delivered_at).max(0)))
+            .filter(|(_, pending, idle)| {
+                *idle >= range.min_idle
+                    && range
+                        .consumer
+                        .as_ref()
+                        .is_none_or(|consumer| pending.consumer == *consumer)
+            })
+            .take(range.count)
+            .map(|(id, pending, idle)| PendingEntryInfo {
+                id: *id,
+                consumer: pending.consumer.clone(),
+                idle,
+                deliveries: pending.deliveries,
+            })
+            .collect())
+    }
+
+    /// Gives pending entries of a group that have been idle for at least
+    /// `min_idle` milliseconds to `consumer` like XCLAIM, and returns them.
+    pub fn stream_claim(
+        &mut self,
+        key: &[u8],
+        group: &[u8],
+        consumer: &[u8],
+        min_idle: i64,
+        ids: &[StreamId],
+        options: ClaimOptions,
+    ) -> Result<Vec<StreamEntry>, RedisError> {
+        let stream = self.lookup_group(key, group)?;
+        let claimed = stream.claim(group, consumer, min_idle, ids, options, unix_time_millis());
+        Ok(claimed.expect("group was just found"))
+    }
+
+    /// Claims up to `count` pending entries from `start` on like
+    /// XAUTOCLAIM.
+    #[allow(clippy::too_many_arguments)]
+    pub fn stream_auto_claim(
+        &mut self,
+        key: &[u8],
+        group: &[u8],
+        consumer: &[u8],
+        min_idle: i64,
+        start: StreamId,
+        count: usize,
+        just_id: bool,
+    ) -> Result<AutoClaimed, RedisError> {
+        let stream = self.lookup_group(key, group)?;
+        let now = unix_time_millis();
+        let claimed = stream.auto_claim(group, consumer, min_idle, start, count, just_id, now);
+        Ok(claimed.expect("group was just found"))
+    }
+
+    /// Looks up the stream at `key`, failing unless it has `group`.
+    fn lookup_group(&mut self, key: &[u8], group: &[u8]) -> Result<&mut Stream, RedisError> {
+        match self.lookup_mut::<Stream>(key)? {
+            Some(stream) if stream.group(group).is_some() => Ok(stream),
+            _ => Err(no_group_error(key, group)),
+        }
+    }
+
+    /// Returns a random live key.
+    pub fn random_key(&mut self) -> Option<Vec<u8>> {
+        // Like redis, give up on skipping expired keys after a while so that a
+        // keyspace full of them cannot keep us here.
+        const MAX_TRIES: usize = 100;
+        let now = Instant::now();
+        for tries in 1.. {
+            let (key, data) = self.data.random(&mut self.rng)?;
+            if !data.is_expired(now) || tries >= MAX_TRIES {
+                return Some(key.clone());
+            }
+            let key = key.clone();
+            self.remove(&key);
+        }
+        None
+    }
+
+    /// The number of keys, including expired ones that have not been evicted
+    /// yet.
+    pub fn dbsize(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn flush(&mut self) {
+        self.data.clear();
+        self.volatile.clear();
+    }
+
+    /// Returns every live key matching `pattern`.
+    pub fn keys(&self, pattern: &[u8]) -> Vec<Vec<u8>> {
+        let now = Instant::now();
+        self.data
+            .iter()
+            .filter(|(key, data)| !data.is_expired(now) && glob::matches(pattern, key))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Returns the next batch of keys from `cursor` on, along with the cursor
+    /// to continue from, which is zero once the scan is complete. A key that
+    /// exists for the whole scan is returned at least once, even if the table
+    /// is resized in between calls.
+    pub fn scan(&mut self, cursor: u64, options: &ScanOptions) -> (u64, Vec<Vec<u8>>) {
+        let mut batch = Vec::new();
+        let cursor = scan_dict(&self.data, cursor, options.count, |key, _| {
+            batch.push(key.clone())
+        });
+        let mut keys = Vec::new();
+        for key in batch {
+            // Looking the key up evicts it if it has expired.
+            let Some(key_type) = self.key_type(&key) else {
+                continue;
+            };
+            if options
+                .pattern
+                .as_ref()
+                .is_some_and(|pattern| !glob::matches(pattern, &key))
+            {
+                continue;
+            }
+            if options
+                .key_type
+                .as_ref()
+                .is_some_and(|wanted| !wanted.eq_ignore_ascii_case(key_type))
+            {
+                continue;
+            }
+            keys.push(key);
+        }
+        (cursor, keys)
+    }
+
+    /// Stores `data` under `key`, keeping the set of keys with a TTL in sync.
+    fn insert(&mut self, key: Vec<u8>, data: DataValue) -> Option<DataValue> {
+        self.signal_ready(&key);
+        match data.expiry {
+            Some(_) => self.volatile.insert(&key),
+            None => self.volatile.remove(&key),
+        }
+        self.data.insert(key, data)
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Option<DataValue> {
+        let removed = self.data.remove(key)?;
+        if removed.expiry.is_some() {
+            self.volatile.remove(key);
+        }
+        Some(removed)
+    }
+
+    /// Notes that a client blocked on `key`, so that writes to it show up in
+    /// `take_ready_keys`.
+    pub fn block_on(&mut self, key: &[u8]) {
+        match self.blocking_keys.get_mut(key) {
+            Some(clients) => *clients += 1,
+            None => {
+                self.blocking_keys.insert(key.to_vec(), 1);
+            }
+        }
+    }
+
+    pub fn unblock_on(&mut self, key: &[u8]) {
+        match self.blocking_keys.get_mut(key) {
+            Some(clients) if *clients > 1 => *clients -= 1,
+            Some(_) => {
+                self.blocking_keys.remove(key);
+            }
+            None => {}
+        }
+    }
+
+    /// Returns the keys clients are blocked on that have been written since
+    /// the last call, which may now let some of those clients be served.
+    pub fn take_ready_keys(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.ready_keys)
+    }
+
+    /// Swaps the keys of two databases like SWAPDB. Blocked clients stay on
+    /// the database index they selected, so every key they wait on is
+    /// checked again.
+    pub fn swap_keys(&mut self, other: &mut Storage) {
+        std::mem::swap(&mut self.data, &mut other.data);
+        std::mem::swap(&mut self.volatile, &mut other.volatile);
+        for storage in [self, other] {
+            let keys: Vec<Vec<u8>> = storage
+                .blocking_keys
+                .iter()
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in keys {
+                storage.signal_ready(&key);
+            }
+        }
+    }
+
+    /// Records that `key` is being written, if a client is blocked on it.
+    fn signal_ready(&mut self, key: &[u8]) {
+        if self.blocking_keys.contains_key(key) && !self.ready_keys.iter().any(|ready| ready == key)
+        {
+            self.ready_keys.push(key.to_vec());
+        }
+    }
+
+    /// Moves keys into resized tables for up to `budget`, so that a resize
+    /// started by a burst of writes completes even if the server goes idle.
+    pub fn rehash_for(&mut self, budget: Duration) {
+        self.data.rehash_for(budget);
+        self.volatile.rehash_for(budget);
+    }
+
+    /// Evicts expired keys by sampling the keys that have a TTL, so that keys
+    /// which are never read again do not stay in memory forever. Stops once a
+    /// sample comes back mostly live or `budget` has been used up, which keeps
+    /// the time taken away from clients bounded. Returns the number of keys
+    /// evicted.
+    pub fn active_expire_cycle(&mut self, budget: Duration) -> usize {
+        let start = Instant::now();
+        let mut evicted = 0;
+        loop {
+            let now = Instant::now();
+            let samples = self.volatile.len().min(ACTIVE_EXPIRE_KEYS_PER_LOOP);
+            let mut expired = 0;
+            for _ in 0..samples {
+                let Some(key) = self.volatile.random(&mut self.rng) else {
+                    break;
+                };
+                if self.data.get(key).is_some_and(|d| d.is_expired(now)) {
+                    let key = key.to_vec();
+                    self.remove(&key);
+                    expired += 1;
+                }
+            }
+            evicted += expired;
+            if samples == 0
+                || expired * 100 <= samples * ACTIVE_EXPIRE_ACCEPTABLE_STALE_PERCENT
+                || start.elapsed() >= budget
+            {
+                break;
+            }
+        }
+        evicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn px(millis: i64) -> SetOptions {
+        SetOptions {
+            expiry: SetExpiry::In(millis),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_get_removes_expired_key() {
+        let mut storage = Storage::new();
+        storage.set(b"key".to_vec(), b"value".to_vec(), px(1));
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(storage.get(b"key").is_none());
+        assert!(storage.data.is_empty());
+        assert_eq!(storage.volatile.len(), 0);
+    }
+
+    #[test]
+    fn test_set_without_expiry_clears_ttl() {
+        let mut storage = Storage::new();
+        storage.set(b"key".to_vec(), b"value".to_vec(), px(1));
+        storage.set(b"key".to_vec(), b"value".to_vec(), SetOptions::default());
+        assert_eq!(storage.volatile.len(), 0);
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(storage.get_string(b"key").unwrap().unwrap(), b"value");
+    }
+
+    #[test]
+    fn test_active_expire_cycle() {
+        let mut storage = Storage::new();
+        for i in 0..100 {
+            storage.set(format!("short{}", i).into_bytes(), b"v".to_vec(), px(1));
+        }
+        for i in 0..10 {
+            storage.set(format!("long{}", i).into_bytes(), b"v".to_vec(), px(60_000));
+        }
+        storage.set(b"persistent".to_vec(), b"v".to_vec(), SetOptions::default());
+        std::thread::sleep(Duration::from_millis(5));
+        let mut evicted = 0;
+        while evicted < 100 {
+            evicted += storage.active_expire_cycle(Duration::from_millis(25));
+        }
+        assert_eq!(evicted, 100);
+        assert_eq!(storage.data.len(), 11);
+        assert_eq!(storage.volatile.len(), 10);
+    }
+
+    #[test]
+    fn test_expire_conditions() {
+        let mut storage = Storage::new();
+        let now = unix_time_millis();
+        let nx = ExpireCondition {
+            nx: true,
+            ..Default::default()
+        };
+        let gt = ExpireCondition {
+            gt: true,
+            ..Default::default()
+        };
+        let lt = ExpireCondition {
+            lt: true,
+            ..Default::default()
+        };
+        assert!(!storage.expire_at(b"missing", now + 10_000, ExpireCondition::default()));
+        storage.set(b"key".to_vec(), b"value".to_vec(), SetOptions::default());
+        assert!(!storage.expire_at(b"key", now + 10_000, gt));
+        assert!(storage.expire_at(b"key", now + 10_000, nx));
+        assert!(!storage.expire_at(b"key", now + 20_000, nx));
+        assert!(!storage.expire_at(b"key", now + 5_000, gt));
+        assert!(storage.expire_at(b"key", now + 20_000, gt));
+        assert!(!storage.expire_at(b"key", now + 30_000, lt));
+        assert!(storage.expire_at(b"key", now + 5_000, lt));
+        let ttl = storage.get(b"key").unwrap().ttl().unwrap();
+        assert!(ttl <= Duration::from_millis(5_000) && ttl > Duration::from_millis(4_000));
+        assert!(storage.persist(b"key"));
+        assert!(!storage.persist(b"key"));
+        assert_eq!(storage.volatile.len(), 0);
+    }
+
+    #[test]
+    fn test_expire_in_the_past_deletes_key() {
+        let mut storage = Storage::new();
+        storage.set(b"key".to_vec(), b"value".to_vec(), SetOptions::default());
+        assert!(storage.expire_at(b"key", unix_time_millis() - 1, ExpireCondition::default()));
+        assert!(storage.get(b"key").is_none());
+    }
+
+    #[test]
+    fn test_set_conditions() {
+        let mut storage = Storage::new();
+        let nx = SetOptions {
+            condition: SetCondition::NX,
+            ..Default::default()
+        };
+        let xx = SetOptions {
+            condition: SetCondition::XX,
+            ..Default::default()
+        };
+        assert!(!storage.set(b"key".to_vec(), b"1".to_vec(), xx).written);
+        assert!(storage.get(b"key").is_none());
+        let outcome = storage.set(b"key".to_vec(), b"1".to_vec(), nx);
+        assert!(outcome.written);
+        assert_eq!(outcome.previous, None);
+        let outcome = storage.set(b"key".to_vec(), b"2".to_vec(), nx);
+        assert!(!outcome.written);
+        assert_eq!(outcome.previous, Some(b"1".to_vec()));
+        let outcome = storage.set(b"key".to_vec(), b"3".to_vec(), xx);
+        assert!(outcome.written);
+        assert_eq!(outcome.previous, Some(b"1".to_vec()));
+        assert_eq!(storage.get_string(b"key").unwrap().unwrap(), b"3");
+    }
+
+    #[test]
+    fn test_set_keepttl() {
+        let mut storage = Storage::new();
+        storage.set(b"key".to_vec(), b"1".to_vec(), px(60_000));
+        let keepttl = SetOptions {
+            expiry: SetExpiry::KeepTtl,
+            ..Default::default()
+        };
+        storage.set(b"key".to_vec(), b"2".to_vec(), keepttl);
+        assert!(storage.get(b"key").unwrap().ttl().is_some());
+        assert_eq!(storage.volatile.len(), 1);
+        storage.set(b"key".to_vec(), b"3".to_vec(), SetOptions::default());
+        assert!(storage.get(b"key").unwrap().ttl().is_none());
+        assert_eq!(storage.volatile.len(), 0);
+    }
+
+    #[test]
+    fn test_set_with_past_deadline() {
+        let mut storage = Storage::new();
+        storage.set(b"key".to_vec(), b"1".to_vec(), SetOptions::default());
+        let pxat = SetOptions {
+            expiry: SetExpiry::At(1),
+            get: true,
+            ..Default::default()
+        };
+        let outcome = storage.set(b"key".to_vec(), b"2".to_vec(), pxat);
+        assert!(outcome.written);
+        assert_eq!(outcome.previous, Some(b"1".to_vec()));
+        assert!(storage.get(b"key").is_none());
+    }
+
+    #[test]
+    fn test_incr_by() {
+        let mut storage = Storage::new();
+        assert_eq!(storage.incr_by(b"counter", 5).unwrap(), 5);
+        assert_eq!(storage.incr_by(b"counter", -7).unwrap(), -2);
+        assert_eq!(storage.get_string(b"counter").unwrap().unwrap(), b"-2");
+        storage.set(
+            b"max".to_vec(),
+            i64::MAX.to_string().into_bytes(),
+            px(60_000),
+        );
+        assert_eq!(
+            storage.incr_by(b"max", 1).unwrap_err().to_string(),
+            "ERR increment or decrement would overflow"
+        );
+        assert_eq!(storage.incr_by(b"max", -1).unwrap(), i64::MAX - 1);
+        assert!(storage.get(b"max").unwrap().ttl().is_some());
+        storage.set(b"text".to_vec(), b"abc".to_vec(), SetOptions::default());
+        assert_eq!(
+            storage.incr_by(b"text", 1).unwrap_err().to_string(),
+            "ERR value is not an integer or out of range"
+        );
+    }
+
+    #[test]
+    fn test_incr_by_float() {
+        let mut storage = Storage::new();
+        storage.set(b"f".to_vec(), b"10.50".to_vec(), SetOptions::default());
+        assert_eq!(storage.incr_by_float(b"f", 0.1).unwrap(), b"10.6");
+        assert_eq!(storage.incr_by_float(b"g", 5.0e3).unwrap(), b"5000");
+        assert_eq!(storage.incr_by(b"g", 1).unwrap(), 5001);
+        assert_eq!(storage.incr_by_float(b"h", 0.1).unwrap(), b"0.1");
+        assert_eq!(storage.incr_by_float(b"h", 0.2).unwrap(), b"0.3");
+        assert_eq!(storage.incr_by_float(b"h", -0.3).unwrap(), b"0");
+    }
+
+    #[test]
+    fn test_float_sum() {
+        let sum = |a: f64, b: f64| String::from_utf8(float_sum(a, b)).unwrap();
+        assert_eq!(sum(0.1, 0.2), "0.3");
+        assert_eq!(sum(-1.5, 0.25), "-1.25");
+        assert_eq!(sum(1e30, 1.0), "1000000000000000000000000000000");
+        assert_eq!(sum(-1.5e17, 0.0), "-150000000000000000");
+        assert_eq!(sum(1e16, 1.0), "10000000000000001");
+        assert_eq!(sum(0.0001, 0.0), "0.0001");
+        assert_eq!(sum(0.00001, 0.0), "0.00001");
+        assert_eq!(sum(1.0, 1e-300), "1");
+        // Rounded to 17 significant digits.
+        assert_eq!(sum(0.12345678901234567, 1.0), "1.1234567890123457");
+        assert_eq!(sum(99999999999999999.0, 0.0), "100000000000000000");
+        // Rounded to 17 decimals.
+        assert_eq!(sum(1.5e-17, 0.0), "0.00000000000000002");
+        assert_eq!(sum(-1e-18, 0.0), "0");
+        assert_eq!(sum(1e-300, 1e-300), "0");
+    }
+
+    #[test]
+    fn test_string_ranges() {
+        let mut storage = Storage::new();
+        assert_eq!(storage.append(b"s", b"Hello").unwrap(), 5);
+        assert_eq!(storage.append(b"s", b" World").unwrap(), 11);
+        assert_eq!(storage.get_range(b"s", 0, 4).unwrap(), b"Hello");
+        assert_eq!(storage.get_range(b"s", -5, -1).unwrap(), b"World");
+        assert_eq!(storage.get_range(b"s", 5, 100).unwrap(), b" World");
+        assert_eq!(storage.get_range(b"s", 7, 2).unwrap(), b"");
+        assert_eq!(storage.get_range(b"s", -100, -200).unwrap(), b"");
+        assert_eq!(storage.get_range(b"s", -100, -11).unwrap(), b"H");
+        assert_eq!(storage.get_range(b"missing", 0, -1).unwrap(), b"");
+        assert_eq!(storage.set_range(b"s", 6, b"Redis").unwrap(), 11);
+        assert_eq!(storage.get_string(b"s").unwrap().unwrap(), b"Hello Redis");
+        assert_eq!(storage.set_range(b"padded", 3, b"x").unwrap(), 4);
+        assert_eq!(storage.get_string(b"padded").unwrap().unwrap(), b"\0\0\0x");
+        assert_eq!(storage.set_range(b"empty", 10, b"").unwrap(), 0);
+        assert!(storage.get(b"empty").is_none());
+    }
+
+    #[test]
+    fn test_bitmaps() {
+        let mut storage = Storage::new();
+        assert!(!storage.set_bit(b"b", 7, true).unwrap());
+        assert!(storage.set_bit(b"b", 7, true).unwrap());
+        assert!(storage.get_bit(b"b", 7).unwrap());
+        assert!(!storage.get_bit(b"b", 100).unwrap());
+        assert_eq!(storage.bit_count(b"b", None).unwrap(), 1);
+        assert_eq!(
+            storage
+                .bit_position(b"b", true, 0, None, BitUnit::Byte)
+                .unwrap(),
+            7
+        );
+        assert_eq!(
+            storage
+                .bit_position(b"missing", false, 0, None, BitUnit::Byte)
+                .unwrap(),
+            0
+        );
+        storage.set(b"ones".to_vec(), vec![0xff], SetOptions::default());
+        assert_eq!(
+            storage
+                .bit_position(b"ones", false, 0, None, BitUnit::Byte)
+                .unwrap(),
+            8
+        );
+        assert_eq!(
+            storage
+                .bit_position(b"ones", false, 0, Some(-1), BitUnit::Byte)
+                .unwrap(),
+            -1
+        );
+        assert_eq!(
+            storage
+                .bit_op(BitOp::Or, b"dst", &[b"b".to_vec(), b"ones".to_vec()])
+                .unwrap(),
+            1
+        );
+        assert_eq!(storage.get_string(b"dst").unwrap().unwrap(), [0xff]);
+        assert_eq!(
+            storage
+                .bit_op(BitOp::And, b"dst", &[b"missing".to_vec()])
+                .unwrap(),
+            0
+        );
+        assert!(storage.get(b"dst").is_none());
+        storage
+            .push(b"list", ListEnd::Left, &[b"a".to_vec()])
+            .unwrap();
+        assert!(storage.set_bit(b"list", 0, true).is_err());
+        assert!(storage
+            .bit_op(BitOp::Not, b"dst", &[b"list".to_vec()])
+            .is_err());
+    }
+
+    #[test]
+    fn test_bit_field() {
+        let mut storage = Storage::new();
+        let u8 = BitfieldType {
+            signed: false,
+            bits: 8,
+        };
+        let get = [BitfieldOp::Get(u8, 0)];
+        assert_eq!(storage.bit_field(b"f", &get).unwrap(), vec![Some(0)]);
+        assert!(storage.get(b"f").is_none());
+        let ops = [
+            BitfieldOp::Set(u8, 0, 250),
+            BitfieldOp::IncrBy(u8, 0, 10),
+            BitfieldOp::Overflow(Overflow::Sat),
+            BitfieldOp::IncrBy(u8, 0, 300),
+            BitfieldOp::Overflow(Overflow::Fail),
+            BitfieldOp::IncrBy(u8, 0, 1),
+            BitfieldOp::Get(u8, 0),
+        ];
+        assert_eq!(
+            storage.bit_field(b"f", &ops).unwrap(),
+            vec![Some(0), Some(4), Some(255), None, Some(255)]
+        );
+        // Writes that fail still grow the string to fit.
+        let ops = [
+            BitfieldOp::Overflow(Overflow::Fail),
+            BitfieldOp::Set(u8, 16, -1),
+        ];
+        assert_eq!(storage.bit_field(b"f", &ops).unwrap(), vec![None]);
+        assert_eq!(storage.get_string(b"f").unwrap().unwrap(), [0xff, 0, 0]);
+    }
+
+    #[test]
+    fn test_msetnx() {
+        let mut storage = Storage::new();
+        assert!(storage.msetnx(vec![
+            (b"a".to_vec(), b"1".to_vec()),
+            (b"b".to_vec(), b"2".to_vec()),
+        ]));
+        assert!(!storage.msetnx(vec![
+            (b"c".to_vec(), b"3".to_vec()),
+            (b"a".to_vec(), b"4".to_vec()),
+        ]));
+        assert!(storage.get(b"c").is_none());
+        assert_eq!(storage.get_string(b"a").unwrap().unwrap(), b"1");
+    }
+
+    #[test]
+    fn test_rename_keeps_ttl() {
+        let mut storage = Storage::new();
+        storage.set(b"src".to_vec(), b"1".to_vec(), px(60_000));
+        storage.set(b"dst".to_vec(), b"2".to_vec(), SetOptions::default());
+        assert!(!storage.rename(b"src", b"dst", true).unwrap());
+        assert!(storage.rename(b"src", b"dst", false).unwrap());
+        assert!(!storage.exists(b"src"));
+        assert_eq!(storage.get_string(b"dst").unwrap().unwrap(), b"1");
+        assert!(storage.get(b"dst").unwrap().ttl().is_some());
+        assert_eq!(storage.volatile.len(), 1);
+        assert_eq!(
+            storage
+                .rename(b"src", b"dst", false)
+                .unwrap_err()
+                .to_string(),
+            "ERR no such key"
+        );
+    }
+
+    #[test]
+    fn test_copy() {
+        let mut storage = Storage::new();
+        storage.set(b"src".to_vec(), b"1".to_vec(), px(60_000));
+        storage.set(b"dst".to_vec(), b"2".to_vec(), SetOptions::default());
+        assert!(!storage.copy(b"src", b"dst", None, false).unwrap());
+        assert!(storage.copy(b"src", b"dst", None, true).unwrap());
+        assert_eq!(storage.get_string(b"dst").unwrap().unwrap(), b"1");
+        assert!(storage.get(b"dst").unwrap().ttl().is_some());
+        assert!(!storage.copy(b"missing", b"other", None, false).unwrap());
+        assert!(storage.copy(b"src", b"src", None, false).is_err());
+        let mut other = Storage::new();
+        assert!(storage
+            .copy(b"src", b"src", Some(&mut other), false)
+            .unwrap());
+        assert!(other.get(b"src").unwrap().ttl().is_some());
+    }
+
+    #[test]
+    fn test_move_to() {
+        let mut storage = Storage::new();
+        let mut other = Storage::new();
+        storage.set(b"a".to_vec(), b"1".to_vec(), px(60_000));
+        storage.set(b"b".to_vec(), b"2".to_vec(), SetOptions::default());
+        other.set(b"b".to_vec(), b"3".to_vec(), SetOptions::default());
+        assert!(storage.move_to(b"a", &mut other));
+        assert!(!storage.exists(b"a"));
+        assert!(other.get(b"a").unwrap().ttl().is_some());
+        assert_eq!(storage.volatile.len(), 0);
+        assert_eq!(other.volatile.len(), 1);
+        assert!(!storage.move_to(b"b", &mut other));
+        assert!(!storage.move_to(b"missing", &mut other));
+        assert_eq!(other.get_string(b"b").unwrap().unwrap(), b"3");
+    }
+
+    #[test]
+    fn test_random_key_skips_expired_keys() {
+        let mut storage = Storage::new();
+        assert_eq!(storage.random_key(), None);
+        for i in 0..10 {
+            storage.set(format!("short{}", i).into_bytes(), b"v".to_vec(), px(1));
+        }
+        storage.set(b"live".to_vec(), b"v".to_vec(), SetOptions::default());
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(storage.random_key(), Some(b"live".to_vec()));
+    }
+
+    #[test]
+    fn test_keys() {
+        let mut storage = Storage::new();
+        storage.mset(vec![
+            (b"user:1".to_vec(), b"a".to_vec()),
+            (b"user:2".to_vec(), b"b".to_vec()),
+            (b"session:1".to_vec(), b"c".to_vec()),
+        ]);
+        storage.set(b"user:3".to_vec(), b"d".to_vec(), px(1));
+        std::thread::sleep(Duration::from_millis(5));
+        let mut keys = storage.keys(b"user:*");
+        keys.sort();
+        assert_eq!(keys, vec![b"user:1".to_vec(), b"user:2".to_vec()]);
+    }
+
+    #[test]
+    fn test_scan_returns_every_key_while_growing() {
+        let mut storage = Storage::new();
+        for i in 0..100 {
+            storage.set(
+                format!("old{}", i).into_bytes(),
+                b"v".to_vec(),
+                SetOptions::default(),
+            );
+        }
+        let options = ScanOptions {
+            pattern: Some(b"old*".to_vec()),
+            ..Default::default()
+        };
+        let mut seen = std::collections::HashSet::new();
+        let mut cursor = 0;
+        let mut calls = 0;
+        loop {
+            let (next, keys) = storage.scan(cursor, &options);
+            seen.extend(keys);
+            // Grow the keyspace through a few resizes while the scan runs.
+            if calls < 50 {
+                for i in 0..10 {
+                    let key = format!("new{}-{}", calls, i).into_bytes();
+                    storage.set(key, b"v".to_vec(), SetOptions::default());
+                }
+            }
+            calls += 1;
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert_eq!(seen.len(), 100);
+    }
+
+    fn list(storage: &mut Storage, key: &[u8]) -> Vec<Vec<u8>> {
+        storage.list_range(key, 0, -1).unwrap()
+    }
+
+    fn elements(elements: &[&str]) -> Vec<Vec<u8>> {
+        elements.iter().map(|e| e.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let mut storage = Storage::new();
+        storage
+            .push(b"list", ListEnd::Right, &elements(&["a"]))
+            .unwrap();
+        storage.set(b"string".to_vec(), b"1".to_vec(), SetOptions::default());
+        let wrong_type = "WRONGTYPE Operation against a key holding the wrong kind of value";
+        assert_eq!(
+            storage.get_string(b"list").unwrap_err().to_string(),
+            wrong_type
+        );
+        assert_eq!(
+            storage.incr_by(b"list", 1).unwrap_err().to_string(),
+            wrong_type
+        );
+        assert_eq!(
+            storage
+                .push(b"string", ListEnd::Left, &elements(&["a"]))
+                .unwrap_err()
+                .to_string(),
+            wrong_type
+        );
+        assert_eq!(storage.key_type(b"list"), Some("list"));
+        storage.set(b"list".to_vec(), b"2".to_vec(), SetOptions::default());
+        assert_eq!(storage.key_type(b"list"), Some("string"));
+    }
+
+    #[test]
+    fn test_push_pop() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            storage
+                .push(b"l", ListEnd::Left, &elements(&["b", "a"]))
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            storage
+                .push(b"l", ListEnd::Right, &elements(&["c"]))
+                .unwrap(),
+            3
+        );
+        assert_eq!(list(&mut storage, b"l"), elements(&["a", "b", "c"]));
+        assert_eq!(
+            storage.pop(b"l", ListEnd::Right, 2).unwrap(),
+            Some(elements(&["c", "b"]))
+        );
+        assert_eq!(
+            storage.pop(b"l", ListEnd::Left, 5).unwrap(),
+            Some(elements(&["a"]))
+        );
+        assert!(!storage.exists(b"l"));
+        assert_eq!(storage.pop(b"l", ListEnd::Left, 1).unwrap(), None);
+    }
+
+    #[test]
+    fn test_list_editing() {
+        let mut storage = Storage::new();
+        let values = elements(&["a", "b", "a", "c", "a"]);
+        storage.push(b"l", ListEnd::Right, &values).unwrap();
+        assert_eq!(storage.list_index(b"l", -2).unwrap(), Some(b"c".to_vec()));
+        assert_eq!(storage.list_index(b"l", 5).unwrap(), None);
+        assert_eq!(storage.list_remove(b"l", -2, b"a").unwrap(), 2);
+        assert_eq!(list(&mut storage, b"l"), elements(&["a", "b", "c"]));
+        assert_eq!(storage.list_insert(b"l", true, b"c", b"x").unwrap(), 4);
+        assert_eq!(storage.list_insert(b"l", false, b"zz", b"x").unwrap(), -1);
+        assert_eq!(
+            storage.list_insert(b"missing", false, b"a", b"x").unwrap(),
+            0
+        );
+        storage.list_set(b"l", 0, b"z").unwrap();
+        assert_eq!(list(&mut storage, b"l"), elements(&["z", "b", "x", "c"]));
+        assert!(storage.list_set(b"l", 4, b"z").is_err());
+        storage.list_trim(b"l", 1, -2).unwrap();
+        assert_eq!(list(&mut storage, b"l"), elements(&["b", "x"]));
+        storage.list_trim(b"l", 5, 10).unwrap();
+        assert!(!storage.exists(b"l"));
+    }
+
+    #[test]
+    fn test_list_move_and_position() {
+        let mut storage = Storage::new();
+        storage
+            .push(b"src", ListEnd::Right, &elements(&["a", "b", "a"]))
+            .unwrap();
+        assert_eq!(
+            storage.list_position(b"src", b"a", 1, 0, 0).unwrap(),
+            vec![0, 2]
+        );
+        assert_eq!(
+            storage.list_position(b"src", b"a", -1, 1, 0).unwrap(),
+            vec![2]
+        );
+        assert_eq!(
+            storage.list_position(b"src", b"a", 2, 1, 2).unwrap(),
+            vec![]
+        );
+        let moved = storage.list_move(b"src", b"dst", ListEnd::Left, ListEnd::Right);
+        assert_eq!(moved.unwrap(), Some(b"a".to_vec()));
+        assert_eq!(list(&mut storage, b"dst"), elements(&["a"]));
+        storage.set(b"string".to_vec(), b"1".to_vec(), SetOptions::default());
+        assert!(storage
+            .list_move(b"src", b"string", ListEnd::Left, ListEnd::Left)
+            .is_err());
+        assert_eq!(list(&mut storage, b"src"), elements(&["b", "a"]));
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> FieldValues {
+        pairs
+            .iter()
+            .map(|(field, value)| (field.as_bytes().to_vec(), value.as_bytes().to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn test_hash_fields() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            storage
+                .hash_set(b"h", pairs(&[("a", "1"), ("b", "2")]))
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            storage
+                .hash_set(b"h", pairs(&[("a", "3"), ("c", "4")]))
+                .unwrap(),
+            1
+        );
+        assert_eq!(storage.hash_get(b"h", b"a").unwrap(), Some(b"3".to_vec()));
+        assert_eq!(
+            storage
+                .hash_mget(b"h", &elements(&["b", "missing"]))
+                .unwrap(),
+            vec![Some(b"2".to_vec()), None]
+        );
+        let mut all = storage.hash_get_all(b"h").unwrap();
+        all.sort();
+        assert_eq!(all, pairs(&[("a", "3"), ("b", "2"), ("c", "4")]));
+        assert_eq!(
+            storage.hash_delete(b"h", &elements(&["a", "x"])).unwrap(),
+            1
+        );
+        assert!(!storage.hash_exists(b"h", b"a").unwrap());
+        assert_eq!(
+            storage.hash_delete(b"h", &elements(&["b", "c"])).unwrap(),
+            2
+        );
+        assert!(!storage.exists(b"h"));
+        assert_eq!(storage.key_type(b"h"), None);
+    }
+
+    #[test]
+    fn test_hash_incr() {
+        let mut storage = Storage::new();
+        assert_eq!(storage.hash_incr_by(b"h", b"n", 5).unwrap(), 5);
+        assert_eq!(storage.hash_incr_by(b"h", b"n", -2).unwrap(), 3);
+        assert_eq!(storage.hash_incr_by_float(b"h", b"n", 0.5).unwrap(), b"3.5");
+        assert_eq!(
+            storage.hash_incr_by(b"h", b"n", 1).unwrap_err().to_string(),
+            "ERR hash value is not an integer"
+        );
+        storage.hash_set(b"h", pairs(&[("s", "abc")])).unwrap();
+        assert_eq!(
+            storage
+                .hash_incr_by_float(b"h", b"s", 1.0)
+                .unwrap_err()
+                .to_string(),
+            "ERR hash value is not a float"
+        );
+    }
+
+    #[test]
+    fn test_hash_scan_and_random() {
+        let mut storage = Storage::new();
+        let fields: Vec<(String, String)> = (0..100)
+            .map(|i| (format!("f{}", i), i.to_string()))
+            .collect();
+        let fields: Vec<(&str, &str)> = fields
+            .iter()
+            .map(|(f, v)| (f.as_str(), v.as_str()))
+            .collect();
+        storage.hash_set(b"h", pairs(&fields)).unwrap();
+        let mut seen = std::collections::HashSet::new();
+        let mut cursor = 0;
+        loop {
+            let (next, batch) = storage
+                .hash_scan(b"h", cursor, &ScanOptions::default())
+                .unwrap();
+            seen.extend(batch);
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert_eq!(seen.len(), 100);
+        let distinct = storage.hash_random(b"h", 80).unwrap();
+        let unique: std::collections::HashSet<_> = distinct.iter().collect();
+        assert_eq!(unique.len(), 80);
+        assert_eq!(storage.hash_random(b"h", 5).unwrap().len(), 5);
+        assert_eq!(storage.hash_random(b"h", 500).unwrap().len(), 100);
+        assert_eq!(storage.hash_random(b"h", -500).unwrap().len(), 500);
+        assert!(storage.hash_random(b"missing", -5).unwrap().is_empty());
+    }
+
+    fn members(items: &[&str]) -> Vec<Vec<u8>> {
+        items.iter().map(|item| item.as_bytes().to_vec()).collect()
+    }
+
+    fn sorted(mut members: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+        members.sort();
+        members
+    }
+
+    #[test]
+    fn test_set_members() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            storage
+                .set_add(b"s", &members(&["1", "2", "2", "3"]))
+                .unwrap(),
+            3
+        );
+        assert_eq!(storage.set_add(b"s", &members(&["a", "3"])).unwrap(), 1);
+        assert_eq!(storage.set_len(b"s").unwrap(), 4);
+        assert_eq!(
+            storage
+                .set_contains(b"s", &members(&["a", "b", "1"]))
+                .unwrap(),
+            vec![true, false, true]
+        );
+        assert_eq!(storage.set_remove(b"s", &members(&["a", "x"])).unwrap(), 1);
+        assert_eq!(
+            sorted(storage.set_members(b"s").unwrap()),
+            members(&["1", "2", "3"])
+        );
+        assert_eq!(storage.set_pop(b"s", 2).unwrap().len(), 2);
+        assert_eq!(storage.set_pop(b"s", 5).unwrap().len(), 1);
+        assert!(storage.get(b"s").is_none());
+        storage.set(b"str".to_vec(), b"v".to_vec(), SetOptions::default());
+        assert!(storage.set_add(b"str", &members(&["a"])).is_err());
+        assert!(storage
+            .set_combine(SetOp::Union, &members(&["s", "str"]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        let mut storage = Storage::new();
+        storage
+            .set_add(b"a", &members(&["1", "2", "3", "x"]))
+            .unwrap();
+        storage.set_add(b"b", &members(&["2", "3", "4"])).unwrap();
+        storage.set_add(b"c", &members(&["3", "x"])).unwrap();
+        let keys = members(&["a", "b", "c"]);
+        assert_eq!(
+            storage.set_combine(SetOp::Inter, &keys).unwrap(),
+            members(&["3"])
+        );
+        assert_eq!(
+            sorted(storage.set_combine(SetOp::Union, &keys).unwrap()),
+            members(&["1", "2", "3", "4", "x"])
+        );
+        assert_eq!(
+            storage.set_combine(SetOp::Diff, &keys).unwrap(),
+            members(&["1"])
+        );
+        assert!(storage
+            .set_combine(SetOp::Inter, &members(&["a", "missing"]))
+            .unwrap()
+            .is_empty());
+        assert_eq!(storage.set_inter_card(&members(&["a", "b"]), 0).unwrap(), 2);
+        assert_eq!(storage.set_inter_card(&members(&["a", "b"]), 1).unwrap(), 1);
+        assert_eq!(
+            storage
+                .set_combine_store(SetOp::Union, b"d", &keys)
+                .unwrap(),
+            5
+        );
+        assert_eq!(storage.set_len(b"d").unwrap(), 5);
+        assert_eq!(
+            storage
+                .set_combine_store(SetOp::Inter, b"d", &members(&["a", "missing"]))
+                .unwrap(),
+            0
+        );
+        assert!(storage.get(b"d").is_none());
+    }
+
+    #[test]
+    fn test_set_scan_and_random() {
+        let mut storage = Storage::new();
+        let all: Vec<String> = (0..100).map(|i| format!("m{}", i)).collect();
+        let all: Vec<&str> = all.iter().map(String::as_str).collect();
+        storage.set_add(b"s", &members(&all)).unwrap();
+        let mut seen = std::collections::HashSet::new();
+        let mut cursor = 0;
+        loop {
+            let (next, batch) = storage
+                .set_scan(b"s", cursor, &ScanOptions::default())
+                .unwrap();
+            seen.extend(batch);
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert_eq!(seen.len(), 100);
+        let distinct = storage.set_random(b"s", 80).unwrap();
+        let unique: std::collections::HashSet<_> = distinct.iter().collect();
+        assert_eq!(unique.len(), 80);
+        assert_eq!(storage.set_random(b"s", 500).unwrap().len(), 100);
+        assert_eq!(storage.set_random(b"s", -500).unwrap().len(), 500);
+    }
+
+    fn entries(items: &[(f64, &str)]) -> Vec<(f64, Vec<u8>)> {
+        items
+            .iter()
+            .map(|(score, member)| (*score, member.as_bytes().to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn test_zset_add_flags() {
+        let mut storage = Storage::new();
+        let options = |condition, gt, lt, ch| ZAddOptions {
+            condition,
+            gt,
+            lt,
+            ch,
+        };
+        let always = SetCondition::Always;
+        let added = storage.zset_add(
+            b"z",
+            &entries(&[(1.0, "a"), (2.0, "b")]),
+            ZAddOptions::default(),
+        );
+        assert_eq!(added.unwrap(), 2);
+        let xx = options(SetCondition::XX, false, false, false);
+        assert_eq!(
+            storage
+                .zset_add(b"missing", &entries(&[(1.0, "a")]), xx)
+                .unwrap(),
+            0
+        );
+        assert!(storage.get(b"missing").is_none());
+        let nx = options(SetCondition::NX, false, false, true);
+        assert_eq!(
+            storage
+                .zset_add(b"z", &entries(&[(5.0, "a"), (3.0, "c")]), nx)
+                .unwrap(),
+            1
+        );
+        assert_eq!(storage.zset_score(b"z", b"a").unwrap(), Some(1.0));
+        let gt_ch = options(always, true, false, true);
+        assert_eq!(
+            storage
+                .zset_add(b"z", &entries(&[(0.5, "a"), (4.0, "b")]), gt_ch)
+                .unwrap(),
+            1
+        );
+        assert_eq!(storage.zset_score(b"z", b"b").unwrap(), Some(4.0));
+        let lt = options(always, false, true, false);
+        assert_eq!(storage.zset_incr_by(b"z", b"b", 1.0, lt).unwrap(), None);
+        assert_eq!(
+            storage.zset_incr_by(b"z", b"b", -1.5, lt).unwrap(),
+            Some(2.5)
+        );
+        storage
+            .zset_add(
+                b"z",
+                &entries(&[(f64::INFINITY, "inf")]),
+                ZAddOptions::default(),
+            )
+            .unwrap();
+        assert!(storage
+            .zset_incr_by(b"z", b"inf", f64::NEG_INFINITY, ZAddOptions::default())
+            .is_err());
+        assert_eq!(storage.zset_rank(b"z", b"b").unwrap(), Some((1, 2.5)));
+        assert_eq!(storage.zset_rank(b"z", b"x").unwrap(), None);
+    }
+
+    #[test]
+    fn test_zset_range_and_pop() {
+        let mut storage = Storage::new();
+        let items = entries(&[(1.0, "a"), (2.0, "b"), (3.0, "c"), (4.0, "d")]);
+        storage
+            .zset_add(b"z", &items, ZAddOptions::default())
+            .unwrap();
+        let range = |storage: &mut Storage, by, rev, limit| {
+            let options = ZRangeOptions {
+                by,
+                rev,
+                limit,
+                with_scores: true,
+            };
+            storage
+                .zset_range(b"z", &options)
+                .unwrap()
+                .into_iter()
+                .map(|(member, _)| member)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            range(&mut storage, ZRangeBy::Rank(1, -2), false, None),
+            members(&["b", "c"])
+        );
+        assert_eq!(
+            range(&mut storage, ZRangeBy::Rank(0, 0), true, None),
+            members(&["d"])
+        );
+        let score = |score, exclusive| ScoreBound { score, exclusive };
+        let by_score = ZRangeBy::Score(score(1.0, true), score(f64::INFINITY, false));
+        assert_eq!(
+            range(&mut storage, by_score.clone(), true, Some((1, 1))),
+            members(&["c"])
+        );
+        assert!(range(&mut storage, by_score, false, Some((-1, 1))).is_empty());
+        assert_eq!(
+            storage.zset_pop(b"z", 3, true).unwrap(),
+            vec![
+                (b"d".to_vec(), 4.0),
+                (b"c".to_vec(), 3.0),
+                (b"b".to_vec(), 2.0)
+            ]
+        );
+        assert_eq!(storage.zset_remove(b"z", &members(&["a", "x"])).unwrap(), 1);
+        assert!(storage.get(b"z").is_none());
+    }
+
+    #[test]
+    fn test_zset_combine_store() {
+        let mut storage = Storage::new();
+        let items = entries(&[(1.0, "a"), (2.0, "b")]);
+        storage
+            .zset_add(b"z", &items, ZAddOptions::default())
+            .unwrap();
+        storage.set_add(b"s", &members(&["b", "c"])).unwrap();
+        let keys = members(&["z", "s"]);
+        let union =
+            storage.zset_combine_store(SetOp::Union, b"d", &keys, &[2.0, 10.0], Aggregate::Sum);
+        assert_eq!(union.unwrap(), 3);
+        assert_eq!(storage.zset_score(b"d", b"a").unwrap(), Some(2.0));
+        assert_eq!(storage.zset_score(b"d", b"b").unwrap(), Some(14.0));
+        assert_eq!(storage.zset_score(b"d", b"c").unwrap(), Some(10.0));
+        let inter =
+            storage.zset_combine_store(SetOp::Inter, b"d", &keys, &[1.0, 1.0], Aggregate::Max);
+        assert_eq!(inter.unwrap(), 1);
+        assert_eq!(storage.zset_score(b"d", b"b").unwrap(), Some(2.0));
+        let keys = members(&["z", "missing"]);
+        let inter =
+            storage.zset_combine_store(SetOp::Inter, b"d", &keys, &[1.0, 1.0], Aggregate::Sum);
+        assert_eq!(inter.unwrap(), 0);
+        assert!(storage.get(b"d").is_none());
+        storage.set(b"str".to_vec(), b"v".to_vec(), SetOptions::default());
+        let keys = members(&["z", "str"]);
+        assert!(storage
+            .zset_combine_store(SetOp::Union, b"d", &keys, &[1.0, 1.0], Aggregate::Sum)
+            .is_err());
+    }
+
+    fn stream_id(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    #[test]
+    fn test_stream_add_ids() {
+        let mut storage = Storage::new();
+        let fields = pairs(&[("f", "v")]);
+        let add = |storage: &mut Storage, id| storage.stream_add(b"s", id, &fields, false, None);
+        assert!(add(&mut storage, StreamAddId::Explicit(StreamId::MIN)).is_err());
+        // A rejected ID must not leave an empty stream behind.
+        assert!(storage.get(b"s").is_none());
+        assert_eq!(
+            add(&mut storage, StreamAddId::AutoSeq(0)).unwrap(),
+            Some(stream_id(0, 1))
+        );
+        assert_eq!(
+            add(&mut storage, StreamAddId::Explicit(stream_id(5, 5))).unwrap(),
+            Some(stream_id(5, 5))
+        );
+        assert!(add(&mut storage, StreamAddId::AutoSeq(4)).is_err());
+        assert!(add(&mut storage, StreamAddId::Explicit(stream_id(5, 5))).is_err());
+        assert_eq!(
+            add(&mut storage, StreamAddId::AutoSeq(5)).unwrap(),
+            Some(stream_id(5, 6))
+        );
+        let auto = add(&mut storage, StreamAddId::Auto).unwrap().unwrap();
+        assert!(auto.ms > 5);
+        assert_eq!(storage.stream_len(b"s").unwrap(), 4);
+        assert!(storage
+            .stream_add(b"t", StreamAddId::Auto, &fields, true, None)
+            .unwrap()
+            .is_none());
+        assert!(storage.get(b"t").is_none());
+        // Deleting every entry keeps the stream and its last ID.
+        let ids: Vec<StreamId> = storage
+            .stream_range(b"s", StreamId::MIN, StreamId::MAX, None, false)
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(storage.stream_delete(b"s", &ids).unwrap(), 4);
+        assert_eq!(storage.stream_len(b"s").unwrap(), 0);
+        assert!(add(&mut storage, StreamAddId::Explicit(stream_id(5, 7))).is_err());
+        storage.set(b"str".to_vec(), b"v".to_vec(), SetOptions::default());
+        assert!(storage
+            .stream_add(b"str", StreamAddId::Auto, &fields, false, None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_stream_groups() {
+        let mut storage = Storage::new();
+        assert!(storage
+            .stream_group_create(b"s", b"g", None, false)
+            .is_err());
+        storage.stream_group_create(b"s", b"g", None, true).unwrap();
+        assert!(storage.stream_group_create(b"s", b"g", None, true).is_err());
+        let fields = pairs(&[("f", "v")]);
+        for seq in 1..=3 {
+            let id = StreamAddId::Explicit(stream_id(1, seq));
+            storage.stream_add(b"s", id, &fields, false, None).unwrap();
+        }
+        let streams = vec![(b"s".to_vec(), None)];
+        let read = storage
+            .stream_read_group(b"g", b"alice", &streams, Some(2), false)
+            .unwrap();
+        assert_eq!(read[0].1.len(), 2);
+        storage
+            .stream_read_group(b"g", b"bob", &streams, None, false)
+            .unwrap();
+        assert!(storage
+            .stream_read_group(b"g", b"bob", &streams, None, false)
+            .unwrap()
+            .is_empty());
+        let missing = vec![(b"s".to_vec(), None), (b"t".to_vec(), None)];
+        assert!(storage
+            .stream_read_group(b"g", b"bob", &missing, None, false)
+            .is_err());
+        assert_eq!(
+            storage.stream_ack(b"s", b"g", &[stream_id(1, 1)]).unwrap(),
+            1
+        );
+        let summary = storage.stream_pending_summary(b"s", b"g").unwrap();
+        assert_eq!(summary.count, 2);
+        assert_eq!(summary.bounds, Some((stream_id(1, 2), stream_id(1, 3))));
+        assert_eq!(
+            summary.consumers,
+            vec![(b"alice".to_vec(), 1), (b"bob".to_vec(), 1)]
+        );
+        assert_eq!(
+            storage
+                .stream_group_delete_consumer(b"s", b"g", b"alice")
+                .unwrap(),
+            1
         );
+        let range = PendingRange {
+            min_idle: 0,
+            start: StreamId::MIN,
+            end: StreamId::MAX,
+            count: 10,
+            consumer: None,
+        };
+        let pending = storage.stream_pending_range(b"s", b"g", &range).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].consumer, b"bob");
+        assert!(storage.stream_pending_summary(b"s", b"missing").is_err());
+        assert!(storage.stream_group_destroy(b"s", b"g").unwrap());
+        assert!(!storage.stream_group_destroy(b"s", b"g").unwrap());
     }
 }
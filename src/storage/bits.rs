@@ -0,0 +1,284 @@
+/// Whether BITCOUNT and BITPOS ranges are given in bytes or bits.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum BitUnit {
+    #[default]
+    Byte,
+    Bit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitOp {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+/// The integer type of a BITFIELD operation, such as `i5` or `u16`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BitfieldType {
+    pub signed: bool,
+    pub bits: u32,
+}
+
+/// What BITFIELD does when SET or INCRBY goes past the range of the type.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Overflow {
+    #[default]
+    Wrap,
+    Sat,
+    Fail,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitfieldOp {
+    Get(BitfieldType, u64),
+    Set(BitfieldType, u64, i64),
+    IncrBy(BitfieldType, u64, i64),
+    Overflow(Overflow),
+}
+
+impl BitfieldOp {
+    /// Whether the operation may change the string.
+    pub fn writes(&self) -> bool {
+        matches!(self, BitfieldOp::Set(..) | BitfieldOp::IncrBy(..))
+    }
+}
+
+/// Reads the bit at `offset`, counting from the most significant bit of the
+/// first byte. Bits past the end of the string read as zero.
+pub fn get_bit(bytes: &[u8], offset: u64) -> bool {
+    let Some(byte) = bytes.get((offset / 8) as usize) else {
+        return false;
+    };
+    byte & (0x80 >> (offset % 8)) != 0
+}
+
+/// Sets the bit at `offset`, growing the string with zero bytes as needed.
+/// Returns the previous value of the bit.
+pub fn set_bit(bytes: &mut Vec<u8>, offset: u64, value: bool) -> bool {
+    let index = (offset / 8) as usize;
+    if bytes.len() <= index {
+        bytes.resize(index + 1, 0);
+    }
+    let mask = 0x80 >> (offset % 8);
+    let previous = bytes[index] & mask != 0;
+    if value {
+        bytes[index] |= mask;
+    } else {
+        bytes[index] &= !mask;
+    }
+    previous
+}
+
+/// Turns a BITCOUNT or BITPOS range into an inclusive range of bit offsets,
+/// with negative indexes counting from the end like GETRANGE does. Returns
+/// `None` when the range is empty.
+pub fn bit_range(bytes: &[u8], start: i64, end: i64, unit: BitUnit) -> Option<(u64, u64)> {
+    let len = match unit {
+        BitUnit::Byte => bytes.len() as i64,
+        BitUnit::Bit => bytes.len() as i64 * 8,
+    };
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let end = if end < 0 { len + end } else { end }.max(0).min(len - 1);
+    if len == 0 || start > end {
+        return None;
+    }
+    Some(match unit {
+        BitUnit::Byte => (start as u64 * 8, end as u64 * 8 + 7),
+        BitUnit::Bit => (start as u64, end as u64),
+    })
+}
+
+/// Counts the set bits between the bit offsets `start` and `end` inclusive.
+pub fn count_bits(bytes: &[u8], start: u64, end: u64) -> u64 {
+    let (first, last) = ((start / 8) as usize, (end / 8) as usize);
+    let mut count: u64 = bytes[first..=last]
+        .iter()
+        .map(|byte| byte.count_ones() as u64)
+        .sum();
+    // Take out the bits of the first and last byte that fall outside.
+    count -= (bytes[first] & !(0xff >> (start % 8))).count_ones() as u64;
+    count -= (bytes[last] & (0xffu16 >> (end % 8 + 1)) as u8).count_ones() as u64;
+    count
+}
+
+/// Finds the first bit equal to `bit` between the bit offsets `start` and
+/// `end` inclusive.
+pub fn find_bit(bytes: &[u8], bit: bool, start: u64, end: u64) -> Option<u64> {
+    // Whole bytes without the wanted bit are skipped at once.
+    let skip = if bit { 0x00 } else { 0xff };
+    let mut offset = start;
+    while offset <= end {
+        if offset.is_multiple_of(8) && offset + 7 <= end && bytes[(offset / 8) as usize] == skip {
+            offset += 8;
+            continue;
+        }
+        if get_bit(bytes, offset) == bit {
+            return Some(offset);
+        }
+        offset += 1;
+    }
+    None
+}
+
+/// Combines `sources` byte by byte, treating missing bytes of the shorter
+/// ones as zero. The result is as long as the longest source.
+pub fn bit_op(op: BitOp, sources: &[&[u8]]) -> Vec<u8> {
+    let len = sources.iter().map(|source| source.len()).max().unwrap_or(0);
+    (0..len)
+        .map(|index| {
+            let mut bytes = sources
+                .iter()
+                .map(|source| source.get(index).copied().unwrap_or(0));
+            let first = bytes.next().unwrap_or(0);
+            match op {
+                BitOp::And => bytes.fold(first, |acc, byte| acc & byte),
+                BitOp::Or => bytes.fold(first, |acc, byte| acc | byte),
+                BitOp::Xor => bytes.fold(first, |acc, byte| acc ^ byte),
+                BitOp::Not => !first,
+            }
+        })
+        .collect()
+}
+
+/// Reads the integer of type `field` stored at bit `offset`.
+pub fn get_field(bytes: &[u8], offset: u64, field: BitfieldType) -> i64 {
+    let mut value: u64 = 0;
+    for i in 0..field.bits as u64 {
+        value = value << 1 | get_bit(bytes, offset + i) as u64;
+    }
+    if field.signed && field.bits < 64 && value >> (field.bits - 1) & 1 == 1 {
+        // Sign extend.
+        value |= u64::MAX << field.bits;
+    }
+    value as i64
+}
+
+/// Writes the low bits of `value` as an integer of type `field` at bit
+/// `offset`.
+pub fn set_field(bytes: &mut Vec<u8>, offset: u64, field: BitfieldType, value: i64) {
+    let value = value as u64;
+    for i in 0..field.bits as u64 {
+        let bit = value >> (field.bits as u64 - 1 - i) & 1 == 1;
+        set_bit(bytes, offset + i, bit);
+    }
+}
+
+/// Fits `value` into the range of `field` according to `overflow`, or
+/// returns `None` if it does not fit and `overflow` is FAIL.
+pub fn fit_field(value: i128, field: BitfieldType, overflow: Overflow) -> Option<i64> {
+    let (min, max) = if field.signed {
+        (
+            -(1i128 << (field.bits - 1)),
+            (1i128 << (field.bits - 1)) - 1,
+        )
+    } else {
+        (0, (1i128 << field.bits) - 1)
+    };
+    if (min..=max).contains(&value) {
+        return Some(value as i64);
+    }
+    match overflow {
+        Overflow::Fail => None,
+        Overflow::Sat => Some(value.clamp(min, max) as i64),
+        Overflow::Wrap => {
+            let wrapped = value.rem_euclid(1i128 << field.bits);
+            Some(if wrapped > max {
+                (wrapped - (1i128 << field.bits)) as i64
+            } else {
+                wrapped as i64
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const U8: BitfieldType = BitfieldType {
+        signed: false,
+        bits: 8,
+    };
+    const I5: BitfieldType = BitfieldType {
+        signed: true,
+        bits: 5,
+    };
+
+    #[test]
+    fn test_set_and_get_bits() {
+        let mut bytes = Vec::new();
+        assert!(!set_bit(&mut bytes, 7, true));
+        assert_eq!(bytes, vec![0x01]);
+        assert!(!set_bit(&mut bytes, 17, true));
+        assert_eq!(bytes, vec![0x01, 0x00, 0x40]);
+        assert!(set_bit(&mut bytes, 7, false));
+        assert!(get_bit(&bytes, 17));
+        assert!(!get_bit(&bytes, 1000));
+    }
+
+    #[test]
+    fn test_count_and_find() {
+        let bytes = b"foobar";
+        assert_eq!(count_bits(bytes, 0, 47), 26);
+        let (start, end) = bit_range(bytes, 1, 1, BitUnit::Byte).unwrap();
+        assert_eq!(count_bits(bytes, start, end), 6);
+        let (start, end) = bit_range(bytes, 5, 30, BitUnit::Bit).unwrap();
+        assert_eq!(count_bits(bytes, start, end), 17);
+        assert_eq!(bit_range(bytes, 4, 2, BitUnit::Byte), None);
+        let bytes = [0xff, 0xf0, 0x00];
+        assert_eq!(find_bit(&bytes, false, 0, 23), Some(12));
+        assert_eq!(find_bit(&bytes, true, 16, 23), None);
+        assert_eq!(find_bit(&[0x00, 0x00, 0x01], true, 0, 23), Some(23));
+    }
+
+    #[test]
+    fn test_bit_op() {
+        assert_eq!(
+            bit_op(BitOp::And, &[b"\xff\x0f", b"\x0f"]),
+            vec![0x0f, 0x00]
+        );
+        assert_eq!(bit_op(BitOp::Or, &[b"\xf0", b"\x0f\x01"]), vec![0xff, 0x01]);
+        assert_eq!(bit_op(BitOp::Xor, &[b"\xff", b"\x0f"]), vec![0xf0]);
+        assert_eq!(bit_op(BitOp::Not, &[b"\x0f"]), vec![0xf0]);
+    }
+
+    #[test]
+    fn test_fields() {
+        let mut bytes = Vec::new();
+        set_field(&mut bytes, 3, I5, -3);
+        assert_eq!(get_field(&bytes, 3, I5), -3);
+        assert_eq!(
+            get_field(
+                &bytes,
+                3,
+                BitfieldType {
+                    signed: false,
+                    bits: 5
+                }
+            ),
+            29
+        );
+        set_field(&mut bytes, 8, U8, 200);
+        assert_eq!(get_field(&bytes, 8, U8), 200);
+        let i64 = BitfieldType {
+            signed: true,
+            bits: 64,
+        };
+        set_field(&mut bytes, 1, i64, i64::MIN + 5);
+        assert_eq!(get_field(&bytes, 1, i64), i64::MIN + 5);
+    }
+
+    #[test]
+    fn test_overflow() {
+        assert_eq!(fit_field(300, U8, Overflow::Wrap), Some(44));
+        assert_eq!(fit_field(-1, U8, Overflow::Wrap), Some(255));
+        assert_eq!(fit_field(300, U8, Overflow::Sat), Some(255));
+        assert_eq!(fit_field(300, U8, Overflow::Fail), None);
+        assert_eq!(fit_field(16, I5, Overflow::Wrap), Some(-16));
+        assert_eq!(fit_field(-17, I5, Overflow::Wrap), Some(15));
+        assert_eq!(fit_field(-17, I5, Overflow::Sat), Some(-16));
+        assert_eq!(fit_field(15, I5, Overflow::Fail), Some(15));
+    }
+}
@@ -0,0 +1,465 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::time::{Duration, Instant};
+
+use super::rand::Rng;
+
+const INITIAL_SIZE: usize = 4;
+// How many buckets are moved to the new table for every operation while a
+// rehash is in progress, and how many empty buckets a step may skip per bucket
+// it is supposed to move, so that a sparse table cannot make one step slow.
+const REHASH_BUCKETS_PER_OPERATION: usize = 1;
+const REHASH_EMPTY_VISITS: usize = 10;
+// The table shrinks once fewer than one in this many buckets is used.
+const MIN_FILL: usize = 8;
+
+#[derive(Clone)]
+struct Entry<K, V> {
+    hash: u64,
+    key: K,
+    value: V,
+}
+
+#[derive(Clone)]
+struct Table<K, V> {
+    buckets: Vec<Vec<Entry<K, V>>>,
+    used: usize,
+}
+
+impl<K, V> Table<K, V> {
+    fn with_size(size: usize) -> Self {
+        let mut buckets = Vec::with_capacity(size);
+        buckets.resize_with(size, Vec::new);
+        Self { buckets, used: 0 }
+    }
+
+    fn size(&self) -> usize {
+        self.buckets.len()
+    }
+
+    fn mask(&self) -> u64 {
+        (self.buckets.len() as u64).wrapping_sub(1)
+    }
+
+    fn bucket(&self, hash: u64) -> usize {
+        (hash & self.mask()) as usize
+    }
+}
+
+/// A chained hash table that grows and shrinks the way redis' dict does: a
+/// resize allocates a second table and entries move over a few buckets at a
+/// time on every following operation, instead of all at once. Lookups check
+/// both tables while a rehash is in progress.
+#[derive(Clone)]
+pub struct Dict<K, V> {
+    tables: [Table<K, V>; 2],
+    // The next bucket of `tables[0]` to move into `tables[1]`, or `None` when
+    // no rehash is in progress and `tables[1]` is unallocated.
+    rehash_index: Option<usize>,
+    hasher: RandomState,
+}
+
+impl<K: Hash + Eq, V> Dict<K, V> {
+    pub fn new() -> Self {
+        Self {
+            tables: [Table::with_size(0), Table::with_size(0)],
+            rehash_index: None,
+            hasher: RandomState::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables[0].used + self.tables[1].used
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn is_rehashing(&self) -> bool {
+        self.rehash_index.is_some()
+    }
+
+    /// The number of tables that may hold entries.
+    fn live_tables(&self) -> usize {
+        if self.is_rehashing() {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Finds the table, bucket and position within the bucket of `key`.
+    fn find<Q>(&self, key: &Q) -> Option<(usize, usize, usize)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.is_empty() {
+            return None;
+        }
+        let hash = self.hasher.hash_one(key);
+        (0..self.live_tables()).find_map(|t| {
+            let table = &self.tables[t];
+            let bucket = table.bucket(hash);
+            table.buckets[bucket]
+                .iter()
+                .position(|entry| entry.hash == hash && entry.key.borrow() == key)
+                .map(|position| (t, bucket, position))
+        })
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (t, bucket, position) = self.find(key)?;
+        Some(&self.tables[t].buckets[bucket][position].value)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.rehash(REHASH_BUCKETS_PER_OPERATION);
+        let (t, bucket, position) = self.find(key)?;
+        Some(&mut self.tables[t].buckets[bucket][position].value)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).is_some()
+    }
+
+    /// Stores `value` under `key`, returning the value it replaced.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.rehash(REHASH_BUCKETS_PER_OPERATION);
+        if let Some((t, bucket, position)) = self.find(&key) {
+            let entry = &mut self.tables[t].buckets[bucket][position];
+            return Some(std::mem::replace(&mut entry.value, value));
+        }
+        if !self.is_rehashing() && self.tables[0].used >= self.tables[0].size() {
+            self.resize(self.tables[0].used + 1);
+        }
+        // New entries go straight to the new table during a rehash, so that
+        // the old one only ever empties.
+        let table = &mut self.tables[self.live_tables() - 1];
+        let hash = self.hasher.hash_one(&key);
+        let bucket = table.bucket(hash);
+        table.buckets[bucket].push(Entry { hash, key, value });
+        table.used += 1;
+        None
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.rehash(REHASH_BUCKETS_PER_OPERATION);
+        let (t, bucket, position) = self.find(key)?;
+        let table = &mut self.tables[t];
+        let entry = table.buckets[bucket].swap_remove(position);
+        table.used -= 1;
+        self.shrink_if_needed();
+        Some(entry.value)
+    }
+
+    pub fn clear(&mut self) {
+        self.tables = [Table::with_size(0), Table::with_size(0)];
+        self.rehash_index = None;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.tables[..self.live_tables()]
+            .iter()
+            .flat_map(|table| table.buckets.iter().flatten())
+            .map(|entry| (&entry.key, &entry.value))
+    }
+
+    /// Returns an entry picked at random, by choosing random buckets until a
+    /// non-empty one turns up, then a random entry within it.
+    pub fn random(&self, rng: &mut Rng) -> Option<(&K, &V)> {
+        if self.is_empty() {
+            return None;
+        }
+        let bucket = loop {
+            let bucket = match self.rehash_index {
+                // Buckets of the old table below the rehash index are empty,
+                // so only the rest of it and the new table are drawn from.
+                Some(index) => {
+                    let old = self.tables[0].size();
+                    let at = index + rng.below(old - index + self.tables[1].size());
+                    if at < old {
+                        &self.tables[0].buckets[at]
+                    } else {
+                        &self.tables[1].buckets[at - old]
+                    }
+                }
+                None => &self.tables[0].buckets[rng.below(self.tables[0].size())],
+            };
+            if !bucket.is_empty() {
+                break bucket;
+            }
+        };
+        let entry = &bucket[rng.below(bucket.len())];
+        Some((&entry.key, &entry.value))
+    }
+
+    /// Visits the entries of the bucket at `cursor` and returns the cursor of
+    /// the next one, zero once every bucket has been visited.
+    ///
+    /// Like redis' `dictScan`, the cursor counts up with its bits reversed.
+    /// A table growing to twice the size splits bucket `i` into `i` and
+    /// `i + size`, which in reversed order are neighbours, so a cursor taken
+    /// before the resize still skips nothing after it. Every entry present
+    /// for the whole scan is visited at least once, although some may be
+    /// visited more than once if the table shrinks.
+    pub fn scan<F: FnMut(&K, &V)>(&self, cursor: u64, mut visit: F) -> u64 {
+        if self.is_empty() {
+            return 0;
+        }
+        let mut cursor = cursor;
+        if !self.is_rehashing() {
+            let table = &self.tables[0];
+            visit_bucket(table, cursor, &mut visit);
+            return next_cursor(cursor, table.mask());
+        }
+        // Visit the bucket of the smaller table, then every bucket of the
+        // larger one it expands to.
+        let (small, large) = if self.tables[0].size() <= self.tables[1].size() {
+            (&self.tables[0], &self.tables[1])
+        } else {
+            (&self.tables[1], &self.tables[0])
+        };
+        visit_bucket(small, cursor, &mut visit);
+        loop {
+            visit_bucket(large, cursor, &mut visit);
+            cursor = next_cursor(cursor, large.mask());
+            if cursor & (small.mask() ^ large.mask()) == 0 {
+                return cursor;
+            }
+        }
+    }
+
+    /// Moves entries to the new table for up to `budget`, so that an idle
+    /// server finishes a rehash without waiting for further operations. Also
+    /// starts a shrink that could not start while a rehash was in progress.
+    pub fn rehash_for(&mut self, budget: Duration) {
+        let start = Instant::now();
+        self.shrink_if_needed();
+        while self.rehash(100) && start.elapsed() < budget {}
+    }
+
+    fn shrink_if_needed(&mut self) {
+        let size = self.tables[0].size();
+        if size > INITIAL_SIZE && self.tables[0].used * MIN_FILL < size {
+            self.resize(self.tables[0].used);
+        }
+    }
+
+    /// Starts a rehash into a table sized for `used` entries. Allocating the
+    /// first table needs no rehash.
+    fn resize(&mut self, used: usize) {
+        let size = used.max(INITIAL_SIZE).next_power_of_two();
+        if self.is_rehashing() || size == self.tables[0].size() {
+            return;
+        }
+        if self.tables[0].size() == 0 {
+            self.tables[0] = Table::with_size(size);
+            return;
+        }
+        self.tables[1] = Table::with_size(size);
+        self.rehash_index = Some(0);
+    }
+
+    /// Moves up to `buckets` non-empty buckets of the old table to the new
+    /// one, finishing the rehash once the old table is empty. Returns whether
+    /// there is still work left.
+    fn rehash(&mut self, buckets: usize) -> bool {
+        let Some(mut index) = self.rehash_index else {
+            return false;
+        };
+        let mut empty_visits = buckets * REHASH_EMPTY_VISITS;
+        let [old, new] = &mut self.tables;
+        for _ in 0..buckets {
+            if old.used == 0 {
+                break;
+            }
+            while old.buckets[index].is_empty() {
+                index += 1;
+                empty_visits -= 1;
+                if empty_visits == 0 {
+                    self.rehash_index = Some(index);
+                    return true;
+                }
+            }
+            for entry in std::mem::take(&mut old.buckets[index]) {
+                let bucket = new.bucket(entry.hash);
+                new.buckets[bucket].push(entry);
+                old.used -= 1;
+                new.used += 1;
+            }
+            index += 1;
+        }
+        if old.used == 0 {
+            self.tables[0] = std::mem::replace(&mut self.tables[1], Table::with_size(0));
+            self.rehash_index = None;
+            return false;
+        }
+        self.rehash_index = Some(index);
+        true
+    }
+}
+
+impl<K: Hash + Eq, V> Default for Dict<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn visit_bucket<K, V, F: FnMut(&K, &V)>(table: &Table<K, V>, cursor: u64, visit: &mut F) {
+    for entry in &table.buckets[(cursor & table.mask()) as usize] {
+        visit(&entry.key, &entry.value);
+    }
+}
+
+/// Increments the bits of `cursor` covered by `mask` in reverse order.
+fn next_cursor(cursor: u64, mask: u64) -> u64 {
+    (cursor | !mask)
+        .reverse_bits()
+        .wrapping_add(1)
+        .reverse_bits()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(i: usize) -> Vec<u8> {
+        format!("key:{}", i).into_bytes()
+    }
+
+    #[test]
+    fn test_insert_get_remove_across_rehashes() {
+        let mut dict = Dict::new();
+        for i in 0..1000 {
+            assert_eq!(dict.insert(key(i), i), None);
+        }
+        assert_eq!(dict.insert(key(7), 70), Some(7));
+        assert_eq!(dict.len(), 1000);
+        for i in 0..1000 {
+            let expected = if i == 7 { 70 } else { i };
+            assert_eq!(dict.get(key(i).as_slice()), Some(&expected));
+        }
+        for i in 0..990 {
+            assert!(dict.remove(key(i).as_slice()).is_some());
+        }
+        assert_eq!(dict.remove(key(0).as_slice()), None);
+        assert_eq!(dict.len(), 10);
+        // Removals cannot start a shrink while another resize is under way,
+        // the first call finishes that one and the second catches up.
+        dict.rehash_for(Duration::from_secs(1));
+        dict.rehash_for(Duration::from_secs(1));
+        assert_eq!(dict.tables[0].size(), 16);
+        for i in 990..1000 {
+            assert_eq!(dict.get(key(i).as_slice()), Some(&i));
+        }
+    }
+
+    #[test]
+    fn test_rehash_is_incremental() {
+        let mut dict = Dict::new();
+        for i in 0..64 {
+            dict.insert(key(i), i);
+        }
+        while dict.rehash(100) {}
+        // The 65th entry starts a rehash, which then advances one bucket per
+        // operation.
+        dict.insert(key(64), 64);
+        assert!(dict.is_rehashing());
+        assert!(dict.tables[0].used > 0);
+        assert_eq!(dict.iter().count(), 65);
+        while dict.is_rehashing() {
+            dict.get_mut(key(0).as_slice());
+        }
+        assert_eq!(dict.tables[0].size(), 128);
+        assert_eq!(dict.tables[0].used, 65);
+    }
+
+    #[test]
+    fn test_scan_survives_resizes() {
+        let mut dict = Dict::new();
+        for i in 0..500 {
+            dict.insert(key(i), i);
+        }
+        let mut seen = std::collections::HashSet::new();
+        let mut cursor = 0;
+        let mut added = 500;
+        loop {
+            cursor = dict.scan(cursor, |_, &value| {
+                seen.insert(value);
+            });
+            // Grow the table past several resizes while the scan runs.
+            if added < 4000 {
+                for _ in 0..20 {
+                    dict.insert(key(added), added);
+                    added += 1;
+                }
+            }
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert!((0..500).all(|i| seen.contains(&i)));
+    }
+
+    #[test]
+    fn test_scan_while_shrinking() {
+        let mut dict = Dict::new();
+        for i in 0..1000 {
+            dict.insert(key(i), i);
+        }
+        let mut seen = std::collections::HashSet::new();
+        let mut cursor = 0;
+        let mut removed = 100;
+        loop {
+            cursor = dict.scan(cursor, |_, &value| {
+                seen.insert(value);
+            });
+            for _ in 0..20 {
+                if removed < 1000 {
+                    dict.remove(key(removed).as_slice());
+                    removed += 1;
+                }
+            }
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert!((0..100).all(|i| seen.contains(&i)));
+    }
+
+    #[test]
+    fn test_random() {
+        let mut rng = Rng::with_seed(42);
+        let mut dict = Dict::new();
+        assert!(dict.random(&mut rng).is_none());
+        for i in 0..100 {
+            dict.insert(key(i), i);
+        }
+        for i in 0..95 {
+            dict.remove(key(i).as_slice());
+        }
+        for _ in 0..50 {
+            let (_, &value) = dict.random(&mut rng).unwrap();
+            assert!(value >= 95);
+        }
+    }
+}
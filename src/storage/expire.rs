@@ -0,0 +1,38 @@
+use super::dict::Dict;
+use super::rand::Rng;
+
+/// The set of keys that carry a TTL, which the active expiry cycle samples
+/// from. It lives in a dict of its own so that it resizes incrementally along
+/// with the keyspace.
+#[derive(Default)]
+pub struct VolatileKeys {
+    keys: Dict<Vec<u8>, ()>,
+}
+
+impl VolatileKeys {
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn random(&self, rng: &mut Rng) -> Option<&[u8]> {
+        self.keys.random(rng).map(|(key, _)| key.as_slice())
+    }
+
+    pub fn insert(&mut self, key: &[u8]) {
+        if !self.keys.contains_key(key) {
+            self.keys.insert(key.to_vec(), ());
+        }
+    }
+
+    pub fn remove(&mut self, key: &[u8]) {
+        self.keys.remove(key);
+    }
+
+    pub fn clear(&mut self) {
+        self.keys.clear();
+    }
+
+    pub fn rehash_for(&mut self, budget: std::time::Duration) {
+        self.keys.rehash_for(budget);
+    }
+}
@@ -0,0 +1,126 @@
+/// Matches `string` against a glob-style `pattern` with the same rules as
+/// redis: `*` matches any run of bytes, `?` any single byte, `[...]` a set of
+/// bytes or ranges, negated by a leading `^`, and `\` escapes the next byte.
+pub fn matches(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // Where to resume after the last `*` when a later part of the pattern
+    // fails: the position just past the star and the byte it should swallow
+    // next. Only the last star matters, earlier ones never need to take back
+    // bytes they already matched.
+    let mut backtrack = None;
+    while s < string.len() {
+        if pattern.get(p) == Some(&b'*') {
+            while pattern.get(p) == Some(&b'*') {
+                p += 1;
+            }
+            if p == pattern.len() {
+                return true;
+            }
+            backtrack = Some((p, s));
+            continue;
+        }
+        if let Some(next) = match_one(pattern, p, string[s]) {
+            p = next;
+            s += 1;
+            continue;
+        }
+        match backtrack {
+            Some((star_p, star_s)) => {
+                p = star_p;
+                s = star_s + 1;
+                backtrack = Some((star_p, s));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|&b| b == b'*')
+}
+
+/// Matches the single byte `c` against the pattern element at `p`, which
+/// must not be a `*`. Returns the position of the next element on a match.
+fn match_one(pattern: &[u8], p: usize, c: u8) -> Option<usize> {
+    match *pattern.get(p)? {
+        b'?' => Some(p + 1),
+        b'[' => match_class(pattern, p + 1, c),
+        b'\\' if p + 1 < pattern.len() => (pattern[p + 1] == c).then_some(p + 2),
+        literal => (literal == c).then_some(p + 1),
+    }
+}
+
+/// Matches `c` against the class whose body starts at `p`, just past the
+/// opening `[`. An unterminated class runs to the end of the pattern.
+fn match_class(pattern: &[u8], mut p: usize, c: u8) -> Option<usize> {
+    let negated = pattern.get(p) == Some(&b'^');
+    if negated {
+        p += 1;
+    }
+    let mut matched = false;
+    while p < pattern.len() {
+        match pattern[p] {
+            b'\\' if p + 1 < pattern.len() => {
+                matched |= pattern[p + 1] == c;
+                p += 2;
+            }
+            b']' => {
+                p += 1;
+                break;
+            }
+            start if p + 2 < pattern.len() && pattern[p + 1] == b'-' => {
+                let end = pattern[p + 2];
+                let (low, high) = if start <= end {
+                    (start, end)
+                } else {
+                    (end, start)
+                };
+                matched |= (low..=high).contains(&c);
+                p += 3;
+            }
+            literal => {
+                matched |= literal == c;
+                p += 1;
+            }
+        }
+    }
+    (matched != negated).then_some(p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcards() {
+        assert!(matches(b"*", b""));
+        assert!(matches(b"*", b"anything"));
+        assert!(matches(b"user:*", b"user:42"));
+        assert!(!matches(b"user:*", b"session:42"));
+        assert!(matches(b"h?llo", b"hello"));
+        assert!(!matches(b"h?llo", b"hllo"));
+        assert!(matches(b"*a*b*c", b"xxaxxbxxbxxc"));
+        assert!(!matches(b"*a*b*c", b"xxaxxbxxcxxd"));
+        assert!(matches(b"a**b", b"ab"));
+        assert!(!matches(b"abc", b"ab"));
+        assert!(!matches(b"ab", b"abc"));
+    }
+
+    #[test]
+    fn test_classes() {
+        assert!(matches(b"h[ae]llo", b"hallo"));
+        assert!(!matches(b"h[ae]llo", b"hillo"));
+        assert!(matches(b"h[^e]llo", b"hallo"));
+        assert!(!matches(b"h[^e]llo", b"hello"));
+        assert!(matches(b"h[a-b]llo", b"hbllo"));
+        assert!(matches(b"h[b-a]llo", b"hallo"));
+        assert!(!matches(b"h[a-b]llo", b"hcllo"));
+        assert!(matches(b"[\\]]", b"]"));
+        assert!(matches(b"x[abc", b"xb"));
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert!(matches(b"a\\*b", b"a*b"));
+        assert!(!matches(b"a\\*b", b"axb"));
+        assert!(matches(b"a\\?", b"a?"));
+        assert!(matches(b"a\\", b"a\\"));
+    }
+}
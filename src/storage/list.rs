@@ -0,0 +1,326 @@
+use std::collections::VecDeque;
+
+// Like redis' quicklist, elements are packed into nodes of up to 8KB, with a
+// cap on the number of elements so that inserting into a node of many tiny
+// elements stays cheap. An element larger than a node gets a node of its own.
+const NODE_MAX_BYTES: usize = 8 * 1024;
+const NODE_MAX_ELEMENTS: usize = 128;
+
+/// The end of a list to push to or pop from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListEnd {
+    Left,
+    Right,
+}
+
+/// A run of elements stored back to back in a single buffer, with the offset
+/// at which each one ends. This costs four bytes per element, instead of a
+/// separate allocation for each.
+#[derive(Clone, Default)]
+struct Node {
+    data: Vec<u8>,
+    ends: Vec<u32>,
+}
+
+impl Node {
+    fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    fn start(&self, index: usize) -> usize {
+        match index {
+            0 => 0,
+            _ => self.ends[index - 1] as usize,
+        }
+    }
+
+    fn get(&self, index: usize) -> &[u8] {
+        &self.data[self.start(index)..self.ends[index] as usize]
+    }
+
+    fn has_room_for(&self, element: &[u8]) -> bool {
+        self.ends.is_empty()
+            || self.len() < NODE_MAX_ELEMENTS && self.data.len() + element.len() <= NODE_MAX_BYTES
+    }
+
+    fn insert(&mut self, index: usize, element: &[u8]) {
+        let start = self.start(index);
+        self.data.splice(start..start, element.iter().copied());
+        for end in &mut self.ends[index..] {
+            *end += element.len() as u32;
+        }
+        self.ends.insert(index, (start + element.len()) as u32);
+    }
+
+    fn remove(&mut self, index: usize) -> Vec<u8> {
+        let (start, end) = (self.start(index), self.ends[index] as usize);
+        let element: Vec<u8> = self.data.drain(start..end).collect();
+        self.ends.remove(index);
+        for end in &mut self.ends[index..] {
+            *end -= element.len() as u32;
+        }
+        element
+    }
+
+    /// Splits off the elements from `index` on into a new node.
+    fn split_off(&mut self, index: usize) -> Node {
+        let start = self.start(index);
+        let data = self.data.split_off(start);
+        let ends = self
+            .ends
+            .split_off(index)
+            .into_iter()
+            .map(|end| end - start as u32)
+            .collect();
+        Node { data, ends }
+    }
+}
+
+/// A list stored as a deque of packed nodes, so that pushing and popping at
+/// either end is cheap and elements take little more memory than their bytes.
+#[derive(Clone, Default)]
+pub struct List {
+    nodes: VecDeque<Node>,
+    len: usize,
+}
+
+impl List {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, end: ListEnd, element: &[u8]) {
+        match end {
+            ListEnd::Left => self.insert(0, element),
+            ListEnd::Right => self.insert(self.len, element),
+        }
+    }
+
+    pub fn pop(&mut self, end: ListEnd) -> Option<Vec<u8>> {
+        match end {
+            _ if self.is_empty() => None,
+            ListEnd::Left => Some(self.remove(0)),
+            ListEnd::Right => Some(self.remove(self.len - 1)),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        if index >= self.len {
+            return None;
+        }
+        let (node, offset) = self.locate(index);
+        Some(self.nodes[node].get(offset))
+    }
+
+    /// Replaces the element at `index`, which must be in range.
+    pub fn set(&mut self, index: usize, element: &[u8]) {
+        let (node, offset) = self.locate(index);
+        let node = &mut self.nodes[node];
+        node.remove(offset);
+        node.insert(offset, element);
+    }
+
+    /// Inserts `element` so that it ends up at `index`, which may be one past
+    /// the last element.
+    pub fn insert(&mut self, index: usize, element: &[u8]) {
+        let (mut node, mut offset) = match index {
+            _ if self.nodes.is_empty() => {
+                self.nodes.push_back(Node::default());
+                (0, 0)
+            }
+            // Appending goes to the end of the last node rather than the
+            // start of a non-existent next one.
+            _ if index == self.len => (self.nodes.len() - 1, self.nodes.back().unwrap().len()),
+            _ => self.locate(index),
+        };
+        if !self.nodes[node].has_room_for(element) {
+            if offset == 0 {
+                self.nodes.insert(node, Node::default());
+            } else if offset == self.nodes[node].len() {
+                node += 1;
+                offset = 0;
+                self.nodes.insert(node, Node::default());
+            } else {
+                let tail = self.nodes[node].split_off(offset);
+                self.nodes.insert(node + 1, tail);
+            }
+        }
+        self.nodes[node].insert(offset, element);
+        self.len += 1;
+    }
+
+    /// Removes the element at `index`, which must be in range.
+    pub fn remove(&mut self, index: usize) -> Vec<u8> {
+        let (node, offset) = self.locate(index);
+        let element = self.nodes[node].remove(offset);
+        if self.nodes[node].len() == 0 {
+            self.nodes.remove(node);
+        }
+        self.len -= 1;
+        element
+    }
+
+    /// Keeps only the elements from `start` to `end` inclusive, dropping
+    /// whole nodes where possible.
+    pub fn retain_range(&mut self, start: usize, end: usize) {
+        if start > end || start >= self.len {
+            *self = List::default();
+            return;
+        }
+        let mut from_back = self.len - 1 - end.min(self.len - 1);
+        while from_back > 0 {
+            let last = self.nodes.back_mut().unwrap();
+            if last.len() <= from_back {
+                from_back -= last.len();
+                self.len -= last.len();
+                self.nodes.pop_back();
+            } else {
+                self.remove(self.len - 1);
+                from_back -= 1;
+            }
+        }
+        let mut from_front = start;
+        while from_front > 0 {
+            let first = self.nodes.front().unwrap();
+            if first.len() <= from_front {
+                from_front -= first.len();
+                self.len -= first.len();
+                self.nodes.pop_front();
+            } else {
+                self.remove(0);
+                from_front -= 1;
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &[u8]> {
+        self.nodes
+            .iter()
+            .flat_map(|node| (0..node.len()).map(move |index| node.get(index)))
+    }
+
+    /// Finds the node holding `index` and its offset within it, walking from
+    /// whichever end of the list is closer.
+    fn locate(&self, index: usize) -> (usize, usize) {
+        if index < self.len / 2 {
+            let mut offset = index;
+            for (node, n) in self.nodes.iter().enumerate() {
+                if offset < n.len() {
+                    return (node, offset);
+                }
+                offset -= n.len();
+            }
+        } else {
+            let mut from_end = self.len - 1 - index;
+            for (node, n) in self.nodes.iter().enumerate().rev() {
+                if from_end < n.len() {
+                    return (node, n.len() - 1 - from_end);
+                }
+                from_end -= n.len();
+            }
+        }
+        unreachable!(
+            "index {} out of range for list of length {}",
+            index, self.len
+        )
+    }
+}
+
+/// Turns the redis-style inclusive range `start..=end`, where negative
+/// indexes count from the end, into in-range indexes. Returns `None` when the
+/// range is empty.
+pub fn normalize_range(start: i64, end: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let end = if end < 0 { len + end } else { end }.min(len - 1);
+    if start > end || start >= len {
+        return None;
+    }
+    Some((start as usize, end as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(list: &List) -> Vec<Vec<u8>> {
+        list.iter().map(|element| element.to_vec()).collect()
+    }
+
+    #[test]
+    fn test_push_pop_across_nodes() {
+        let mut list = List::default();
+        for i in 0..1000 {
+            list.push(ListEnd::Right, i.to_string().as_bytes());
+            list.push(ListEnd::Left, (-i).to_string().as_bytes());
+        }
+        assert_eq!(list.len(), 2000);
+        assert!(list.nodes.len() > 10);
+        assert_eq!(list.get(0), Some(&b"-999"[..]));
+        assert_eq!(list.get(1999), Some(&b"999"[..]));
+        assert_eq!(list.get(1000), Some(&b"0"[..]));
+        assert_eq!(list.iter().next_back(), Some(&b"999"[..]));
+        for i in (0..1000).rev() {
+            assert_eq!(list.pop(ListEnd::Right), Some(i.to_string().into_bytes()));
+        }
+        assert_eq!(list.pop(ListEnd::Left), Some(b"-999".to_vec()));
+        assert_eq!(list.len(), 999);
+    }
+
+    #[test]
+    fn test_insert_splits_full_nodes() {
+        let mut list = List::default();
+        for i in 0..NODE_MAX_ELEMENTS {
+            list.push(ListEnd::Right, i.to_string().as_bytes());
+        }
+        assert_eq!(list.nodes.len(), 1);
+        list.insert(10, b"x");
+        assert_eq!(list.nodes.len(), 2);
+        assert_eq!(list.get(9), Some(&b"9"[..]));
+        assert_eq!(list.get(10), Some(&b"x"[..]));
+        assert_eq!(list.get(11), Some(&b"10"[..]));
+        list.set(11, b"longer");
+        assert_eq!(list.remove(11), b"longer");
+        assert_eq!(list.get(11), Some(&b"11"[..]));
+    }
+
+    #[test]
+    fn test_large_elements_get_their_own_node() {
+        let mut list = List::default();
+        let large = vec![b'x'; NODE_MAX_BYTES * 2];
+        list.push(ListEnd::Right, b"a");
+        list.push(ListEnd::Right, &large);
+        list.push(ListEnd::Right, b"b");
+        assert_eq!(list.nodes.len(), 3);
+        assert_eq!(list.get(1), Some(&large[..]));
+    }
+
+    #[test]
+    fn test_retain_range() {
+        let mut list = List::default();
+        for i in 0..500 {
+            list.push(ListEnd::Right, i.to_string().as_bytes());
+        }
+        list.retain_range(130, 300);
+        assert_eq!(list.len(), 171);
+        assert_eq!(list.get(0), Some(&b"130"[..]));
+        assert_eq!(list.get(170), Some(&b"300"[..]));
+        assert_eq!(collect(&list).len(), 171);
+        list.retain_range(5, 2);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_normalize_range() {
+        assert_eq!(normalize_range(0, -1, 5), Some((0, 4)));
+        assert_eq!(normalize_range(-100, 100, 5), Some((0, 4)));
+        assert_eq!(normalize_range(-2, -1, 5), Some((3, 4)));
+        assert_eq!(normalize_range(3, 1, 5), None);
+        assert_eq!(normalize_range(5, 10, 5), None);
+        assert_eq!(normalize_range(0, -1, 0), None);
+    }
+}
@@ -0,0 +1,280 @@
+use super::dict::Dict;
+use super::rand::Rng;
+use super::scan_dict;
+use crate::resp::parse_integer;
+
+// Like redis' set-max-intset-entries: sets of only integers are kept in the
+// compact encoding up to this many members.
+const MAX_INTSET_ENTRIES: usize = 512;
+
+/// A sorted array of integers, all stored with the width the largest of them
+/// needs, so that small numbers take two bytes each.
+#[derive(Clone)]
+struct IntSet {
+    width: usize,
+    contents: Vec<u8>,
+}
+
+impl Default for IntSet {
+    fn default() -> Self {
+        Self {
+            width: 2,
+            contents: Vec::new(),
+        }
+    }
+}
+
+impl IntSet {
+    fn width_for(value: i64) -> usize {
+        if i16::try_from(value).is_ok() {
+            2
+        } else if i32::try_from(value).is_ok() {
+            4
+        } else {
+            8
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.contents.len() / self.width
+    }
+
+    fn get(&self, index: usize) -> i64 {
+        let bytes = &self.contents[index * self.width..(index + 1) * self.width];
+        match self.width {
+            2 => i16::from_le_bytes(bytes.try_into().unwrap()) as i64,
+            4 => i32::from_le_bytes(bytes.try_into().unwrap()) as i64,
+            _ => i64::from_le_bytes(bytes.try_into().unwrap()),
+        }
+    }
+
+    /// Binary searches for `value`, returning where it is or where it would
+    /// have to be inserted.
+    fn search(&self, value: i64) -> Result<usize, usize> {
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let middle = (low + high) / 2;
+            match self.get(middle).cmp(&value) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return Ok(middle),
+            }
+        }
+        Err(low)
+    }
+
+    fn contains(&self, value: i64) -> bool {
+        Self::width_for(value) <= self.width && self.search(value).is_ok()
+    }
+
+    fn insert(&mut self, value: i64) -> bool {
+        let width = Self::width_for(value);
+        if width > self.width {
+            let values: Vec<i64> = self.iter().collect();
+            self.width = width;
+            self.contents = values.into_iter().flat_map(|v| self.encode(v)).collect();
+        }
+        match self.search(value) {
+            Ok(_) => false,
+            Err(index) => {
+                let at = index * self.width;
+                self.contents.splice(at..at, self.encode(value));
+                true
+            }
+        }
+    }
+
+    fn remove(&mut self, value: i64) -> bool {
+        if Self::width_for(value) > self.width {
+            return false;
+        }
+        match self.search(value) {
+            Ok(index) => {
+                self.contents
+                    .drain(index * self.width..(index + 1) * self.width);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn encode(&self, value: i64) -> Vec<u8> {
+        value.to_le_bytes()[..self.width].to_vec()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = i64> + '_ {
+        (0..self.len()).map(|index| self.get(index))
+    }
+}
+
+#[derive(Clone)]
+enum Encoding {
+    Ints(IntSet),
+    Hash(Dict<Vec<u8>, ()>),
+}
+
+/// An unordered collection of distinct members. Small sets of integers use a
+/// compact sorted array and switch to a hash table once they grow or gain a
+/// member that is not an integer.
+#[derive(Clone)]
+pub struct Set {
+    encoding: Encoding,
+}
+
+impl Default for Set {
+    fn default() -> Self {
+        Self {
+            encoding: Encoding::Ints(IntSet::default()),
+        }
+    }
+}
+
+impl Set {
+    pub fn len(&self) -> usize {
+        match &self.encoding {
+            Encoding::Ints(ints) => ints.len(),
+            Encoding::Hash(hash) => hash.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match &self.encoding {
+            Encoding::Ints(ints) => parse_integer(member).is_ok_and(|value| ints.contains(value)),
+            Encoding::Hash(hash) => hash.contains_key(member),
+        }
+    }
+
+    /// Adds `member`, returning whether it was not already there.
+    pub fn insert(&mut self, member: &[u8]) -> bool {
+        if let Encoding::Ints(ints) = &mut self.encoding {
+            match parse_integer(member) {
+                Ok(value) if ints.len() < MAX_INTSET_ENTRIES || ints.contains(value) => {
+                    return ints.insert(value);
+                }
+                _ => self.convert_to_hash(),
+            }
+        }
+        let Encoding::Hash(hash) = &mut self.encoding else {
+            unreachable!("set was just converted");
+        };
+        hash.insert(member.to_vec(), ()).is_none()
+    }
+
+    /// Removes `member`, returning whether it was there.
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match &mut self.encoding {
+            Encoding::Ints(ints) => parse_integer(member).is_ok_and(|value| ints.remove(value)),
+            Encoding::Hash(hash) => hash.remove(member).is_some(),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        let (ints, hash) = match &self.encoding {
+            Encoding::Ints(ints) => (Some(ints), None),
+            Encoding::Hash(hash) => (None, Some(hash)),
+        };
+        let ints = ints
+            .into_iter()
+            .flat_map(|ints| ints.iter())
+            .map(|value| value.to_string().into_bytes());
+        let members = hash
+            .into_iter()
+            .flat_map(|hash| hash.iter())
+            .map(|(member, _)| member.clone());
+        ints.chain(members)
+    }
+
+    pub fn random(&self, rng: &mut Rng) -> Option<Vec<u8>> {
+        match &self.encoding {
+            Encoding::Ints(ints) if ints.len() == 0 => None,
+            Encoding::Ints(ints) => Some(ints.get(rng.below(ints.len())).to_string().into_bytes()),
+            Encoding::Hash(hash) => hash.random(rng).map(|(member, _)| member.clone()),
+        }
+    }
+
+    /// Walks the set for SSCAN. The compact encoding is small enough to be
+    /// returned whole, so it always finishes in one call.
+    pub fn scan(&self, cursor: u64, count: usize, mut visit: impl FnMut(&[u8])) -> u64 {
+        match &self.encoding {
+            Encoding::Ints(ints) => {
+                for value in ints.iter() {
+                    visit(value.to_string().as_bytes());
+                }
+                0
+            }
+            Encoding::Hash(hash) => scan_dict(hash, cursor, count, |member, _| visit(member)),
+        }
+    }
+
+    fn convert_to_hash(&mut self) {
+        let mut hash = Dict::new();
+        for member in self.iter() {
+            hash.insert(member, ());
+        }
+        self.encoding = Encoding::Hash(hash);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_compact(set: &Set) -> bool {
+        matches!(set.encoding, Encoding::Ints(_))
+    }
+
+    #[test]
+    fn test_intset_widens() {
+        let mut ints = IntSet::default();
+        assert!(ints.insert(5));
+        assert!(ints.insert(-3));
+        assert!(!ints.insert(5));
+        assert_eq!(ints.width, 2);
+        assert!(ints.insert(100_000));
+        assert_eq!(ints.width, 4);
+        assert!(ints.insert(i64::MIN));
+        assert_eq!(ints.width, 8);
+        assert_eq!(
+            ints.iter().collect::<Vec<_>>(),
+            vec![i64::MIN, -3, 5, 100_000]
+        );
+        assert!(ints.remove(-3));
+        assert!(!ints.remove(-3));
+        assert!(ints.contains(100_000));
+        assert!(!ints.contains(7));
+    }
+
+    #[test]
+    fn test_set_converts_on_non_integer() {
+        let mut set = Set::default();
+        assert!(set.insert(b"1"));
+        assert!(set.insert(b"2"));
+        // Not the canonical form of an integer, so it must stay distinct.
+        assert!(set.insert(b"01"));
+        assert!(!is_compact(&set));
+        assert!(set.contains(b"1"));
+        assert!(set.contains(b"01"));
+        assert!(!set.insert(b"2"));
+        assert_eq!(set.len(), 3);
+    }
+
+    #[test]
+    fn test_set_converts_when_large() {
+        let mut set = Set::default();
+        for i in 0..MAX_INTSET_ENTRIES {
+            set.insert(i.to_string().as_bytes());
+        }
+        assert!(is_compact(&set));
+        assert!(!set.insert(b"0"));
+        assert!(is_compact(&set));
+        set.insert(b"-1");
+        assert!(!is_compact(&set));
+        assert_eq!(set.len(), MAX_INTSET_ENTRIES + 1);
+        assert!(set.remove(b"-1"));
+        assert_eq!(set.iter().count(), MAX_INTSET_ENTRIES);
+    }
+}
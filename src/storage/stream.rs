@@ -0,0 +1,740 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::{Bound, Range};
+
+use super::FieldValues;
+
+// Like redis' stream-node-max-bytes and stream-node-max-entries: a node is
+// closed once it holds this many bytes or entries, and the next entry starts
+// a new one.
+const NODE_MAX_BYTES: usize = 4096;
+const NODE_MAX_ENTRIES: usize = 100;
+
+/// A stream entry ID: a millisecond timestamp and a sequence number within
+/// that millisecond, written as `ms-seq`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_add(1)?,
+                seq: 0,
+            }),
+        }
+    }
+
+    pub fn previous(self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_sub(1)?,
+                seq: u64::MAX,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// A stream entry with its fields and values.
+pub type StreamEntry = (StreamId, FieldValues);
+
+/// An entry delivered to a consumer, without fields if it was deleted from
+/// the stream while pending.
+pub type DeliveredEntry = (StreamId, Option<FieldValues>);
+
+/// What XAUTOCLAIM found: the ID to continue from, `0-0` once the whole
+/// pending list has been scanned, the claimed entries and the IDs that were
+/// dropped because they had been deleted from the stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoClaimed {
+    pub next: StreamId,
+    pub claimed: Vec<StreamEntry>,
+    pub deleted: Vec<StreamId>,
+}
+
+/// Which entries XTRIM, or XADD with trimming, removes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrimStrategy {
+    /// Keep at most this many entries.
+    MaxLen(usize),
+    /// Remove entries with IDs below this one.
+    MinId(StreamId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamTrim {
+    pub strategy: TrimStrategy,
+    /// With `~`, only whole nodes are removed, so a few more entries than
+    /// asked for may remain.
+    pub approximate: bool,
+    /// The most entries to remove, only allowed along with `~`.
+    pub limit: Option<usize>,
+}
+
+/// The options of XCLAIM besides the IDs.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ClaimOptions {
+    /// Set the idle time of the claimed entries instead of resetting it.
+    pub idle: Option<i64>,
+    /// Set the delivery time of the claimed entries, as a unix time.
+    pub time: Option<i64>,
+    pub retry_count: Option<u64>,
+    /// Claim IDs that exist in the stream even if nobody had them pending.
+    pub force: bool,
+    /// Reply with IDs only, without counting a delivery.
+    pub just_id: bool,
+    /// Move the group's last delivered ID forward to this one.
+    pub last_id: Option<StreamId>,
+}
+
+fn write_varint(data: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        data.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> u64 {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+/// An entry as found while walking a node, before its fields are decoded.
+struct RawEntry {
+    id: StreamId,
+    /// Where the entry's deleted flag is.
+    flag: usize,
+    deleted: bool,
+    fields: Range<usize>,
+}
+
+/// A run of entries packed into a single buffer, in the spirit of redis'
+/// listpacks. Each entry is a deleted flag, its ID as an offset from the
+/// node's master ID, and its length-prefixed fields and values. Deleting
+/// only sets the flag; the node goes away once all of its entries have.
+#[derive(Clone, Default)]
+struct Node {
+    data: Vec<u8>,
+    entries: usize,
+    live: usize,
+}
+
+impl Node {
+    fn is_full(&self) -> bool {
+        self.entries >= NODE_MAX_ENTRIES || self.data.len() >= NODE_MAX_BYTES
+    }
+
+    fn push(&mut self, master: StreamId, id: StreamId, fields: &FieldValues) {
+        self.data.push(0);
+        write_varint(&mut self.data, id.ms - master.ms);
+        write_varint(&mut self.data, id.seq);
+        write_varint(&mut self.data, fields.len() as u64);
+        for (field, value) in fields {
+            write_varint(&mut self.data, field.len() as u64);
+            self.data.extend_from_slice(field);
+            write_varint(&mut self.data, value.len() as u64);
+            self.data.extend_from_slice(value);
+        }
+        self.entries += 1;
+        self.live += 1;
+    }
+
+    fn raw_entries(&self, master: StreamId) -> impl Iterator<Item = RawEntry> + '_ {
+        let mut pos = 0;
+        std::iter::from_fn(move || {
+            if pos >= self.data.len() {
+                return None;
+            }
+            let flag = pos;
+            pos += 1;
+            let ms = master.ms + read_varint(&self.data, &mut pos);
+            let seq = read_varint(&self.data, &mut pos);
+            let start = pos;
+            let count = read_varint(&self.data, &mut pos);
+            for _ in 0..count * 2 {
+                pos += read_varint(&self.data, &mut pos) as usize;
+            }
+            Some(RawEntry {
+                id: StreamId { ms, seq },
+                flag,
+                deleted: self.data[flag] != 0,
+                fields: start..pos,
+            })
+        })
+    }
+
+    fn fields(&self, entry: &RawEntry) -> FieldValues {
+        let mut pos = entry.fields.start;
+        let read = |pos: &mut usize| {
+            let len = read_varint(&self.data, pos) as usize;
+            *pos += len;
+            self.data[*pos - len..*pos].to_vec()
+        };
+        let count = read_varint(&self.data, &mut pos);
+        (0..count)
+            .map(|_| (read(&mut pos), read(&mut pos)))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingEntry {
+    pub consumer: Vec<u8>,
+    /// Unix time in milliseconds of the last delivery.
+    pub delivered_at: i64,
+    pub deliveries: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Consumer {
+    /// Unix time in milliseconds the consumer last read or claimed.
+    pub seen_at: i64,
+}
+
+/// A consumer group: how far it has read, and the entries delivered to its
+/// consumers that have not been acknowledged yet.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConsumerGroup {
+    pub last_delivered: StreamId,
+    pub pending: BTreeMap<StreamId, PendingEntry>,
+    pub consumers: BTreeMap<Vec<u8>, Consumer>,
+}
+
+impl ConsumerGroup {
+    fn touch(&mut self, consumer: &[u8], now: i64) {
+        self.consumers.entry(consumer.to_vec()).or_default().seen_at = now;
+    }
+}
+
+/// An append-only log of entries ordered by ID, stored in nodes keyed by the
+/// ID of their first entry.
+#[derive(Clone, Default)]
+pub struct Stream {
+    nodes: BTreeMap<StreamId, Node>,
+    len: usize,
+    last_id: StreamId,
+    groups: BTreeMap<Vec<u8>, ConsumerGroup>,
+}
+
+impl Stream {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The ID of the last entry ever added, which new ones must exceed even
+    /// if it has since been deleted.
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// Appends an entry, whose ID must be greater than `last_id`.
+    pub fn append(&mut self, id: StreamId, fields: &FieldValues) {
+        if self
+            .nodes
+            .last_key_value()
+            .is_none_or(|(_, node)| node.is_full())
+        {
+            self.nodes.insert(id, Node::default());
+        }
+        let mut last = self.nodes.last_entry().expect("a node was just ensured");
+        let master = *last.key();
+        last.get_mut().push(master, id, fields);
+        self.len += 1;
+        self.last_id = id;
+    }
+
+    /// Returns up to `count` entries with IDs from `start` to `end`
+    /// inclusive, walking backwards from `end` when `rev` is set.
+    pub fn range<'a>(
+        &'a self,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+        rev: bool,
+    ) -> Vec<StreamEntry> {
+        if start > end {
+            return Vec::new();
+        }
+        // The node holding `start` may begin before it.
+        let first = self
+            .nodes
+            .range(..=start)
+            .next_back()
+            .map_or(start, |(master, _)| *master);
+        let in_range = |(master, node): (&StreamId, &'a Node)| {
+            node.raw_entries(*master)
+                .filter(move |entry| !entry.deleted && entry.id >= start && entry.id <= end)
+                .map(move |entry| (node, entry))
+        };
+        let nodes = self.nodes.range(first..=end);
+        let entries: Box<dyn Iterator<Item = (&Node, RawEntry)>> = match rev {
+            true => Box::new(nodes.rev().flat_map(|node| {
+                let mut entries: Vec<_> = in_range(node).collect();
+                entries.reverse();
+                entries
+            })),
+            false => Box::new(nodes.flat_map(in_range)),
+        };
+        entries
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(node, entry)| (entry.id, node.fields(&entry)))
+            .collect()
+    }
+
+    pub fn get(&self, id: StreamId) -> Option<FieldValues> {
+        self.range(id, id, Some(1), false)
+            .pop()
+            .map(|(_, fields)| fields)
+    }
+
+    pub fn delete(&mut self, id: StreamId) -> bool {
+        let Some((&master, node)) = self.nodes.range_mut(..=id).next_back() else {
+            return false;
+        };
+        let Some(entry) = node
+            .raw_entries(master)
+            .find(|entry| entry.id == id && !entry.deleted)
+        else {
+            return false;
+        };
+        node.data[entry.flag] = 1;
+        node.live -= 1;
+        if node.live == 0 {
+            self.nodes.remove(&master);
+        }
+        self.len -= 1;
+        true
+    }
+
+    /// Removes entries from the start of the stream and returns how many.
+    pub fn trim(&mut self, trim: StreamTrim) -> usize {
+        let limit = trim.limit.unwrap_or(usize::MAX);
+        let mut removed = 0;
+        while let Some(mut first) = self.nodes.first_entry() {
+            let master = *first.key();
+            let node = first.get();
+            let last = node
+                .raw_entries(master)
+                .last()
+                .expect("nodes are not empty")
+                .id;
+            let whole = match trim.strategy {
+                TrimStrategy::MaxLen(max) => self.len - node.live >= max,
+                TrimStrategy::MinId(min) => last < min,
+            };
+            if whole {
+                if removed + node.live > limit {
+                    break;
+                }
+                removed += node.live;
+                self.len -= node.live;
+                first.remove();
+                continue;
+            }
+            if trim.approximate {
+                break;
+            }
+            // An exact trim also takes out entries of the node that is only
+            // partly past the threshold.
+            let doomed: Vec<usize> = node
+                .raw_entries(master)
+                .filter(|entry| !entry.deleted)
+                .enumerate()
+                .take_while(|(index, entry)| match trim.strategy {
+                    TrimStrategy::MaxLen(max) => self.len - index > max,
+                    TrimStrategy::MinId(min) => entry.id < min,
+                })
+                .map(|(_, entry)| entry.flag)
+                .collect();
+            let node = first.get_mut();
+            for flag in &doomed {
+                node.data[*flag] = 1;
+            }
+            node.live -= doomed.len();
+            // The node's last entries may all have been deleted already, in
+            // which case none is left.
+            if node.live == 0 {
+                first.remove();
+            }
+            self.len -= doomed.len();
+            removed += doomed.len();
+            break;
+        }
+        removed
+    }
+
+    pub fn group(&self, name: &[u8]) -> Option<&ConsumerGroup> {
+        self.groups.get(name)
+    }
+
+    pub fn group_mut(&mut self, name: &[u8]) -> Option<&mut ConsumerGroup> {
+        self.groups.get_mut(name)
+    }
+
+    /// Creates a group that has read up to `last_delivered`, returning false
+    /// if it already exists.
+    pub fn create_group(&mut self, name: &[u8], last_delivered: StreamId) -> bool {
+        if self.groups.contains_key(name) {
+            return false;
+        }
+        let group = ConsumerGroup {
+            last_delivered,
+            ..Default::default()
+        };
+        self.groups.insert(name.to_vec(), group);
+        true
+    }
+
+    pub fn destroy_group(&mut self, name: &[u8]) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    /// Reads for `consumer` of the group `name` like XREADGROUP. Without
+    /// `after`, the entries the group has not seen yet are delivered and
+    /// become pending, unless `no_ack`. Otherwise the consumer's pending
+    /// entries with IDs above `after` are delivered again, with `None` for
+    /// the ones deleted from the stream since. Returns `None` if there is
+    /// no such group.
+    pub fn read_group(
+        &mut self,
+        name: &[u8],
+        consumer: &[u8],
+        after: Option<StreamId>,
+        count: Option<usize>,
+        no_ack: bool,
+        now: i64,
+    ) -> Option<Vec<DeliveredEntry>> {
+        let group = self.groups.get(name)?;
+        let Some(after) = after else {
+            let entries = match group.last_delivered.next() {
+                Some(start) => self.range(start, StreamId::MAX, count, false),
+                None => Vec::new(),
+            };
+            let group = self.groups.get_mut(name)?;
+            group.touch(consumer, now);
+            if let Some((id, _)) = entries.last() {
+                group.last_delivered = *id;
+            }
+            if !no_ack {
+                for (id, _) in &entries {
+                    let pending = PendingEntry {
+                        consumer: consumer.to_vec(),
+                        delivered_at: now,
+                        deliveries: 1,
+                    };
+                    group.pending.insert(*id, pending);
+                }
+            }
+            return Some(
+                entries
+                    .into_iter()
+                    .map(|(id, fields)| (id, Some(fields)))
+                    .collect(),
+            );
+        };
+        let ids: Vec<StreamId> = group
+            .pending
+            .range((Bound::Excluded(after), Bound::Unbounded))
+            .filter(|(_, pending)| pending.consumer == consumer)
+            .map(|(id, _)| *id)
+            .take(count.unwrap_or(usize::MAX))
+            .collect();
+        let entries = ids.iter().map(|id| (*id, self.get(*id))).collect();
+        let group = self.groups.get_mut(name)?;
+        group.touch(consumer, now);
+        for id in &ids {
+            let pending = group.pending.get_mut(id).expect("id was just found");
+            pending.delivered_at = now;
+            pending.deliveries += 1;
+        }
+        Some(entries)
+    }
+
+    /// Gives the pending entries `ids` that have been idle for at least
+    /// `min_idle` milliseconds to `consumer`, like XCLAIM. Pending entries
+    /// deleted from the stream are dropped instead.
+    pub fn claim(
+        &mut self,
+        name: &[u8],
+        consumer: &[u8],
+        min_idle: i64,
+        ids: &[StreamId],
+        options: ClaimOptions,
+        now: i64,
+    ) -> Option<Vec<StreamEntry>> {
+        self.groups.get(name)?;
+        let mut claimed = Vec::new();
+        for id in ids {
+            let fields = self.get(*id);
+            let group = self.groups.get_mut(name)?;
+            if let Some(last_id) = options.last_id {
+                group.last_delivered = group.last_delivered.max(last_id);
+            }
+            let Some(fields) = fields else {
+                group.pending.remove(id);
+                continue;
+            };
+            if !group.pending.contains_key(id) {
+                if !options.force {
+                    continue;
+                }
+                let pending = PendingEntry {
+                    consumer: consumer.to_vec(),
+                    delivered_at: now,
+                    deliveries: 0,
+                };
+                group.pending.insert(*id, pending);
+            }
+            let pending = group.pending.get_mut(id).expect("entry was just ensured");
+            if min_idle > 0 && now - pending.delivered_at < min_idle {
+                continue;
+            }
+            pending.consumer = consumer.to_vec();
+            pending.delivered_at = match (options.time, options.idle) {
+                (Some(time), _) => time,
+                (None, Some(idle)) => now - idle,
+                (None, None) => now,
+            };
+            match options.retry_count {
+                Some(retry_count) => pending.deliveries = retry_count,
+                None if !options.just_id => pending.deliveries += 1,
+                None => {}
+            }
+            group.touch(consumer, now);
+            claimed.push((*id, fields));
+        }
+        Some(claimed)
+    }
+
+    /// Claims up to `count` pending entries from `start` on that have been
+    /// idle for at least `min_idle` milliseconds, like XAUTOCLAIM.
+    #[allow(clippy::too_many_arguments)]
+    pub fn auto_claim(
+        &mut self,
+        name: &[u8],
+        consumer: &[u8],
+        min_idle: i64,
+        start: StreamId,
+        count: usize,
+        just_id: bool,
+        now: i64,
+    ) -> Option<AutoClaimed> {
+        // As in redis, look at no more than ten times `count` entries, so a
+        // long list of entries that are not idle enough stays cheap.
+        let attempts = count.saturating_mul(10);
+        let ids: Vec<StreamId> = self
+            .groups
+            .get(name)?
+            .pending
+            .range(start..)
+            .map(|(id, _)| *id)
+            .take(attempts.saturating_add(1))
+            .collect();
+        let mut next = StreamId::MIN;
+        let mut claimed = Vec::new();
+        let mut deleted = Vec::new();
+        for (index, id) in ids.iter().enumerate() {
+            if claimed.len() == count || index == attempts {
+                next = *id;
+                break;
+            }
+            let fields = self.get(*id);
+            let group = self.groups.get_mut(name)?;
+            let Some(fields) = fields else {
+                group.pending.remove(id);
+                deleted.push(*id);
+                continue;
+            };
+            let pending = group.pending.get_mut(id).expect("id was just found");
+            if now - pending.delivered_at < min_idle {
+                continue;
+            }
+            pending.consumer = consumer.to_vec();
+            pending.delivered_at = now;
+            if !just_id {
+                pending.deliveries += 1;
+            }
+            claimed.push((*id, fields));
+        }
+        self.groups.get_mut(name)?.touch(consumer, now);
+        Some(AutoClaimed {
+            next,
+            claimed,
+            deleted,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    fn fields(value: &str) -> FieldValues {
+        vec![(b"f".to_vec(), value.as_bytes().to_vec())]
+    }
+
+    fn filled(entries: u64) -> Stream {
+        let mut stream = Stream::default();
+        for i in 0..entries {
+            stream.append(id(1000 + i / 3, i % 3), &fields(&i.to_string()));
+        }
+        stream
+    }
+
+    fn ids(entries: Vec<StreamEntry>) -> Vec<StreamId> {
+        entries.into_iter().map(|(id, _)| id).collect()
+    }
+
+    #[test]
+    fn test_range_across_nodes() {
+        let stream = filled(1000);
+        assert!(stream.nodes.len() >= 10);
+        let all = stream.range(StreamId::MIN, StreamId::MAX, None, false);
+        assert_eq!(all.len(), 1000);
+        assert_eq!(all[999], (id(1333, 0), fields("999")));
+        let some = stream.range(id(1100, 1), id(1200, 0), None, true);
+        assert_eq!(some.len(), 300);
+        assert_eq!(some[0].0, id(1200, 0));
+        assert_eq!(some[299].0, id(1100, 1));
+        assert_eq!(
+            ids(stream.range(id(1100, 1), StreamId::MAX, Some(2), false)),
+            [id(1100, 1), id(1100, 2)]
+        );
+        assert_eq!(stream.get(id(1001, 1)), Some(fields("4")));
+        assert_eq!(stream.get(id(1001, 5)), None);
+    }
+
+    #[test]
+    fn test_delete_and_trim() {
+        let mut stream = filled(300);
+        assert!(stream.delete(id(1000, 1)));
+        assert!(!stream.delete(id(1000, 1)));
+        assert_eq!(stream.len(), 299);
+        assert_eq!(stream.get(id(1000, 1)), None);
+        let approximate = StreamTrim {
+            strategy: TrimStrategy::MaxLen(150),
+            approximate: true,
+            limit: None,
+        };
+        let removed = stream.trim(approximate);
+        assert_eq!(removed % NODE_MAX_ENTRIES, 99);
+        assert!(stream.len() >= 150);
+        let exact = StreamTrim {
+            approximate: false,
+            ..approximate
+        };
+        stream.trim(exact);
+        assert_eq!(stream.len(), 150);
+        let min_id = StreamTrim {
+            strategy: TrimStrategy::MinId(id(1080, 0)),
+            approximate: false,
+            limit: None,
+        };
+        assert_eq!(stream.trim(min_id), 90);
+        assert_eq!(
+            stream.range(StreamId::MIN, StreamId::MAX, Some(1), false)[0].0,
+            id(1080, 0)
+        );
+        assert_eq!(stream.last_id(), id(1099, 2));
+    }
+
+    #[test]
+    fn test_trim_node_ending_in_deleted_entry() {
+        let mut stream = filled(150);
+        let nodes = stream.nodes.len();
+        let (&master, node) = stream.nodes.first_key_value().unwrap();
+        let last = node.raw_entries(master).last().unwrap().id;
+        let live = node.live;
+        assert!(stream.delete(last));
+        let min_id = StreamTrim {
+            strategy: TrimStrategy::MinId(last),
+            approximate: false,
+            limit: None,
+        };
+        assert_eq!(stream.trim(min_id), live - 1);
+        assert_eq!(stream.nodes.len(), nodes - 1);
+        assert!(stream.nodes.values().all(|node| node.live > 0));
+        assert_eq!(stream.len(), 150 - live);
+    }
+
+    #[test]
+    fn test_consumer_groups() {
+        let mut stream = filled(6);
+        assert!(stream.create_group(b"g", StreamId::MIN));
+        assert!(!stream.create_group(b"g", StreamId::MIN));
+        let read = stream
+            .read_group(b"g", b"alice", None, Some(4), false, 100)
+            .unwrap();
+        assert_eq!(read.len(), 4);
+        let read = stream
+            .read_group(b"g", b"bob", None, None, false, 200)
+            .unwrap();
+        assert_eq!(read.len(), 2);
+        assert!(stream
+            .read_group(b"g", b"bob", None, None, false, 200)
+            .unwrap()
+            .is_empty());
+        stream.delete(id(1000, 1));
+        let history = stream
+            .read_group(b"g", b"alice", Some(StreamId::MIN), None, false, 300)
+            .unwrap();
+        assert_eq!(history.len(), 4);
+        assert_eq!(history[1], (id(1000, 1), None));
+        let group = stream.group(b"g").unwrap();
+        assert_eq!(group.pending[&id(1000, 0)].deliveries, 2);
+        assert_eq!(group.last_delivered, id(1001, 2));
+        // Only the entry bob got before alice's history read is idle enough.
+        let claimed = stream.claim(
+            b"g",
+            b"alice",
+            50,
+            &[id(1000, 0), id(1001, 2)],
+            ClaimOptions::default(),
+            320,
+        );
+        assert_eq!(ids(claimed.unwrap()), [id(1001, 2)]);
+        let group = stream.group(b"g").unwrap();
+        assert_eq!(group.pending[&id(1001, 2)].consumer, b"alice");
+        assert_eq!(group.pending[&id(1001, 2)].deliveries, 2);
+        let claimed = stream
+            .auto_claim(b"g", b"carol", 100, StreamId::MIN, 2, false, 400)
+            .unwrap();
+        assert_eq!(ids(claimed.claimed), [id(1000, 0), id(1000, 2)]);
+        assert_eq!(claimed.deleted, [id(1000, 1)]);
+        assert_eq!(claimed.next, id(1001, 0));
+        assert!(stream
+            .read_group(b"missing", b"bob", None, None, false, 0)
+            .is_none());
+    }
+}
@@ -0,0 +1,545 @@
+use super::dict::Dict;
+use super::rand::Rng;
+
+// Same as redis' ZSKIPLIST_MAXLEVEL and ZSKIPLIST_P: each level holds about a
+// quarter of the nodes of the level below it.
+const MAX_LEVEL: usize = 32;
+const LEVEL_ODDS: usize = 4;
+
+// Nodes live in an arena and link to each other by index. The header is
+// always the first one and holds no entry.
+const HEADER: usize = 0;
+
+/// One end of a score range, as given to ZRANGE BYSCORE.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreBound {
+    pub score: f64,
+    pub exclusive: bool,
+}
+
+/// One end of a lexicographic range, as given to ZRANGE BYLEX.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexBound {
+    NegativeInfinity,
+    PositiveInfinity,
+    Inclusive(Vec<u8>),
+    Exclusive(Vec<u8>),
+}
+
+impl ScoreBound {
+    fn below(&self, score: f64) -> bool {
+        self.score < score || !self.exclusive && self.score == score
+    }
+
+    fn above(&self, score: f64) -> bool {
+        self.score > score || !self.exclusive && self.score == score
+    }
+}
+
+impl LexBound {
+    fn below(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::NegativeInfinity => true,
+            LexBound::PositiveInfinity => false,
+            LexBound::Inclusive(bound) => bound.as_slice() <= member,
+            LexBound::Exclusive(bound) => bound.as_slice() < member,
+        }
+    }
+
+    fn above(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::NegativeInfinity => false,
+            LexBound::PositiveInfinity => true,
+            LexBound::Inclusive(bound) => bound.as_slice() >= member,
+            LexBound::Exclusive(bound) => bound.as_slice() > member,
+        }
+    }
+}
+
+/// A link to the next node on one level, along with how many nodes of the
+/// bottom level it skips over, which is what makes rank queries logarithmic.
+#[derive(Clone, Default)]
+struct Level {
+    forward: Option<usize>,
+    span: usize,
+}
+
+#[derive(Clone, Default)]
+struct Node {
+    member: Vec<u8>,
+    score: f64,
+    backward: Option<usize>,
+    levels: Vec<Level>,
+}
+
+impl Node {
+    /// Whether the node orders before the entry `score`, `member`.
+    fn precedes(&self, score: f64, member: &[u8]) -> bool {
+        self.score < score || self.score == score && self.member.as_slice() < member
+    }
+}
+
+/// Entries ordered by score and then member, like redis' zskiplist.
+#[derive(Clone)]
+struct SkipList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    tail: Option<usize>,
+    len: usize,
+    level: usize,
+    rng: Rng,
+}
+
+impl Default for SkipList {
+    fn default() -> Self {
+        let header = Node {
+            levels: vec![Level::default(); MAX_LEVEL],
+            ..Default::default()
+        };
+        Self {
+            nodes: vec![header],
+            free: Vec::new(),
+            tail: None,
+            len: 0,
+            level: 1,
+            rng: Rng::new(),
+        }
+    }
+}
+
+impl SkipList {
+    fn random_level(&mut self) -> usize {
+        let mut level = 1;
+        while level < MAX_LEVEL && self.rng.below(LEVEL_ODDS) == 0 {
+            level += 1;
+        }
+        level
+    }
+
+    fn forward(&self, node: usize, level: usize) -> Option<usize> {
+        self.nodes[node].levels[level].forward
+    }
+
+    /// Inserts an entry, which must not be in the list yet.
+    fn insert(&mut self, score: f64, member: Vec<u8>) {
+        // The last node before the new one on each level, and its rank.
+        let mut update = [HEADER; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut node = HEADER;
+        for i in (0..self.level).rev() {
+            rank[i] = if i + 1 == self.level { 0 } else { rank[i + 1] };
+            while let Some(next) = self.forward(node, i) {
+                if !self.nodes[next].precedes(score, &member) {
+                    break;
+                }
+                rank[i] += self.nodes[node].levels[i].span;
+                node = next;
+            }
+            update[i] = node;
+        }
+        let level = self.random_level();
+        if level > self.level {
+            for i in self.level..level {
+                self.nodes[HEADER].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+        let new = Node {
+            member,
+            score,
+            backward: (update[0] != HEADER).then_some(update[0]),
+            levels: Vec::with_capacity(level),
+        };
+        let new = match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = new;
+                index
+            }
+            None => {
+                self.nodes.push(new);
+                self.nodes.len() - 1
+            }
+        };
+        for i in 0..level {
+            let previous = &mut self.nodes[update[i]].levels[i];
+            let link = Level {
+                forward: previous.forward,
+                span: previous.span - (rank[0] - rank[i]),
+            };
+            *previous = Level {
+                forward: Some(new),
+                span: rank[0] - rank[i] + 1,
+            };
+            self.nodes[new].levels.push(link);
+        }
+        for (i, &previous) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[previous].levels[i].span += 1;
+        }
+        match self.forward(new, 0) {
+            Some(next) => self.nodes[next].backward = Some(new),
+            None => self.tail = Some(new),
+        }
+        self.len += 1;
+    }
+
+    /// Removes an entry, returning whether it was there.
+    fn remove(&mut self, score: f64, member: &[u8]) -> bool {
+        let mut update = [HEADER; MAX_LEVEL];
+        let mut node = HEADER;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(node, i) {
+                if !self.nodes[next].precedes(score, member) {
+                    break;
+                }
+                node = next;
+            }
+            update[i] = node;
+        }
+        let Some(target) = self.forward(node, 0) else {
+            return false;
+        };
+        if self.nodes[target].score != score || self.nodes[target].member != member {
+            return false;
+        }
+        for (i, &previous) in update.iter().enumerate().take(self.level) {
+            if self.forward(previous, i) == Some(target) {
+                let link = self.nodes[target].levels[i].clone();
+                let previous = &mut self.nodes[previous].levels[i];
+                previous.span += link.span;
+                previous.span -= 1;
+                previous.forward = link.forward;
+            } else {
+                self.nodes[previous].levels[i].span -= 1;
+            }
+        }
+        let backward = self.nodes[target].backward;
+        match self.forward(target, 0) {
+            Some(next) => self.nodes[next].backward = backward,
+            None => self.tail = backward,
+        }
+        while self.level > 1 && self.forward(HEADER, self.level - 1).is_none() {
+            self.level -= 1;
+        }
+        self.nodes[target] = Node::default();
+        self.free.push(target);
+        self.len -= 1;
+        true
+    }
+
+    /// Returns the zero based rank of an entry that is in the list.
+    fn rank(&self, score: f64, member: &[u8]) -> usize {
+        let mut rank = 0;
+        let mut node = HEADER;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(node, i) {
+                let n = &self.nodes[next];
+                if n.precedes(score, member) || n.score == score && n.member == member {
+                    rank += self.nodes[node].levels[i].span;
+                    node = next;
+                } else {
+                    break;
+                }
+            }
+            if node != HEADER && self.nodes[node].member == member {
+                return rank - 1;
+            }
+        }
+        unreachable!("rank of an entry that is not in the list")
+    }
+
+    /// Finds the node at the zero based `rank`.
+    fn by_rank(&self, rank: usize) -> Option<usize> {
+        let target = rank + 1;
+        let mut traversed = 0;
+        let mut node = HEADER;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(node, i) {
+                let span = self.nodes[node].levels[i].span;
+                if traversed + span > target {
+                    break;
+                }
+                traversed += span;
+                node = next;
+            }
+            if traversed == target {
+                return Some(node);
+            }
+        }
+        None
+    }
+
+    /// Finds the first node for which `before` is false, given that it holds
+    /// for some prefix of the list.
+    fn first_where_not(&self, before: impl Fn(&Node) -> bool) -> Option<usize> {
+        let mut node = HEADER;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(node, i) {
+                if !before(&self.nodes[next]) {
+                    break;
+                }
+                node = next;
+            }
+        }
+        self.forward(node, 0)
+    }
+
+    /// Finds the last node for which `within` holds, given that it holds for
+    /// some prefix of the list.
+    fn last_where(&self, within: impl Fn(&Node) -> bool) -> Option<usize> {
+        match self.first_where_not(within) {
+            Some(node) => self.nodes[node].backward,
+            None => self.tail,
+        }
+    }
+
+    /// Walks the list from `start` towards the tail, or towards the head when
+    /// `rev` is set.
+    fn walk(&self, start: Option<usize>, rev: bool) -> impl Iterator<Item = &Node> + '_ {
+        std::iter::successors(start, move |&node| match rev {
+            true => self.nodes[node].backward,
+            false => self.forward(node, 0),
+        })
+        .map(|node| &self.nodes[node])
+    }
+}
+
+/// A sorted set: a skiplist ordered by score for ranges and ranks, and a
+/// dict from member to score for lookups by member.
+#[derive(Clone, Default)]
+pub struct ZSet {
+    scores: Dict<Vec<u8>, f64>,
+    list: SkipList,
+}
+
+impl ZSet {
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Sets the score of `member`, returning whether it was newly added.
+    pub fn insert(&mut self, member: &[u8], score: f64) -> bool {
+        match self.scores.get_mut(member) {
+            Some(current) if *current == score => false,
+            Some(current) => {
+                self.list.remove(*current, member);
+                *current = score;
+                self.list.insert(score, member.to_vec());
+                false
+            }
+            None => {
+                self.scores.insert(member.to_vec(), score);
+                self.list.insert(score, member.to_vec());
+                true
+            }
+        }
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => self.list.remove(score, member),
+            None => false,
+        }
+    }
+
+    /// Returns the zero based rank of `member`, counting from the highest
+    /// score when `rev` is set.
+    pub fn rank(&self, member: &[u8], rev: bool) -> Option<usize> {
+        let score = self.score(member)?;
+        let rank = self.list.rank(score, member);
+        Some(if rev { self.len() - 1 - rank } else { rank })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], f64)> + '_ {
+        self.list
+            .walk(self.list.forward(HEADER, 0), false)
+            .map(|node| (node.member.as_slice(), node.score))
+    }
+
+    /// Returns the entries with ranks `start` to `end` inclusive, which must
+    /// be in range, counting from the highest score when `rev` is set.
+    pub fn range_by_rank(&self, start: usize, end: usize, rev: bool) -> Vec<(Vec<u8>, f64)> {
+        let first = match rev {
+            true => self.list.by_rank(self.len() - 1 - start),
+            false => self.list.by_rank(start),
+        };
+        self.list
+            .walk(first, rev)
+            .take(end - start + 1)
+            .map(|node| (node.member.clone(), node.score))
+            .collect()
+    }
+
+    /// Returns the entries with scores between `min` and `max`, skipping
+    /// `offset` of them and returning at most `count`.
+    pub fn range_by_score(
+        &self,
+        min: ScoreBound,
+        max: ScoreBound,
+        rev: bool,
+        offset: usize,
+        count: Option<usize>,
+    ) -> Vec<(Vec<u8>, f64)> {
+        let first = match rev {
+            true => self.list.last_where(|node| max.above(node.score)),
+            false => self.list.first_where_not(|node| !min.below(node.score)),
+        };
+        self.list
+            .walk(first, rev)
+            .take_while(|node| min.below(node.score) && max.above(node.score))
+            .skip(offset)
+            .take(count.unwrap_or(usize::MAX))
+            .map(|node| (node.member.clone(), node.score))
+            .collect()
+    }
+
+    /// Returns the members between `min` and `max` in byte order, which only
+    /// makes sense when all scores are equal, like ZRANGE BYLEX.
+    pub fn range_by_lex(
+        &self,
+        min: &LexBound,
+        max: &LexBound,
+        rev: bool,
+        offset: usize,
+        count: Option<usize>,
+    ) -> Vec<(Vec<u8>, f64)> {
+        let first = match rev {
+            true => self.list.last_where(|node| max.above(&node.member)),
+            false => self.list.first_where_not(|node| !min.below(&node.member)),
+        };
+        self.list
+            .walk(first, rev)
+            .take_while(|node| min.below(&node.member) && max.above(&node.member))
+            .skip(offset)
+            .take(count.unwrap_or(usize::MAX))
+            .map(|node| (node.member.clone(), node.score))
+            .collect()
+    }
+
+    /// Removes and returns up to `count` entries with the lowest scores, or
+    /// the highest when `max` is set.
+    pub fn pop(&mut self, count: usize, max: bool) -> Vec<(Vec<u8>, f64)> {
+        let popped = match max {
+            true => self.list.walk(self.list.tail, true),
+            false => self.list.walk(self.list.forward(HEADER, 0), false),
+        }
+        .take(count)
+        .map(|node| (node.member.clone(), node.score))
+        .collect::<Vec<_>>();
+        for (member, _) in &popped {
+            self.remove(member);
+        }
+        popped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bound(score: f64, exclusive: bool) -> ScoreBound {
+        ScoreBound { score, exclusive }
+    }
+
+    fn members(entries: Vec<(Vec<u8>, f64)>) -> Vec<String> {
+        entries
+            .into_iter()
+            .map(|(member, _)| String::from_utf8(member).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_ranks_follow_updates() {
+        let mut zset = ZSet::default();
+        for i in 0..1000 {
+            assert!(zset.insert(format!("m{}", i).as_bytes(), (i % 100) as f64));
+        }
+        assert_eq!(zset.len(), 1000);
+        let mut expected: Vec<(Vec<u8>, f64)> = zset
+            .iter()
+            .map(|(member, score)| (member.to_vec(), score))
+            .collect();
+        expected.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        assert_eq!(zset.range_by_rank(0, 999, false), expected);
+        for (rank, (member, _)) in expected.iter().enumerate() {
+            assert_eq!(zset.rank(member, false), Some(rank));
+            assert_eq!(zset.rank(member, true), Some(999 - rank));
+        }
+        assert!(!zset.insert(b"m5", 1000.0));
+        assert_eq!(zset.rank(b"m5", false), Some(999));
+        assert_eq!(zset.rank(b"m5", true), Some(0));
+        for i in 0..500 {
+            assert!(zset.remove(format!("m{}", i * 2).as_bytes()));
+        }
+        assert!(!zset.remove(b"m0"));
+        assert_eq!(zset.len(), 500);
+        assert_eq!(zset.list.len, 500);
+        assert_eq!(zset.rank(b"m1", false), Some(0));
+        assert_eq!(zset.range_by_rank(499, 499, false)[0].0, b"m5");
+    }
+
+    #[test]
+    fn test_range_by_score() {
+        let mut zset = ZSet::default();
+        for (member, score) in [("a", 1.0), ("b", 2.0), ("c", 2.0), ("d", 3.0), ("e", 4.0)] {
+            zset.insert(member.as_bytes(), score);
+        }
+        let all = |min, max, rev| members(zset.range_by_score(min, max, rev, 0, None));
+        assert_eq!(
+            all(bound(2.0, false), bound(3.0, false), false),
+            ["b", "c", "d"]
+        );
+        assert_eq!(all(bound(2.0, true), bound(4.0, false), false), ["d", "e"]);
+        assert_eq!(all(bound(2.0, false), bound(3.0, true), true), ["c", "b"]);
+        assert_eq!(
+            all(
+                bound(f64::NEG_INFINITY, false),
+                bound(f64::INFINITY, false),
+                true
+            ),
+            ["e", "d", "c", "b", "a"]
+        );
+        assert!(all(bound(5.0, false), bound(6.0, false), false).is_empty());
+        assert!(all(bound(0.0, false), bound(0.5, false), true).is_empty());
+        let limited = zset.range_by_score(bound(1.0, false), bound(4.0, false), false, 1, Some(2));
+        assert_eq!(members(limited), ["b", "c"]);
+    }
+
+    #[test]
+    fn test_range_by_lex_and_pop() {
+        let mut zset = ZSet::default();
+        for member in ["a", "b", "c", "d"] {
+            zset.insert(member.as_bytes(), 0.0);
+        }
+        let range = |min: LexBound, max: LexBound, rev| {
+            members(zset.range_by_lex(&min, &max, rev, 0, None))
+        };
+        assert_eq!(
+            range(
+                LexBound::Inclusive(b"b".to_vec()),
+                LexBound::PositiveInfinity,
+                false
+            ),
+            ["b", "c", "d"]
+        );
+        assert_eq!(
+            range(
+                LexBound::NegativeInfinity,
+                LexBound::Exclusive(b"c".to_vec()),
+                true
+            ),
+            ["b", "a"]
+        );
+        assert_eq!(members(zset.pop(2, true)), ["d", "c"]);
+        assert_eq!(members(zset.pop(5, false)), ["a", "b"]);
+        assert!(zset.is_empty());
+        assert_eq!(zset.list.tail, None);
+    }
+}